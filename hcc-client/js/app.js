@@ -9,8 +9,7 @@ import("../pkg/index.js").then((x => {
     // console.log("imported index.js");
     // global exports for JS interop here:
 
-    window.recv_claims = x.recv_claims;
-    window.EphemeralSharedKeyring = x.SharedKeyring;
+    window.ClientKeyExchange = x.ClientKeyExchange;
     window.render_media_node = x.render_media_node;
     
     let render = x.render_app;
@@ -26,7 +26,7 @@ function handleEvent(event) {
   if (event.origin !== ORIGIN) {
     // console.log("I DONT LKE YOUR ORIGIN!");
     return;
-  } else if (!window.recv_claims || !window.EphemeralSharedKeyring) {
+  } else if (!window.ClientKeyExchange) {
     // console.log("I AM NOT READY TO RECEIVE THESE CLAIMS");
     requestAnimationFrame(handleEvent.bind(null, event));
     return;
@@ -35,22 +35,44 @@ function handleEvent(event) {
     return;
   }
 
-  let claims = recv_claims(ORIGIN, event.data.token);
+  // our secret key stays in wasm, only the public half goes over the wire
+  let kex = new ClientKeyExchange();
 
-  let keyring = new EphemeralSharedKeyring(claims);
+  fetch(ORIGIN + "/handshake", {
+    method: "POST",
+    credentials: "include",
+    headers: {
+      "content-type": "application/json",
+      "x-handshake-token": event.data.token,
+    },
+    body: JSON.stringify({ client_public_key: kex.public_key() }),
+  })
+    .then(response => {
+      if (!response.ok) {
+        throw new Error("handshake rejected: " + response.status);
+      }
+      return response.json();
+    })
+    .then(body => {
+      let keyring = kex.establish(body.server_public_key, body.grace_epochs);
 
-  TOKEN_DB[encryptionKey] = keyring;
-  TOKEN_DB[antiForgeryKey] = keyring.encrypt_header(event.data.token);
+      TOKEN_DB[encryptionKey] = keyring;
+      TOKEN_DB[antiForgeryKey] = keyring.encrypt_header(event.data.token);
 
-  event.source.postMessage("ack-token", event.origin);
-  window.removeEventListener("message", recvTokenMessage);
+      event.source.postMessage("ack-token", event.origin);
+      window.removeEventListener("message", recvTokenMessage);
+
+      loaded = true;
+
+      // console.log("loaded and time to call the callbacks...");
+
+      callbacks.forEach(cb => cb());
+    })
+    .catch(error => {
+      console.error(error);
+      working = false;
+    });
 
-  loaded = true;
-  
-  // console.log("loaded and time to call the callbacks...");
-  
-  callbacks.forEach(cb => cb());
-  
 }
 
 function recvTokenMessage(event) {
@@ -75,6 +97,11 @@ export default {
         return TOKEN_DB[encryptionKey];
     },
     getAntiForgeryToken: function() {
+        // every request needs a freshly sealed token, the server refuses ones it has already seen
+        let keyring = TOKEN_DB[encryptionKey];
+        if (keyring && TOKEN_DB[antiForgeryKey]) {
+          TOKEN_DB[antiForgeryKey] = keyring.reseal_header(TOKEN_DB[antiForgeryKey]);
+        }
         return TOKEN_DB[antiForgeryKey];
     },
     onEncryptionLoad: onLoad
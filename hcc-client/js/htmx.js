@@ -43,6 +43,12 @@ htmx.onLoad(function(target) {
 
 let jwt;
 
+// fragment encodings we read, most preferred first. the server answers in the first one it knows
+// and names it in the same header. put "emoji" first to get the cute bodies back
+const ENCODING_HEADER = "x-hcc-encoding";
+const WIRE_ENCODINGS = ["binary", "base64url", "emoji"];
+const PUBLIC_ENCODING = "identity";
+
 let formTransform = {
   "password_confirm": function(key, value, keyring) {
     return ["password_bcrypt", keyring.encrypt(value)];
@@ -59,9 +65,12 @@ function signRequestHeaders(evt) {
   if (evt && evt.detail && evt.detail.headers) {
     // todo: only sign request if we are requesting a secure asset
     if (jwt) {
+      jwt = encryption.getKeyring().reseal_header(jwt);
       evt.detail.headers["x-auth-token"] = jwt;
     }
 
+    evt.detail.headers[ENCODING_HEADER] = WIRE_ENCODINGS.join(", ");
+
     // todo: only sign with CSRF if unsafe request method
     const csrf = encryption.getAntiForgeryToken();
 
@@ -80,6 +89,19 @@ function signRequestHeaders(evt) {
   }
 }
 
+function readResponseBytes(evt) {
+  // a binary body only survives the xhr as an array buffer, text encodings read the same way
+  if (WIRE_ENCODINGS.includes("binary")) {
+    evt.detail.xhr.responseType = "arraybuffer";
+  }
+}
+
+function responseBytes(response) {
+  return response instanceof ArrayBuffer
+    ? new Uint8Array(response)
+    : new TextEncoder().encode(response);
+}
+
 function decryptResponse(evt) {
   let header = evt.detail.xhr.getResponseHeader("x-auth-token");
 
@@ -92,7 +114,12 @@ function decryptResponse(evt) {
   let obj = evt.detail;
   if (obj && obj.serverResponse) {
     let target = obj.target;
-    let serverHtml = keyring.decrypt(obj.serverResponse);
+    let encoding = obj.xhr.getResponseHeader(ENCODING_HEADER) || "emoji";
+    let body = responseBytes(obj.serverResponse);
+    // public fragments come as plain html
+    let serverHtml = encoding === PUBLIC_ENCODING
+      ? new TextDecoder().decode(body)
+      : keyring.decrypt_body(body, encoding);
 
     let node = document.createElement("div");
     node.className = "hcc-htmx";
@@ -110,4 +137,6 @@ function decryptResponse(evt) {
 
 document.body.addEventListener("htmx:configRequest", signRequestHeaders);
 
+document.body.addEventListener("htmx:beforeSend", readResponseBytes);
+
 document.body.addEventListener("htmx:beforeSwap", decryptResponse);
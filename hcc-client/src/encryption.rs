@@ -2,144 +2,222 @@
 
 use serde::{Serialize, Deserialize};
 
-use orion::kex::{SecretKey};
-use orion::aead;
+use orion::kex::{EphemeralClientSession, PublicKey};
 
-use super::emoji;
+use hcc_common::emoji;
+use hcc_common::wire::WireEncoding;
+use hcc_common::keyring::{
+    envelope, split_envelope, split_epoch, EpochKeys, DEFAULT_GRACE_EPOCHS, ENVELOPE_NONCE_SIZE
+};
 
 use wasm_bindgen::prelude::*;
 
-macro_rules! expect_two {
-    ($iter:expr) => {{
-        let mut i = $iter;
-        match (i.next(), i.next(), i.next()) {
-            (Some(first), Some(second), None) => (first, second),
-            _ => ("", ""),
+/// The browser half of the key exchange, the secret key never leaves this struct.
+/// The public key is posted to the server's /handshake which answers with its own.
+#[wasm_bindgen]
+pub struct ClientKeyExchange {
+    session: Option<EphemeralClientSession>,
+    public_key: String
+}
+
+#[wasm_bindgen]
+impl ClientKeyExchange {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> ClientKeyExchange {
+        let session = EphemeralClientSession::new().unwrap();
+        let public_key = emoji::encode(&session.public_key().to_bytes());
+        ClientKeyExchange {
+            session: Some(session),
+            public_key
+        }
+    }
+
+    pub fn public_key(&self) -> String {
+        self.public_key.to_owned()
+    }
+
+    // consumes the ephemeral session, a second call would reuse the same keypair
+    // grace_epochs comes with the server's public key, it is the server's HCC_KEYRING_GRACE_EPOCHS
+    pub fn establish(&mut self, server_public_key_emoji: &str, grace_epochs: usize) -> SharedKeyring {
+        let session = self.session.take().expect("key exchange already established");
+        let server_public_key = PublicKey::from_slice(&emoji::decode(server_public_key_emoji)).unwrap();
+        let keys = session.establish_with_server(&server_public_key).unwrap();
+
+        // mirrored from the server: what it transmits we receive, and the other way round
+        SharedKeyring {
+            keys: EpochKeys::new(
+                emoji::encode(keys.receiving().unprotected_as_bytes()),
+                emoji::encode(keys.transport().unprotected_as_bytes())
+            ),
+            grace_epochs,
+            last_timestamp: 0
         }
-    }};
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct EncryptedKeyring {
-    b: String
+// the server only moves one epoch per request, a big jump is garbage or an attack
+const MAX_EPOCH_SKIP: u32 = 16;
+
+// a message we can't open is thrown to the caller as an error, a panic would take the whole module down
+fn keyring_error(reason: &str) -> JsValue {
+    JsValue::from_str(reason)
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct JsonClaims {
-    exp: i32,
-    iss: String,
-    keyring: EncryptedKeyring,
-    sid: String
+// an epoch we have no secret for is told apart from a message that just didn't open
+fn unopened(keys: &EpochKeys, epoch: u32) -> JsValue {
+    if epoch < keys.epoch && keys.broadcast_secret_for(epoch).is_err() {
+        keyring_error("message from an expired epoch")
+    } else {
+        keyring_error("message could not be decrypted")
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct TopSecretSharedKeyring {
-    a: String,
-    b: String,
-    x: String,
-    y: String
+fn utf8(bytes: Vec<u8>) -> Result<String, JsValue> {
+    String::from_utf8(bytes).map_err(|_| keyring_error("message is not utf8"))
 }
 
+fn decode_emoji(encrypted: &str) -> Result<Vec<u8>, JsValue> {
+    emoji::try_decode(encrypted).ok_or_else(|| keyring_error("message is not emoji encoded"))
+}
+
+fn decode_header(encrypted: &str) -> Result<Vec<u8>, JsValue> {
+    base64::decode_config(encrypted, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| keyring_error("header is not base64"))
+}
 
 #[wasm_bindgen]
-pub struct SharedKeyring {
-    broadcast_secret: Vec<u8>,
-    user_secret: Vec<u8>
+extern "C" {
+    #[wasm_bindgen(js_namespace = Date)]
+    fn now() -> f64;
 }
 
+/// The server decides when to rotate, we notice a newer epoch id on a response and ratchet to it.
 #[wasm_bindgen]
+#[derive(Clone)]
+pub struct SharedKeyring {
+    // the same epoch set the server keeps, from hcc-common
+    keys: EpochKeys,
+    grace_epochs: usize,
+    // never goes backwards, even if the wall clock does
+    last_timestamp: i64
+}
+
 impl SharedKeyring {
-    #[wasm_bindgen(constructor)]
-    pub fn new(decrypted_secrets: JsValue) -> SharedKeyring {
-        let convert: TopSecretSharedKeyring = serde_wasm_bindgen::from_value(decrypted_secrets).unwrap();
-        SharedKeyring { 
-            broadcast_secret: emoji::decode(&convert.x),
-            user_secret: emoji::decode(&convert.y)
-         }
+    // everything we seal starts with our clock in millis and a random nonce so the server can refuse replays
+    fn envelope(&mut self, plaintext: &str) -> Vec<u8> {
+        let timestamp = (now() as i64).max(self.last_timestamp);
+        self.last_timestamp = timestamp;
+
+        let mut nonce = [0u8; ENVELOPE_NONCE_SIZE];
+        getrandom::getrandom(&mut nonce).unwrap();
+
+        envelope(timestamp, &nonce, plaintext.as_bytes())
     }
-    
-    pub fn decrypt(&self, encrypted: &str) -> String {
-        let secret = SecretKey::from_slice(&self.broadcast_secret).unwrap();
 
-        let message_bytes = emoji::decode(encrypted);
+    fn seal_user(&mut self, plaintext: &str) -> Vec<u8> {
+        let envelope = self.envelope(plaintext);
+        self.keys.seal_user(&envelope).unwrap()
+    }
 
-        let bytes = aead::open(&secret, &message_bytes).unwrap();
-        String::from_utf8(bytes).expect("invalid utf8")
+    fn open_broadcast(&mut self, message_bytes: &[u8]) -> Result<String, JsValue> {
+        let (epoch, _) =
+            split_epoch(message_bytes).map_err(|_| keyring_error("message is missing its epoch"))?;
+
+        if epoch > self.keys.epoch {
+            if epoch - self.keys.epoch > MAX_EPOCH_SKIP {
+                return Err(keyring_error("message epoch is too far ahead"));
+            }
+
+            // only keep the new epoch once it actually opened the message
+            let mut next = self.keys.clone();
+            while next.epoch < epoch {
+                next.ratchet(self.grace_epochs)
+                    .map_err(|_| keyring_error("keyring could not ratchet"))?;
+            }
+            let bytes = next
+                .open_broadcast(message_bytes)
+                .map_err(|_| keyring_error("message could not be decrypted"))?;
+            self.keys = next;
+            return utf8(bytes);
+        }
+
+        let bytes = self
+            .keys
+            .open_broadcast(message_bytes)
+            .map_err(|_| unopened(&self.keys, epoch))?;
+        utf8(bytes)
     }
 
-    pub fn decrypt_self(&self, encrypted: &str) -> String {
-        let secret = SecretKey::from_slice(&self.user_secret).unwrap();
+    fn open_user(&self, message_bytes: &[u8]) -> Result<String, JsValue> {
+        let (epoch, _) =
+            split_epoch(message_bytes).map_err(|_| keyring_error("message is missing its epoch"))?;
 
-        let message_bytes = emoji::decode(&encrypted);
+        let bytes = self
+            .keys
+            .open_user(message_bytes)
+            .map_err(|_| unopened(&self.keys, epoch))?;
 
-        let bytes = aead::open(&secret, &message_bytes).unwrap();
-        String::from_utf8(bytes).expect("invalid utf8")
+        match split_envelope(&bytes) {
+            Some((_, _, payload)) => utf8(payload.to_vec()),
+            None => Err(keyring_error("message is missing its envelope")),
+        }
     }
+}
 
-    pub fn decrypt_header(&self, encrypted: &str) -> String {
-        let secret = SecretKey::from_slice(&self.broadcast_secret).unwrap();
+#[wasm_bindgen]
+impl SharedKeyring {
+    pub fn epoch(&self) -> u32 {
+        self.keys.epoch
+    }
+
+    pub fn decrypt(&mut self, encrypted: &str) -> Result<String, JsValue> {
+        let message_bytes = decode_emoji(encrypted)?;
+        self.open_broadcast(&message_bytes)
+    }
+
+    /// A fragment body in whichever encoding the server answered with (its x-hcc-encoding header).
+    pub fn decrypt_body(&mut self, body: &[u8], encoding: &str) -> Result<String, JsValue> {
+        let encoding =
+            WireEncoding::from_name(encoding).ok_or_else(|| keyring_error("unknown wire encoding"))?;
+        let message_bytes = encoding
+            .decode(body)
+            .ok_or_else(|| keyring_error("malformed fragment body"))?;
+        self.open_broadcast(&message_bytes)
+    }
 
-        let message_bytes = base64::decode(encrypted).unwrap();
+    pub fn decrypt_self(&self, encrypted: &str) -> Result<String, JsValue> {
+        let message_bytes = decode_emoji(encrypted)?;
+        self.open_user(&message_bytes)
+    }
 
-        let bytes = aead::open(&secret, &message_bytes).unwrap();
-        String::from_utf8(bytes).expect("invalid utf8")
+    pub fn decrypt_header(&mut self, encrypted: &str) -> Result<String, JsValue> {
+        let message_bytes = decode_header(encrypted)?;
+        self.open_broadcast(&message_bytes)
     }
     
-    pub fn encrypt(&self, plaintext: &str) -> String {
-        let secret = SecretKey::from_slice(&self.user_secret).unwrap();
-        let bytes = aead::seal(&secret, plaintext.as_bytes()).unwrap();
+    pub fn encrypt(&mut self, plaintext: &str) -> String {
+        let bytes = self.seal_user(plaintext);
         emoji::encode(&bytes)
     }
 
-    pub fn encrypt_header(&self, plaintext: &str) -> String {
-        let secret = SecretKey::from_slice(&self.user_secret).unwrap();
-        let bytes = aead::seal(&secret, plaintext.as_bytes()).unwrap();
+    pub fn encrypt_header(&mut self, plaintext: &str) -> String {
+        let bytes = self.seal_user(plaintext);
         base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD)
     }
 
+    /// Re-seal a header we encrypted earlier with a fresh envelope under the current epoch.
+    /// The server only accepts each sealed header once, so stored tokens go through here per request.
+    pub fn reseal_header(&mut self, encrypted: &str) -> Result<String, JsValue> {
+        let message_bytes = decode_header(encrypted)?;
+        let plaintext = self.open_user(&message_bytes)?;
+        Ok(self.encrypt_header(&plaintext))
+    }
+
     pub fn empty() -> SharedKeyring {
         Self {
-            broadcast_secret: vec![],
-            user_secret: vec![]
+            keys: EpochKeys::new(String::new(), String::new()),
+            grace_epochs: DEFAULT_GRACE_EPOCHS,
+            last_timestamp: 0
         }
     }
 }
-
-
-#[wasm_bindgen]
-extern "C" {
-    #[wasm_bindgen(js_namespace=JSON)]
-    pub fn parse(message: &str) -> JsValue; 
-
-    pub fn atob(message: &str) -> String;
-}
-
-
-#[wasm_bindgen]
-pub fn recv_claims(issuer: &str, csrf_signed: &str,) -> JsValue {
-
-    let (_signature, message) = expect_two!(csrf_signed.rsplitn(2, '.'));
-    let (_header, claims) = expect_two!(message.splitn(2, '.'));
-
-    let try_decode = base64::decode_config(claims, base64::URL_SAFE_NO_PAD).unwrap();
-
-    let extracted_json = String::from_utf8(try_decode).unwrap();
-
-    let decoded = parse(&extracted_json);
-
-    let claims_json: JsonClaims = serde_wasm_bindgen::from_value(decoded).unwrap();
-
-    if claims_json.iss == issuer {
-        let secret_slice = emoji::EmojiEncodedBytes::blake_hash_to_secret(claims_json.sid.as_bytes().to_owned());
-
-        let message = emoji::decode(&claims_json.keyring.b);
-        let secret: SecretKey = SecretKey::from_slice(&secret_slice).unwrap();
-    
-        let bytes = aead::open(&secret, &message).unwrap();
-        let json = String::from_utf8(bytes).unwrap();
-        parse(&json)
-    } else {
-        parse("{}")
-    }
-    
-}
\ No newline at end of file
@@ -1,19 +1,15 @@
 #![recursion_limit = "512"]
 
 mod app;
-mod emoji;
 mod encryption;
 mod hooks;
 mod htmx;
 mod media_renderer;
 
-#[macro_use]
-extern crate lazy_static;
-
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
-pub use encryption::recv_claims;
+pub use encryption::ClientKeyExchange;
 pub use encryption::SharedKeyring;
 
 pub use media_renderer::render_media_node;
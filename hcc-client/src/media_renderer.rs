@@ -3,12 +3,13 @@ use web_sys::Element;
 
 use yew::prelude::*;
 
-use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
 use crate::app::audioplayer;
 use crate::hooks::use_mount;
 
+use hcc_common::media::{AudioMedia, ImageMedia, MediaType, TextMedia};
+
 struct MediaRenderer {}
 
 #[derive(Properties, Clone, PartialEq)]
@@ -18,10 +19,8 @@ struct MediaRendererProps {
     media: JsValue,
 }
 
-#[derive(Properties, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Properties, Clone, PartialEq)]
 struct AudioNodeProps {
-    // Matches AudioMedia on server...
-    // pull into shared lib...
     title: String,
     duration: i32,
     khz: i32,
@@ -48,9 +47,9 @@ fn audio_node(props: &AudioNodeProps) -> Html {
     }
 }
 
-#[derive(Properties, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Properties, Clone, PartialEq)]
 struct ImageNodeProps {
-    url: String, // todo: add alt text, etc
+    url: String,
 }
 
 #[function_component(ImageNode)]
@@ -60,7 +59,7 @@ fn image_node(props: &ImageNodeProps) -> Html {
     }
 }
 
-#[derive(Properties, Serialize, Deserialize, Clone, PartialEq)]
+#[derive(Properties, Clone, PartialEq)]
 struct TextNodeProps {
     body: String,
 }
@@ -75,7 +74,7 @@ fn text_node(props: &TextNodeProps) -> Html {
 impl MediaRenderer {
     fn render_image(&self, ctx: &Context<Self>) -> Html {
         let media = ctx.props().media.to_owned();
-        let parsed: ImageNodeProps =
+        let parsed: ImageMedia =
             serde_wasm_bindgen::from_value(media).expect("hope I can serde media - img");
         html! {
             <ImageNode url={parsed.url}/>
@@ -84,7 +83,7 @@ impl MediaRenderer {
 
     fn render_text(&self, ctx: &Context<Self>) -> Html {
         let media = ctx.props().media.to_owned();
-        let parsed: TextNodeProps =
+        let parsed: TextMedia =
             serde_wasm_bindgen::from_value(media).expect("hope I can serde media - txt");
         html! {
             <TextNode body={parsed.body} />
@@ -93,7 +92,7 @@ impl MediaRenderer {
 
     fn render_audio(&self, ctx: &Context<Self>) -> Html {
         let media = ctx.props().media.to_owned();
-        let parsed: AudioNodeProps =
+        let parsed: AudioMedia =
             serde_wasm_bindgen::from_value(media).expect("hope I can serde media - audio");
         html! {
             <AudioNode url={parsed.url}
@@ -125,11 +124,11 @@ impl Component for MediaRenderer {
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
-        match ctx.props().medium.as_str() {
-            "image" => self.render_image(ctx),
-            "text" => self.render_text(ctx),
-            "audio" => self.render_audio(ctx),
-            _ => self.render_default(ctx),
+        match MediaType::from_name(&ctx.props().medium) {
+            Some(MediaType::Image) => self.render_image(ctx),
+            Some(MediaType::Text) => self.render_text(ctx),
+            Some(MediaType::Audio) => self.render_audio(ctx),
+            None => self.render_default(ctx),
         }
     }
 }
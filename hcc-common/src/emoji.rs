@@ -137,13 +137,25 @@ pub fn decode(emojis: &str) -> Vec<u8> {
     e.as_bytes()
 }
 
+/// Like `decode`, but `None` for text that isn't emoji encoded bytes instead of panicking.
+pub fn try_decode(emojis: &str) -> Option<Vec<u8>> {
+    let indices = emojis
+        .chars()
+        .map(|c| REVERSE_EMOJI.get(&c).cloned())
+        .collect::<Option<Vec<usize>>>()?;
+
+    if !luhn::valid(&indices, 256) {
+        return None;
+    }
+
+    Some(indices.iter().take(indices.len() - 1).map(|i| *i as u8).collect())
+}
+
 #[cfg(test)]
 mod test {
 
-    use crate::util::encryption::DeterministicEmojiEncrypt;
-
     use super::*;
-    use orion::aead::{streaming::Nonce, SecretKey};
+    use orion::aead::SecretKey;
 
     #[test]
     fn test_emoji_byte_round_trip() {
@@ -168,6 +180,20 @@ mod test {
         assert_eq!(expected_emoji.len(), 68) // emoji encoding = 4 bytes per + 4 for checksum
     }
 
+    #[test]
+    fn test_try_decode_rejects_garbage() {
+        let encoded = encode(b"secret");
+        assert_eq!(try_decode(&encoded), Some(b"secret".to_vec()));
+
+        assert_eq!(try_decode("not emoji"), None);
+        assert_eq!(try_decode(""), None);
+        // a wrong checksum emoji
+        let mut chars: Vec<char> = encoded.chars().collect();
+        let checksum = chars.pop().unwrap();
+        chars.push(if checksum == EMOJI[0] { EMOJI[1] } else { EMOJI[0] });
+        assert_eq!(try_decode(&chars.into_iter().collect::<String>()), None);
+    }
+
     #[test]
     fn emoji_checksum() {
         let checksum = EmojiEncodedBytes::emoji_checksum("💉🦕💢🛒🦝🐾📣🤟🍑🍃😮💎📢🌱🖕🌈");
@@ -186,33 +212,4 @@ mod test {
 
         assert_eq!(decrypted, "secrets");
     }
-
-    #[test]
-    fn test_emoji_deterministic_encryption_stuff() {
-        let nonce_password = orion::pwhash::Password::generate(24).unwrap();
-        let nonce_bytes = nonce_password.unprotected_as_bytes();
-
-        let nonce_emoji = encode(nonce_bytes);
-        println!("encoded nonce: {}", nonce_emoji);
-
-        let plaintext = "i'm a secret";
-        let plaintext_bytes = plaintext.as_bytes();
-
-        let auth_password = orion::pwhash::Password::generate(32).unwrap();
-        let auth_bytes = auth_password.unprotected_as_bytes();
-
-        let auth_emoji = encode(auth_bytes);
-        println!("encoded auth: {}", auth_emoji);
-
-        let encrypted = DeterministicEmojiEncrypt::new(&auth_emoji, &nonce_emoji, &plaintext_bytes)
-            .unwrap()
-            .encrypted;
-
-        let encrypted_2 =
-            DeterministicEmojiEncrypt::new(&auth_emoji, &nonce_emoji, &plaintext_bytes)
-                .unwrap()
-                .encrypted;
-
-        assert_eq!(encrypted, encrypted_2);
-    }
 }
@@ -0,0 +1,369 @@
+// the keyring wire format, what the server and the wasm client put around every encrypted message
+//
+// broadcast (server to client): epoch | aead(plaintext)
+// user (client to server):      epoch | aead(timestamp | nonce | plaintext)
+//
+// the epoch is 4 bytes big endian, the timestamp is the sender's clock in millis as 8 bytes big endian
+// both sides ratchet to the next epoch on their own, so they have to derive exactly the same chain
+
+use orion::aead;
+use orion::errors::UnknownCryptoError;
+use orion::hazardous::kdf::hkdf;
+use serde::{Deserialize, Serialize};
+
+use super::emoji;
+
+pub const EPOCH_PREFIX_SIZE: usize = 4;
+
+pub const RATCHET_BROADCAST_INFO: &[u8] = b"hcc keyring ratchet broadcast";
+pub const RATCHET_USER_INFO: &[u8] = b"hcc keyring ratchet user";
+pub const RATCHET_SECRET_SIZE: usize = 32;
+
+pub const ENVELOPE_TIMESTAMP_SIZE: usize = 8;
+pub const ENVELOPE_NONCE_SIZE: usize = 16;
+pub const ENVELOPE_SIZE: usize = ENVELOPE_TIMESTAMP_SIZE + ENVELOPE_NONCE_SIZE;
+
+pub fn prefix_epoch(epoch: u32, cipher_bytes: Vec<u8>) -> Vec<u8> {
+    let mut bytes = epoch.to_be_bytes().to_vec();
+    bytes.extend(cipher_bytes);
+    bytes
+}
+
+pub fn split_epoch(bytes: &[u8]) -> Result<(u32, &[u8]), UnknownCryptoError> {
+    if bytes.len() < EPOCH_PREFIX_SIZE {
+        return Err(UnknownCryptoError);
+    }
+    let mut epoch = [0u8; EPOCH_PREFIX_SIZE];
+    epoch.copy_from_slice(&bytes[..EPOCH_PREFIX_SIZE]);
+    Ok((u32::from_be_bytes(epoch), &bytes[EPOCH_PREFIX_SIZE..]))
+}
+
+/// One step of the KDF ratchet, salted with the epoch being moved to.
+/// Old keys can't be recovered from new ones, so dropping a retired epoch really forgets it.
+pub fn ratchet_secret(
+    secret: &[u8],
+    next_epoch: u32,
+    info: &[u8],
+) -> Result<[u8; RATCHET_SECRET_SIZE], UnknownCryptoError> {
+    let mut next_secret = [0u8; RATCHET_SECRET_SIZE];
+    hkdf::sha512::derive_key(&next_epoch.to_be_bytes(), secret, Some(info), &mut next_secret)?;
+    Ok(next_secret)
+}
+
+/// Puts the replay envelope in front of a user message before it is sealed.
+pub fn envelope(timestamp: i64, nonce: &[u8; ENVELOPE_NONCE_SIZE], payload: &[u8]) -> Vec<u8> {
+    let mut bytes = timestamp.to_be_bytes().to_vec();
+    bytes.extend(nonce);
+    bytes.extend(payload);
+    bytes
+}
+
+/// Splits the replay envelope off an opened user message, `None` if it is too short to have one.
+pub fn split_envelope(plaintext: &[u8]) -> Option<(i64, [u8; ENVELOPE_NONCE_SIZE], &[u8])> {
+    if plaintext.len() < ENVELOPE_SIZE {
+        return None;
+    }
+
+    let mut timestamp = [0u8; ENVELOPE_TIMESTAMP_SIZE];
+    timestamp.copy_from_slice(&plaintext[..ENVELOPE_TIMESTAMP_SIZE]);
+    let mut nonce = [0u8; ENVELOPE_NONCE_SIZE];
+    nonce.copy_from_slice(&plaintext[ENVELOPE_TIMESTAMP_SIZE..ENVELOPE_SIZE]);
+
+    Some((i64::from_be_bytes(timestamp), nonce, &plaintext[ENVELOPE_SIZE..]))
+}
+
+/// The keys of an epoch the server has moved past, kept around for the grace window.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RetiredEpoch {
+    pub epoch: u32,
+    pub broadcast_secret: String,
+    pub user_secret: String,
+}
+
+/// How many retired epochs a keyring keeps unless the server says otherwise (HCC_KEYRING_GRACE_EPOCHS).
+pub const DEFAULT_GRACE_EPOCHS: usize = 2;
+
+/// The secrets of the current epoch plus the retired ones still inside the grace window, emoji encoded.
+/// The server and the client each keep one and move it forward the same way, only the server decides when.
+#[derive(Clone, Debug)]
+pub struct EpochKeys {
+    pub epoch: u32,
+    pub broadcast_secret: String,
+    pub user_secret: String,
+    /// newest first, at most `grace_epochs` long
+    pub retired: Vec<RetiredEpoch>,
+}
+
+impl EpochKeys {
+    pub fn new(broadcast_secret: String, user_secret: String) -> EpochKeys {
+        EpochKeys {
+            epoch: 0,
+            broadcast_secret,
+            user_secret,
+            retired: vec![],
+        }
+    }
+
+    /// Move on to the next epoch, the last `grace_epochs` stay around for messages already on their way.
+    pub fn ratchet(&mut self, grace_epochs: usize) -> Result<(), UnknownCryptoError> {
+        let next_epoch = self.epoch.checked_add(1).ok_or(UnknownCryptoError)?;
+
+        let next_broadcast_secret =
+            ratchet_emoji_secret(&self.broadcast_secret, next_epoch, RATCHET_BROADCAST_INFO)?;
+        let next_user_secret =
+            ratchet_emoji_secret(&self.user_secret, next_epoch, RATCHET_USER_INFO)?;
+
+        self.retired.insert(
+            0,
+            RetiredEpoch {
+                epoch: self.epoch,
+                broadcast_secret: std::mem::replace(
+                    &mut self.broadcast_secret,
+                    next_broadcast_secret,
+                ),
+                user_secret: std::mem::replace(&mut self.user_secret, next_user_secret),
+            },
+        );
+        self.retired.truncate(grace_epochs);
+        self.epoch = next_epoch;
+        Ok(())
+    }
+
+    /// Anything older than the grace window, or from an epoch not reached yet, has no secret.
+    pub fn broadcast_secret_for(&self, epoch: u32) -> Result<&str, UnknownCryptoError> {
+        if epoch == self.epoch {
+            return Ok(&self.broadcast_secret);
+        }
+        self.retired_epoch(epoch)
+            .map(|retired| retired.broadcast_secret.as_str())
+    }
+
+    pub fn user_secret_for(&self, epoch: u32) -> Result<&str, UnknownCryptoError> {
+        if epoch == self.epoch {
+            return Ok(&self.user_secret);
+        }
+        self.retired_epoch(epoch)
+            .map(|retired| retired.user_secret.as_str())
+    }
+
+    fn retired_epoch(&self, epoch: u32) -> Result<&RetiredEpoch, UnknownCryptoError> {
+        self.retired
+            .iter()
+            .find(|retired| retired.epoch == epoch)
+            .ok_or(UnknownCryptoError)
+    }
+
+    pub fn seal_broadcast(&self, plaintext: &[u8]) -> Result<Vec<u8>, UnknownCryptoError> {
+        Ok(prefix_epoch(
+            self.epoch,
+            seal_with_emoji_secret(&self.broadcast_secret, plaintext)?,
+        ))
+    }
+
+    /// `enveloped` already carries the replay envelope, see `envelope`.
+    pub fn seal_user(&self, enveloped: &[u8]) -> Result<Vec<u8>, UnknownCryptoError> {
+        Ok(prefix_epoch(
+            self.epoch,
+            seal_with_emoji_secret(&self.user_secret, enveloped)?,
+        ))
+    }
+
+    pub fn open_broadcast(&self, message_bytes: &[u8]) -> Result<Vec<u8>, UnknownCryptoError> {
+        let (epoch, cipher_bytes) = split_epoch(message_bytes)?;
+        open_with_emoji_secret(self.broadcast_secret_for(epoch)?, cipher_bytes)
+    }
+
+    /// The opened message still starts with its replay envelope, checking it is up to the receiver.
+    pub fn open_user(&self, message_bytes: &[u8]) -> Result<Vec<u8>, UnknownCryptoError> {
+        let (epoch, cipher_bytes) = split_epoch(message_bytes)?;
+        open_with_emoji_secret(self.user_secret_for(epoch)?, cipher_bytes)
+    }
+}
+
+fn emoji_secret(emoji_encoded_secret: &str) -> Result<aead::SecretKey, UnknownCryptoError> {
+    aead::SecretKey::from_slice(&emoji::try_decode(emoji_encoded_secret).ok_or(UnknownCryptoError)?)
+}
+
+fn ratchet_emoji_secret(
+    emoji_encoded_secret: &str,
+    next_epoch: u32,
+    info: &[u8],
+) -> Result<String, UnknownCryptoError> {
+    let secret_bytes = emoji::try_decode(emoji_encoded_secret).ok_or(UnknownCryptoError)?;
+    let next_secret = ratchet_secret(&secret_bytes, next_epoch, info)?;
+    Ok(emoji::encode(&next_secret))
+}
+
+fn seal_with_emoji_secret(
+    emoji_encoded_secret: &str,
+    plaintext: &[u8],
+) -> Result<Vec<u8>, UnknownCryptoError> {
+    aead::seal(&emoji_secret(emoji_encoded_secret)?, plaintext)
+}
+
+fn open_with_emoji_secret(
+    emoji_encoded_secret: &str,
+    cipher_bytes: &[u8],
+) -> Result<Vec<u8>, UnknownCryptoError> {
+    aead::open(&emoji_secret(emoji_encoded_secret)?, cipher_bytes)
+}
+
+/// A session keyring as the server stores it on the session, sealed with HCC_ENCRYPTION_KEY_EMOJI.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EncryptedKeyring {
+    b: String,
+}
+
+/// What is inside an `EncryptedKeyring`, the field names are short because every session carries them.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TopSecretSharedKeyring {
+    /// server identity
+    pub a: String,
+    /// client identity
+    pub b: String,
+    /// broadcast secret
+    pub x: String,
+    /// user secret
+    pub y: String,
+    // keyrings sealed before rotation existed are epoch 0 with nothing retired
+    #[serde(default)]
+    pub e: u32,
+    #[serde(default)]
+    pub t: i64,
+    #[serde(default)]
+    pub m: u32,
+    #[serde(default)]
+    pub r: Vec<RetiredEpoch>,
+}
+
+impl EncryptedKeyring {
+    pub fn open_with_emoji(&self, emoji_key: &str) -> Result<TopSecretSharedKeyring, UnknownCryptoError> {
+        let secret = aead::SecretKey::from_slice(&emoji::decode(emoji_key))?;
+        let bytes = aead::open(&secret, &emoji::decode(&self.b))?;
+        serde_json::from_slice(&bytes).map_err(|_| UnknownCryptoError)
+    }
+
+    pub fn seal_with_emoji(
+        keyring: &TopSecretSharedKeyring,
+        emoji_key: &str,
+    ) -> Result<EncryptedKeyring, UnknownCryptoError> {
+        let message = serde_json::to_vec(keyring).map_err(|_| UnknownCryptoError)?;
+        let secret = aead::SecretKey::from_slice(&emoji::decode(emoji_key))?;
+        let bytes = aead::seal(&secret, &message)?;
+        Ok(EncryptedKeyring {
+            b: emoji::encode(&bytes),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    // computed independently with a textbook rfc 5869 hkdf-sha512, a change here breaks every client
+    const BROADCAST_EPOCH_1: [u8; 32] = [
+        0x53, 0x34, 0x67, 0xc1, 0x36, 0x08, 0xaa, 0xff, 0x71, 0x76, 0xb3, 0x67, 0x27, 0xd3, 0x9e, 0x53,
+        0x68, 0x65, 0xb8, 0x85, 0x29, 0x61, 0x97, 0xd3, 0x80, 0x90, 0xdc, 0x9e, 0xfe, 0xe4, 0xb0, 0x7f,
+    ];
+    const USER_EPOCH_1: [u8; 32] = [
+        0x42, 0x03, 0x07, 0x6b, 0x66, 0x4b, 0x6e, 0x6a, 0x8d, 0x11, 0x1e, 0x09, 0x4f, 0x6b, 0xa1, 0x9a,
+        0xa7, 0x1e, 0x7f, 0xfb, 0x48, 0x40, 0x54, 0x32, 0xd6, 0x32, 0xf0, 0xc8, 0x75, 0x4e, 0xcc, 0xa7,
+    ];
+
+    #[test]
+    fn test_ratchet_matches_known_chain() {
+        let secret = [1u8; 32];
+        assert_eq!(ratchet_secret(&secret, 1, RATCHET_BROADCAST_INFO).unwrap(), BROADCAST_EPOCH_1);
+        assert_eq!(ratchet_secret(&secret, 1, RATCHET_USER_INFO).unwrap(), USER_EPOCH_1);
+    }
+
+    #[test]
+    fn test_user_message_round_trip() {
+        // sealed the way the client does, opened the way the server does
+        let secret = aead::SecretKey::from_slice(&[9u8; 32]).unwrap();
+        let nonce = [3u8; ENVELOPE_NONCE_SIZE];
+        let sealed = aead::seal(&secret, &envelope(1_650_000_000_000, &nonce, b"hello")).unwrap();
+        let message = prefix_epoch(7, sealed);
+
+        assert_eq!(&message[..EPOCH_PREFIX_SIZE], &[0, 0, 0, 7]);
+
+        let (epoch, cipher_bytes) = split_epoch(&message).unwrap();
+        let opened = aead::open(&secret, cipher_bytes).unwrap();
+        assert_eq!(&opened[..ENVELOPE_TIMESTAMP_SIZE], &1_650_000_000_000i64.to_be_bytes());
+
+        let (timestamp, opened_nonce, payload) = split_envelope(&opened).unwrap();
+        assert_eq!(epoch, 7);
+        assert_eq!(timestamp, 1_650_000_000_000);
+        assert_eq!(opened_nonce, nonce);
+        assert_eq!(payload, b"hello");
+
+        assert!(split_epoch(&[0, 1]).is_err());
+        assert!(split_envelope(b"short").is_none());
+    }
+
+    #[test]
+    fn test_epoch_keys_keep_the_grace_window() {
+        let mut server = EpochKeys::new(emoji::encode(&[1u8; 32]), emoji::encode(&[2u8; 32]));
+        let mut client = server.clone();
+
+        let nonce = [3u8; ENVELOPE_NONCE_SIZE];
+        let early = client
+            .seal_user(&envelope(1, &nonce, b"first epoch"))
+            .unwrap();
+
+        server.ratchet(1).unwrap();
+        assert_eq!(server.epoch, 1);
+        assert_eq!(server.broadcast_secret, emoji::encode(&BROADCAST_EPOCH_1));
+        assert_eq!(
+            split_envelope(&server.open_user(&early).unwrap())
+                .unwrap()
+                .2,
+            b"first epoch"
+        );
+
+        // the client follows the server's broadcasts forward
+        let broadcast = server.seal_broadcast(b"hello client").unwrap();
+        assert!(client.open_broadcast(&broadcast).is_err());
+        client.ratchet(1).unwrap();
+        assert_eq!(client.open_broadcast(&broadcast).unwrap(), b"hello client");
+
+        server.ratchet(1).unwrap();
+        assert_eq!(server.retired.len(), 1);
+        assert!(server.open_user(&early).is_err());
+        assert!(server.user_secret_for(3).is_err());
+    }
+
+    #[test]
+    fn test_sealed_keyring_round_trip() {
+        let key = emoji::encode(&[5u8; 32]);
+        let keyring = TopSecretSharedKeyring {
+            a: String::from("server"),
+            b: String::from("client"),
+            x: emoji::encode(&[1u8; 32]),
+            y: emoji::encode(&[2u8; 32]),
+            e: 3,
+            t: 42,
+            m: 1,
+            r: vec![],
+        };
+
+        let opened = EncryptedKeyring::seal_with_emoji(&keyring, &key)
+            .unwrap()
+            .open_with_emoji(&key)
+            .unwrap();
+        assert_eq!(opened.x, keyring.x);
+        assert_eq!(opened.e, 3);
+
+        let other_key = emoji::encode(&[6u8; 32]);
+        assert!(EncryptedKeyring::seal_with_emoji(&keyring, &key)
+            .unwrap()
+            .open_with_emoji(&other_key)
+            .is_err());
+
+        let before_rotation: TopSecretSharedKeyring =
+            serde_json::from_str(r#"{"a":"s","b":"c","x":"x","y":"y"}"#).unwrap();
+        assert_eq!(before_rotation.e, 0);
+        assert!(before_rotation.r.is_empty());
+    }
+}
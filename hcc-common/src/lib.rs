@@ -0,0 +1,10 @@
+// formats the server and the wasm client both speak
+// if one side changed its own copy the other would fail to open messages, so there is only one copy
+
+#[macro_use]
+extern crate lazy_static;
+
+pub mod emoji;
+pub mod keyring;
+pub mod media;
+pub mod wire;
@@ -0,0 +1,88 @@
+// media nodes the server renders into a page as json and the wasm client mounts
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaType {
+    Image,
+    Text,
+    Audio,
+}
+
+impl MediaType {
+    /// The `medium` attribute on a media node.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MediaType::Image => "image",
+            MediaType::Text => "text",
+            MediaType::Audio => "audio",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<MediaType> {
+        match name {
+            "image" => Some(MediaType::Image),
+            "text" => Some(MediaType::Text),
+            "audio" => Some(MediaType::Audio),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for MediaType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ImageMedia {
+    pub url: String, // todo: add alt text, etc
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TextMedia {
+    pub body: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AudioMedia {
+    pub title: String,
+    /// seconds
+    pub duration: i32,
+    pub khz: i32,
+    pub kbps: i32,
+    pub url: String,
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_audio_json_round_trip() {
+        let audio = AudioMedia {
+            title: String::from("ready"),
+            duration: 186,
+            khz: 44,
+            kbps: 320,
+            url: String::from("https://example.com/ready.mp3"),
+        };
+
+        let json = serde_json::to_string(&audio).unwrap();
+        assert_eq!(
+            json,
+            r#"{"title":"ready","duration":186,"khz":44,"kbps":320,"url":"https://example.com/ready.mp3"}"#
+        );
+        assert_eq!(serde_json::from_str::<AudioMedia>(&json).unwrap(), audio);
+    }
+
+    #[test]
+    fn test_media_type_names() {
+        for medium in [MediaType::Image, MediaType::Text, MediaType::Audio] {
+            assert_eq!(MediaType::from_name(medium.name()), Some(medium));
+        }
+        assert_eq!(MediaType::from_name("video"), None);
+    }
+}
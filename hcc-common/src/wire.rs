@@ -0,0 +1,102 @@
+// how an encrypted fragment travels in a response body
+// the client lists the encodings it reads in the request header, most preferred first,
+// and the server answers in the first one it knows and names it in the same response header
+
+use super::emoji;
+
+pub const ENCODING_HEADER: &str = "x-hcc-encoding";
+
+// answered instead of an encoding for public fragments, the body is plain html
+pub const PUBLIC_ENCODING: &str = "identity";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WireEncoding {
+    /// 4 bytes of emoji per byte of ciphertext, the original format and still the prettiest
+    Emoji,
+    Base64Url,
+    /// the ciphertext as is, `application/octet-stream`
+    Binary,
+}
+
+// clients from before the negotiation send no header and only read emoji
+impl Default for WireEncoding {
+    fn default() -> Self {
+        WireEncoding::Emoji
+    }
+}
+
+impl WireEncoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WireEncoding::Emoji => "emoji",
+            WireEncoding::Base64Url => "base64url",
+            WireEncoding::Binary => "binary",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<WireEncoding> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "emoji" => Some(WireEncoding::Emoji),
+            "base64url" => Some(WireEncoding::Base64Url),
+            "binary" => Some(WireEncoding::Binary),
+            _ => None,
+        }
+    }
+
+    /// The first encoding in a comma separated preference list that we know.
+    pub fn negotiate(header: Option<&str>) -> WireEncoding {
+        header
+            .and_then(|names| names.split(',').find_map(WireEncoding::from_name))
+            .unwrap_or_default()
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            WireEncoding::Emoji | WireEncoding::Base64Url => "text/plain;charset=utf-8",
+            WireEncoding::Binary => "application/octet-stream",
+        }
+    }
+
+    pub fn encode(&self, cipher_bytes: &[u8]) -> Vec<u8> {
+        match self {
+            WireEncoding::Emoji => emoji::encode(cipher_bytes).into_bytes(),
+            WireEncoding::Base64Url => {
+                base64::encode_config(cipher_bytes, base64::URL_SAFE_NO_PAD).into_bytes()
+            }
+            WireEncoding::Binary => cipher_bytes.to_vec(),
+        }
+    }
+
+    pub fn decode(&self, body: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            WireEncoding::Emoji => std::str::from_utf8(body).ok().map(emoji::decode),
+            WireEncoding::Base64Url => base64::decode_config(body, base64::URL_SAFE_NO_PAD).ok(),
+            WireEncoding::Binary => Some(body.to_vec()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_encodings_round_trip() {
+        let cipher_bytes = [0u8, 1, 2, 254, 255, 4, 0, 0, 0, 7];
+        for encoding in [WireEncoding::Emoji, WireEncoding::Base64Url, WireEncoding::Binary] {
+            let body = encoding.encode(&cipher_bytes);
+            assert_eq!(encoding.decode(&body).unwrap(), cipher_bytes);
+            assert_eq!(WireEncoding::from_name(encoding.name()), Some(encoding));
+        }
+        assert_eq!(WireEncoding::Base64Url.encode(&[251, 255]), b"-_8");
+    }
+
+    #[test]
+    fn test_negotiates_first_known_encoding() {
+        assert_eq!(WireEncoding::negotiate(None), WireEncoding::Emoji);
+        assert_eq!(WireEncoding::negotiate(Some("brotli, Binary, emoji")), WireEncoding::Binary);
+        assert_eq!(WireEncoding::negotiate(Some("base64url")), WireEncoding::Base64Url);
+        assert_eq!(WireEncoding::negotiate(Some("brotli")), WireEncoding::Emoji);
+    }
+}
@@ -0,0 +1,396 @@
+// column types for values we only keep encrypted at rest
+//
+// Encrypted<T>:   version | nonce | xchacha20poly1305(json(T), ad = version | "table.column")
+// BlindIndexed<T>: blake2b-mac(T)             deterministic, for lookups by equality
+//
+// both are stored emoji encoded in a text column, the version is 4 bytes big endian.
+// the column name is bound in as associated data, a value copied into another column won't open there.
+//
+// nothing here happens on its own: sea-orm only moves the stored text, so the dao seals before every
+// insert or update and opens after every select, with the key provider on the server wiring
+// (HCC_COLUMN_KEY_EMOJI and its retired versions, never HCC_ENCRYPTION_KEY_EMOJI):
+//
+//   let profile = Encrypted::seal(&wiring.services.column_keys, "profile.bio", &bio)?;
+//   let bio: String = model.profile.open(&wiring.services.column_keys, "profile.bio")?;
+//   Entity::find().filter(Column::EmailIndex.eq(BlindIndexed::of(&wiring.services.column_keys, &email)?))
+
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use hcc_common::emoji;
+use orion::hazardous::aead::xchacha20poly1305::{self, Nonce, SecretKey};
+use orion::hazardous::mac::blake2b;
+use orion::hazardous::mac::poly1305::POLY1305_OUTSIZE;
+use orion::hazardous::stream::xchacha20::XCHACHA_NONCESIZE;
+use sea_orm::sea_query::{ColumnType, Nullable, ValueType, ValueTypeErr};
+use sea_orm::{QueryResult, TryGetError, TryGetable, Value};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+pub const KEY_VERSION_SIZE: usize = 4;
+pub const COLUMN_KEY_SIZE: usize = 32;
+// the same width as the member email index, so the two can be compared
+pub const BLIND_INDEX_SIZE: usize = 32;
+
+#[derive(Debug)]
+pub struct ColumnCryptoError(pub String);
+
+impl fmt::Display for ColumnCryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column encryption failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for ColumnCryptoError {}
+
+impl From<orion::errors::UnknownCryptoError> for ColumnCryptoError {
+    fn from(_: orion::errors::UnknownCryptoError) -> Self {
+        ColumnCryptoError(String::from("crypto error"))
+    }
+}
+
+impl From<serde_json::Error> for ColumnCryptoError {
+    fn from(e: serde_json::Error) -> Self {
+        ColumnCryptoError(e.to_string())
+    }
+}
+
+/// Where encrypted columns get their keys. Old key versions stay readable for as long as
+/// the provider hands them out, new values are always sealed under the current one.
+pub trait ColumnKeyProvider: Send + Sync {
+    fn current_key(&self) -> (u32, &[u8]);
+    /// Any version values may still be sealed under, the current one included.
+    fn key(&self, version: u32) -> Option<&[u8]>;
+    fn blind_index_key(&self) -> &[u8];
+}
+
+impl<K: ColumnKeyProvider + ?Sized> ColumnKeyProvider for Arc<K> {
+    fn current_key(&self) -> (u32, &[u8]) {
+        (**self).current_key()
+    }
+
+    fn key(&self, version: u32) -> Option<&[u8]> {
+        (**self).key(version)
+    }
+
+    fn blind_index_key(&self) -> &[u8] {
+        (**self).blind_index_key()
+    }
+}
+
+/// Keys held in memory, straight from the config.
+pub struct ColumnKeys {
+    current_version: u32,
+    keys: HashMap<u32, Vec<u8>>,
+    blind_index_key: Vec<u8>,
+}
+
+impl ColumnKeys {
+    pub fn new(
+        current_version: u32,
+        current_key: Vec<u8>,
+        retired_keys: Vec<(u32, Vec<u8>)>,
+        blind_index_key: Vec<u8>,
+    ) -> Result<ColumnKeys, ColumnCryptoError> {
+        let mut keys = HashMap::new();
+
+        for (version, key) in std::iter::once((current_version, current_key)).chain(retired_keys) {
+            if key.len() != COLUMN_KEY_SIZE {
+                return Err(ColumnCryptoError(format!(
+                    "key version {} must be {} bytes",
+                    version, COLUMN_KEY_SIZE
+                )));
+            }
+            if keys.insert(version, key).is_some() {
+                return Err(ColumnCryptoError(format!("key version {} is used twice", version)));
+            }
+        }
+
+        if blind_index_key.len() != COLUMN_KEY_SIZE {
+            return Err(ColumnCryptoError(format!(
+                "the blind index key must be {} bytes",
+                COLUMN_KEY_SIZE
+            )));
+        }
+
+        Ok(ColumnKeys {
+            current_version: current_version,
+            keys: keys,
+            blind_index_key: blind_index_key,
+        })
+    }
+}
+
+impl ColumnKeyProvider for ColumnKeys {
+    fn current_key(&self) -> (u32, &[u8]) {
+        (self.current_version, self.keys[&self.current_version].as_slice())
+    }
+
+    fn key(&self, version: u32) -> Option<&[u8]> {
+        self.keys.get(&version).map(Vec::as_slice)
+    }
+
+    fn blind_index_key(&self) -> &[u8] {
+        &self.blind_index_key
+    }
+}
+
+fn decode_stored(stored: &str) -> Result<Vec<u8>, ColumnCryptoError> {
+    emoji::try_decode(stored).ok_or_else(|| ColumnCryptoError(String::from("not emoji encoded")))
+}
+
+/// A `T` sealed with a random nonce, the same value never looks the same twice.
+pub struct Encrypted<T> {
+    stored: String,
+    plaintext: PhantomData<fn() -> T>,
+}
+
+// the version goes in too, so the prefix can't be swapped for another version's
+fn associated_data(version: u32, column: &str) -> Vec<u8> {
+    let mut ad = version.to_be_bytes().to_vec();
+    ad.extend(column.as_bytes());
+    ad
+}
+
+impl<T: Serialize + DeserializeOwned> Encrypted<T> {
+    /// `column` names where the value is stored, `table.column`, and has to be given again to open it.
+    pub fn seal<K: ColumnKeyProvider + ?Sized>(
+        keys: &K,
+        column: &str,
+        value: &T,
+    ) -> Result<Self, ColumnCryptoError> {
+        let (version, key) = keys.current_key();
+        let secret = SecretKey::from_slice(key)?;
+        let nonce = Nonce::generate();
+        let plaintext = serde_json::to_vec(value)?;
+
+        let mut sealed = vec![0u8; plaintext.len() + POLY1305_OUTSIZE];
+        xchacha20poly1305::seal(
+            &secret,
+            &nonce,
+            &plaintext,
+            Some(&associated_data(version, column)),
+            &mut sealed,
+        )?;
+
+        let mut bytes = version.to_be_bytes().to_vec();
+        bytes.extend(nonce.as_ref());
+        bytes.extend(sealed);
+
+        Ok(Encrypted::from_stored(emoji::encode(&bytes)))
+    }
+
+    pub fn open<K: ColumnKeyProvider + ?Sized>(
+        &self,
+        keys: &K,
+        column: &str,
+    ) -> Result<T, ColumnCryptoError> {
+        let version = self.key_version()?;
+        let key = keys
+            .key(version)
+            .ok_or_else(|| ColumnCryptoError(format!("no key for version {}", version)))?;
+        let secret = SecretKey::from_slice(key)?;
+
+        let bytes = decode_stored(&self.stored)?;
+        let sealed = &bytes[KEY_VERSION_SIZE..];
+        if sealed.len() < XCHACHA_NONCESIZE + POLY1305_OUTSIZE {
+            return Err(ColumnCryptoError(String::from("too short for a sealed value")));
+        }
+        let nonce = Nonce::from_slice(&sealed[..XCHACHA_NONCESIZE])?;
+        let ciphertext = &sealed[XCHACHA_NONCESIZE..];
+
+        let mut plaintext = vec![0u8; ciphertext.len() - POLY1305_OUTSIZE];
+        xchacha20poly1305::open(
+            &secret,
+            &nonce,
+            ciphertext,
+            Some(&associated_data(version, column)),
+            &mut plaintext,
+        )?;
+
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+impl<T> Encrypted<T> {
+    fn from_stored(stored: String) -> Self {
+        Encrypted {
+            stored: stored,
+            plaintext: PhantomData,
+        }
+    }
+
+    /// The key version this was sealed under. Values on a retired version need opening and sealing
+    /// again before that key can be dropped.
+    pub fn key_version(&self) -> Result<u32, ColumnCryptoError> {
+        let bytes = decode_stored(&self.stored)?;
+        if bytes.len() < KEY_VERSION_SIZE {
+            return Err(ColumnCryptoError(String::from("too short for a key version")));
+        }
+
+        let mut version = [0u8; KEY_VERSION_SIZE];
+        version.copy_from_slice(&bytes[..KEY_VERSION_SIZE]);
+        Ok(u32::from_be_bytes(version))
+    }
+}
+
+/// A keyed hash of a `T`, equal values always index the same so the column can be searched.
+/// Strings are indexed as their bytes (like member emails), anything else as its json.
+pub struct BlindIndexed<T> {
+    stored: String,
+    plaintext: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize> BlindIndexed<T> {
+    pub fn of<K: ColumnKeyProvider + ?Sized>(keys: &K, value: &T) -> Result<Self, ColumnCryptoError> {
+        let bytes = match serde_json::to_value(value)? {
+            serde_json::Value::String(text) => text.into_bytes(),
+            other => other.to_string().into_bytes(),
+        };
+
+        let key = blake2b::SecretKey::from_slice(keys.blind_index_key())?;
+        let mut state = blake2b::Blake2b::new(&key, BLIND_INDEX_SIZE)?;
+        state.update(&bytes)?;
+        let tag = state.finalize()?;
+
+        Ok(BlindIndexed::from_stored(emoji::encode(tag.unprotected_as_bytes())))
+    }
+}
+
+impl<T> BlindIndexed<T> {
+    fn from_stored(stored: String) -> Self {
+        BlindIndexed {
+            stored: stored,
+            plaintext: PhantomData,
+        }
+    }
+}
+
+// what sea-orm needs to read and write them as text columns, no bounds on T so entities can derive
+macro_rules! text_column {
+    ($column:ident) => {
+        impl<T> Clone for $column<T> {
+            fn clone(&self) -> Self {
+                $column::from_stored(self.stored.clone())
+            }
+        }
+
+        impl<T> PartialEq for $column<T> {
+            fn eq(&self, other: &Self) -> bool {
+                self.stored == other.stored
+            }
+        }
+
+        impl<T> fmt::Debug for $column<T> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_tuple(stringify!($column)).field(&self.stored).finish()
+            }
+        }
+
+        impl<T> From<$column<T>> for Value {
+            fn from(column: $column<T>) -> Self {
+                Value::String(Some(Box::new(column.stored)))
+            }
+        }
+
+        impl<T> TryGetable for $column<T> {
+            fn try_get(res: &QueryResult, pre: &str, col: &str) -> Result<Self, TryGetError> {
+                String::try_get(res, pre, col).map($column::from_stored)
+            }
+        }
+
+        impl<T> ValueType for $column<T> {
+            fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
+                match v {
+                    Value::String(Some(stored)) => Ok($column::from_stored(*stored)),
+                    _ => Err(ValueTypeErr),
+                }
+            }
+
+            fn type_name() -> String {
+                String::from(stringify!($column))
+            }
+
+            fn column_type() -> ColumnType {
+                ColumnType::Text
+            }
+        }
+
+        impl<T> Nullable for $column<T> {
+            fn null() -> Value {
+                Value::String(None)
+            }
+        }
+    };
+}
+
+text_column!(Encrypted);
+text_column!(BlindIndexed);
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    // every version before the current one is still around
+    fn keys(current_version: u32) -> ColumnKeys {
+        ColumnKeys::new(
+            current_version,
+            vec![current_version as u8; COLUMN_KEY_SIZE],
+            (1..current_version).map(|v| (v, vec![v as u8; COLUMN_KEY_SIZE])).collect(),
+            vec![9u8; COLUMN_KEY_SIZE],
+        )
+        .unwrap()
+    }
+
+    const COLUMN: &str = "ledger.entry";
+
+    #[test]
+    fn test_sealed_values_survive_a_key_rotation() {
+        let sealed = Encrypted::seal(&keys(1), COLUMN, &String::from("ledger entry")).unwrap();
+        assert_eq!(sealed.key_version().unwrap(), 1);
+        assert_ne!(sealed, Encrypted::seal(&keys(1), COLUMN, &String::from("ledger entry")).unwrap());
+
+        let rotated = keys(2);
+        assert_eq!(sealed.open(&rotated, COLUMN).unwrap(), "ledger entry");
+        assert_eq!(
+            Encrypted::seal(&rotated, COLUMN, &vec![1, 2, 3]).unwrap().key_version().unwrap(),
+            2
+        );
+
+        let forgotten = ColumnKeys::new(2, vec![2u8; 32], vec![], vec![9u8; 32]).unwrap();
+        assert!(sealed.open(&forgotten, COLUMN).is_err());
+    }
+
+    #[test]
+    fn test_sealed_values_only_open_in_their_column() {
+        let sealed = Encrypted::seal(&keys(1), COLUMN, &String::from("ledger entry")).unwrap();
+        assert!(Encrypted::<String>::from_stored(sealed.stored.clone())
+            .open(&keys(1), "ledger.memo")
+            .is_err());
+
+        // relabelling the version can't move a value onto another key
+        let mut bytes = decode_stored(&sealed.stored).unwrap();
+        bytes[..KEY_VERSION_SIZE].copy_from_slice(&2u32.to_be_bytes());
+        let relabelled = Encrypted::<String>::from_stored(emoji::encode(&bytes));
+        let same_key_twice =
+            ColumnKeys::new(2, vec![1u8; 32], vec![(1, vec![1u8; 32])], vec![9u8; 32]).unwrap();
+        assert!(relabelled.open(&same_key_twice, COLUMN).is_err());
+        assert_eq!(sealed.open(&same_key_twice, COLUMN).unwrap(), "ledger entry");
+    }
+
+    #[test]
+    fn test_blind_index_is_deterministic() {
+        let email = String::from("member@holycharisma.com");
+        assert_eq!(
+            BlindIndexed::of(&keys(1), &email).unwrap(),
+            BlindIndexed::of(&keys(2), &email).unwrap()
+        );
+        assert_ne!(
+            BlindIndexed::of(&keys(1), &email).unwrap(),
+            BlindIndexed::of(&keys(1), &String::from("other@holycharisma.com")).unwrap()
+        );
+    }
+}
@@ -1,3 +1,6 @@
+pub mod permission;
 pub mod session;
+pub mod settings;
 pub mod server_config;
-pub mod sea_orm;
\ No newline at end of file
+pub mod sea_orm;
+pub mod encrypted;
@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+
+// names match the rows seeded by m01_000005_create_role_tables
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Role {
+    Admin,
+    Moderator,
+    Patron,
+    Member,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Admin, Role::Moderator, Role::Patron, Role::Member];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Moderator => "moderator",
+            Role::Patron => "patron",
+            Role::Member => "member",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Role> {
+        Role::ALL.iter().find(|r| r.name() == name).copied()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Permission {
+    ViewApp,
+    ViewSecrets,
+    ViewPatronMedia,
+    ModerateContent,
+    ViewAdmin,
+    ManageUsers,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 6] = [
+        Permission::ViewApp,
+        Permission::ViewSecrets,
+        Permission::ViewPatronMedia,
+        Permission::ModerateContent,
+        Permission::ViewAdmin,
+        Permission::ManageUsers,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Permission::ViewApp => "app.view",
+            Permission::ViewSecrets => "secrets.view",
+            Permission::ViewPatronMedia => "media.patron",
+            Permission::ModerateContent => "content.moderate",
+            Permission::ViewAdmin => "admin.view",
+            Permission::ManageUsers => "users.manage",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Permission> {
+        Permission::ALL.iter().find(|p| p.name() == name).copied()
+    }
+}
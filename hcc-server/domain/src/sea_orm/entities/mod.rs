@@ -3,7 +3,17 @@
 pub mod prelude;
 
 pub mod async_sessions;
+pub mod blind_index_key;
+pub mod invite;
+pub mod invite_redemption;
+pub mod login_attempt;
 pub mod media_node;
+pub mod permission;
+pub mod role;
+pub mod role_permission;
 pub mod seaql_migrations;
 pub mod user_attributes;
 pub mod user_email_password;
+pub mod user_role;
+pub mod user_session;
+pub mod user_totp;
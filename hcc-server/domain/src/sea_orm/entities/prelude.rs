@@ -1,7 +1,17 @@
 //! SeaORM Entity. Generated by sea-orm-codegen 0.7.0
 
 pub use super::async_sessions::Entity as AsyncSessions;
+pub use super::blind_index_key::Entity as BlindIndexKey;
+pub use super::invite::Entity as Invite;
+pub use super::invite_redemption::Entity as InviteRedemption;
+pub use super::login_attempt::Entity as LoginAttempt;
 pub use super::media_node::Entity as MediaNode;
+pub use super::permission::Entity as Permission;
+pub use super::role::Entity as Role;
+pub use super::role_permission::Entity as RolePermission;
 pub use super::seaql_migrations::Entity as SeaqlMigrations;
 pub use super::user_attributes::Entity as UserAttributes;
 pub use super::user_email_password::Entity as UserEmailPassword;
+pub use super::user_role::Entity as UserRole;
+pub use super::user_session::Entity as UserSession;
+pub use super::user_totp::Entity as UserTotp;
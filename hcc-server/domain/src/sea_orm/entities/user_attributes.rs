@@ -14,6 +14,7 @@ pub struct Model {
     pub last_login: Option<DateTimeWithTimeZone>,
     pub last_updated: DateTimeWithTimeZone,
     pub settings: String,
+    pub invite_quota: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
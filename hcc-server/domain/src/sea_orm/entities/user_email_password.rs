@@ -12,12 +12,35 @@ pub struct Model {
     pub email_hash: String,
     pub password: String,
     pub active: bool,
+    pub email_verified_at: Option<DateTimeWithTimeZone>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::invite::Entity")]
+    Invite,
+    #[sea_orm(has_many = "super::invite_redemption::Entity")]
+    InviteRedemption,
     #[sea_orm(has_many = "super::user_attributes::Entity")]
     UserAttributes,
+    #[sea_orm(has_many = "super::user_totp::Entity")]
+    UserTotp,
+    #[sea_orm(has_many = "super::user_role::Entity")]
+    UserRole,
+    #[sea_orm(has_many = "super::user_session::Entity")]
+    UserSession,
+}
+
+impl Related<super::invite::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Invite.def()
+    }
+}
+
+impl Related<super::invite_redemption::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::InviteRedemption.def()
+    }
 }
 
 impl Related<super::user_attributes::Entity> for Entity {
@@ -26,4 +49,22 @@ impl Related<super::user_attributes::Entity> for Entity {
     }
 }
 
+impl Related<super::user_totp::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserTotp.def()
+    }
+}
+
+impl Related<super::user_role::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserRole.def()
+    }
+}
+
+impl Related<super::user_session::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserSession.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -1,14 +1,81 @@
+use std::fmt;
+
 #[derive(Clone)]
 pub struct ServerConfig {
     pub domain: String,
     pub session_cookie_name: String,
     pub session_ttl_hours: u32,
     pub encryption_key_emoji: String,
-    pub encryption_salt_emoji: String,
-    pub rsa_private_key_path: String,
-    pub rsa_public_key_path: String,
+    pub column_key_emoji: String,
+    pub column_key_version: u32,
+    pub column_retired_keys_emoji: String,
+    pub blind_index_key_emoji: String,
+    pub jwt_private_key_path: String,
+    pub jwt_public_key_path: String,
+    pub jwt_signing_kid: String,
+    pub jwt_signing_algorithm: String,
+    pub jwt_verify_keys: String,
     pub postgres_sql_connection_url: String,
     pub bind_url: String,
+    pub trust_proxy_headers: bool,
     pub super_user_email: String,
     pub super_user_pwhash_emoji: String,
+    pub mailer_transport: String,
+    pub mailer_from: String,
+    pub mailer_smtp_url: String,
+    pub mailer_file_path: String,
+    pub password_argon2_memory_kib: u32,
+    pub password_argon2_iterations: u32,
+    pub password_argon2_parallelism: u32,
+    pub keyring_rotate_messages: u32,
+    pub keyring_rotate_seconds: i64,
+    pub keyring_grace_epochs: usize,
+    pub content_security_policy: String,
+    pub frame_ancestors: String,
+    pub hsts_max_age_seconds: u64,
+    pub referrer_policy: String,
+    pub permissions_policy: String,
+}
+
+const REDACTED: &str = "<redacted>";
+
+// keys, the super user and urls that may carry passwords never show up in logs
+impl fmt::Debug for ServerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerConfig")
+            .field("domain", &self.domain)
+            .field("session_cookie_name", &self.session_cookie_name)
+            .field("session_ttl_hours", &self.session_ttl_hours)
+            .field("encryption_key_emoji", &REDACTED)
+            .field("column_key_emoji", &REDACTED)
+            .field("column_key_version", &self.column_key_version)
+            .field("column_retired_keys_emoji", &REDACTED)
+            .field("blind_index_key_emoji", &REDACTED)
+            .field("jwt_private_key_path", &self.jwt_private_key_path)
+            .field("jwt_public_key_path", &self.jwt_public_key_path)
+            .field("jwt_signing_kid", &self.jwt_signing_kid)
+            .field("jwt_signing_algorithm", &self.jwt_signing_algorithm)
+            .field("jwt_verify_keys", &self.jwt_verify_keys)
+            .field("postgres_sql_connection_url", &REDACTED)
+            .field("bind_url", &self.bind_url)
+            .field("trust_proxy_headers", &self.trust_proxy_headers)
+            .field("super_user_email", &REDACTED)
+            .field("super_user_pwhash_emoji", &REDACTED)
+            .field("mailer_transport", &self.mailer_transport)
+            .field("mailer_from", &self.mailer_from)
+            .field("mailer_smtp_url", &REDACTED)
+            .field("mailer_file_path", &self.mailer_file_path)
+            .field("password_argon2_memory_kib", &self.password_argon2_memory_kib)
+            .field("password_argon2_iterations", &self.password_argon2_iterations)
+            .field("password_argon2_parallelism", &self.password_argon2_parallelism)
+            .field("keyring_rotate_messages", &self.keyring_rotate_messages)
+            .field("keyring_rotate_seconds", &self.keyring_rotate_seconds)
+            .field("keyring_grace_epochs", &self.keyring_grace_epochs)
+            .field("content_security_policy", &self.content_security_policy)
+            .field("frame_ancestors", &self.frame_ancestors)
+            .field("hsts_max_age_seconds", &self.hsts_max_age_seconds)
+            .field("referrer_policy", &self.referrer_policy)
+            .field("permissions_policy", &self.permissions_policy)
+            .finish()
+    }
 }
@@ -1,7 +1,42 @@
 use serde::{Deserialize, Serialize};
 
+use crate::permission::{Permission, Role};
+
 #[derive(Serialize, Deserialize, Default, Clone)]
 pub struct SessionUser {
+    pub uid: i32,
     pub email: String,
-    pub is_admin: bool,
+    // empty for accounts without attributes, like the super user before they set one
+    #[serde(default)]
+    pub display: String,
+    // both are looked up again on every request, what the session holds is only the login time copy
+    pub roles: Vec<Role>,
+    pub permissions: Vec<Permission>,
+}
+
+impl SessionUser {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
+
+    /// What to call the member in templates.
+    pub fn greeting_name(&self) -> &str {
+        if self.display.is_empty() {
+            &self.email
+        } else {
+            &self.display
+        }
+    }
+
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&role)
+    }
+}
+
+// password was accepted but the second factor is still outstanding
+// lives under its own session key so nothing mistakes it for a logged in user
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PendingSecondFactor {
+    pub user: SessionUser,
+    pub started_at: i64,
 }
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+// stored as json in user_attributes.settings
+// every field needs a serde default so rows written before it existed still load
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct MemberSettings {
+    pub email_notifications: bool,
+    pub media_autoplay: bool,
+    pub reduced_motion: bool,
+}
+
+impl Default for MemberSettings {
+    fn default() -> Self {
+        MemberSettings {
+            email_notifications: true,
+            media_autoplay: true,
+            reduced_motion: false,
+        }
+    }
+}
@@ -3,6 +3,13 @@ pub use sea_schema::migration::*;
 mod m01_000001_create_user_table;
 mod m01_000002_create_user_attributes_table;
 mod m01_000003_create_media_node_table;
+mod m01_000004_create_user_totp_table;
+mod m01_000005_create_role_tables;
+mod m01_000006_create_login_attempt_table;
+mod m01_000007_create_user_session_table;
+mod m01_000008_create_invite_tables;
+mod m01_000009_add_email_verified_column;
+mod m01_000010_create_blind_index_key_table;
 
 pub struct Migrator;
 
@@ -13,6 +20,13 @@ impl MigratorTrait for Migrator {
             Box::new(m01_000001_create_user_table::Migration),
             Box::new(m01_000002_create_user_attributes_table::Migration),
             Box::new(m01_000003_create_media_node_table::Migration),
+            Box::new(m01_000004_create_user_totp_table::Migration),
+            Box::new(m01_000005_create_role_tables::Migration),
+            Box::new(m01_000006_create_login_attempt_table::Migration),
+            Box::new(m01_000007_create_user_session_table::Migration),
+            Box::new(m01_000008_create_invite_tables::Migration),
+            Box::new(m01_000009_add_email_verified_column::Migration),
+            Box::new(m01_000010_create_blind_index_key_table::Migration),
         ]
     }
 }
@@ -0,0 +1,36 @@
+use sea_orm::Statement;
+use sea_schema::migration::prelude::*;
+use sea_schema::migration::sea_orm::ConnectionTrait;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m01_000004_create_user_totp_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // secret is sealed with the server key, recovery codes are stored as a json list of hashes
+        let sql = "\
+        CREATE TABLE user_totp ( \
+            id serial NOT NULL PRIMARY KEY, \
+            uid integer NOT NULL UNIQUE REFERENCES user_email_password (id), \
+            secret varchar NOT NULL, \
+            confirmed boolean NOT NULL, \
+            recovery_codes varchar NOT NULL, \
+            last_used_step bigint NOT NULL DEFAULT 0, \
+            created_at timestamp with time zone NOT NULL \
+        )";
+        let stmt = Statement::from_string(manager.get_database_backend(), sql.to_owned());
+        manager.get_connection().execute(stmt).await.map(|_| ())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let sql = "DROP TABLE user_totp";
+        let stmt = Statement::from_string(manager.get_database_backend(), sql.to_owned());
+        manager.get_connection().execute(stmt).await.map(|_| ())
+    }
+}
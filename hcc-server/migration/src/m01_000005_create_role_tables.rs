@@ -0,0 +1,81 @@
+use sea_orm::Statement;
+use sea_schema::migration::prelude::*;
+use sea_schema::migration::sea_orm::ConnectionTrait;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m01_000005_create_role_tables"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // names here must match domain::permission
+        let statements = vec![
+            "\
+            CREATE TABLE role ( \
+                id serial NOT NULL PRIMARY KEY, \
+                name varchar NOT NULL UNIQUE \
+            )",
+            "\
+            CREATE TABLE permission ( \
+                id serial NOT NULL PRIMARY KEY, \
+                name varchar NOT NULL UNIQUE \
+            )",
+            "\
+            CREATE TABLE role_permission ( \
+                role_id integer NOT NULL REFERENCES role (id), \
+                permission_id integer NOT NULL REFERENCES permission (id), \
+                PRIMARY KEY (role_id, permission_id) \
+            )",
+            "\
+            CREATE TABLE user_role ( \
+                uid integer NOT NULL REFERENCES user_email_password (id), \
+                role_id integer NOT NULL REFERENCES role (id), \
+                PRIMARY KEY (uid, role_id) \
+            )",
+            "INSERT INTO role (name) VALUES ('admin'), ('moderator'), ('patron'), ('member')",
+            "\
+            INSERT INTO permission (name) VALUES \
+                ('app.view'), ('secrets.view'), ('media.patron'), \
+                ('content.moderate'), ('admin.view'), ('users.manage')",
+            "\
+            INSERT INTO role_permission (role_id, permission_id) \
+            SELECT r.id, p.id FROM role r, permission p WHERE \
+                (r.name = 'member' AND p.name IN ('app.view', 'secrets.view')) \
+                OR (r.name = 'patron' AND p.name IN ('app.view', 'secrets.view', 'media.patron')) \
+                OR (r.name = 'moderator' AND p.name IN ('app.view', 'secrets.view', 'content.moderate')) \
+                OR (r.name = 'admin')",
+            // everyone who signed up before roles existed is a member
+            "\
+            INSERT INTO user_role (uid, role_id) \
+            SELECT u.id, r.id FROM user_email_password u, role r WHERE r.name = 'member'",
+        ];
+
+        for sql in statements {
+            let stmt = Statement::from_string(manager.get_database_backend(), sql.to_owned());
+            manager.get_connection().execute(stmt).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let statements = vec![
+            "DROP TABLE user_role",
+            "DROP TABLE role_permission",
+            "DROP TABLE permission",
+            "DROP TABLE role",
+        ];
+
+        for sql in statements {
+            let stmt = Statement::from_string(manager.get_database_backend(), sql.to_owned());
+            manager.get_connection().execute(stmt).await?;
+        }
+
+        Ok(())
+    }
+}
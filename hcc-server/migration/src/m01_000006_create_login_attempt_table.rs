@@ -0,0 +1,36 @@
+use sea_orm::Statement;
+use sea_schema::migration::prelude::*;
+use sea_schema::migration::sea_orm::ConnectionTrait;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m01_000006_create_login_attempt_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // subject is the blind indexed email for scope 'email', or the client address for scope 'ip'
+        let sql = "\
+        CREATE TABLE login_attempt ( \
+            id serial NOT NULL PRIMARY KEY, \
+            scope varchar NOT NULL, \
+            subject varchar NOT NULL, \
+            failures integer NOT NULL, \
+            last_failure timestamp with time zone NOT NULL, \
+            locked_until timestamp with time zone NULL, \
+            UNIQUE (scope, subject) \
+        )";
+        let stmt = Statement::from_string(manager.get_database_backend(), sql.to_owned());
+        manager.get_connection().execute(stmt).await.map(|_| ())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let sql = "DROP TABLE login_attempt";
+        let stmt = Statement::from_string(manager.get_database_backend(), sql.to_owned());
+        manager.get_connection().execute(stmt).await.map(|_| ())
+    }
+}
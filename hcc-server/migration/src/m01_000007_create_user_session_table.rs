@@ -0,0 +1,36 @@
+use sea_orm::Statement;
+use sea_schema::migration::prelude::*;
+use sea_schema::migration::sea_orm::ConnectionTrait;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m01_000007_create_user_session_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // session_id matches async_sessions.id, which the session store creates on its own so it can't be a foreign key
+        let sql = "\
+        CREATE TABLE user_session ( \
+            id serial NOT NULL PRIMARY KEY, \
+            uid integer NOT NULL REFERENCES user_email_password (id), \
+            session_id varchar NOT NULL UNIQUE, \
+            user_agent varchar NOT NULL, \
+            ip varchar NOT NULL, \
+            created_at timestamp with time zone NOT NULL, \
+            last_seen timestamp with time zone NOT NULL \
+        )";
+        let stmt = Statement::from_string(manager.get_database_backend(), sql.to_owned());
+        manager.get_connection().execute(stmt).await.map(|_| ())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let sql = "DROP TABLE user_session";
+        let stmt = Statement::from_string(manager.get_database_backend(), sql.to_owned());
+        manager.get_connection().execute(stmt).await.map(|_| ())
+    }
+}
@@ -0,0 +1,62 @@
+use sea_orm::Statement;
+use sea_schema::migration::prelude::*;
+use sea_schema::migration::sea_orm::ConnectionTrait;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m01_000008_create_invite_tables"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // role is the domain::permission::Role name the invitee is signed up with
+        // max_uses and expires_at are optional, null means unlimited
+        let statements = vec![
+            "\
+            CREATE TABLE invite ( \
+                id serial NOT NULL PRIMARY KEY, \
+                inviter_uid integer NOT NULL REFERENCES user_email_password (id), \
+                role varchar NOT NULL, \
+                max_uses integer NULL, \
+                uses integer NOT NULL DEFAULT 0, \
+                expires_at timestamp with time zone NULL, \
+                created_at timestamp with time zone NOT NULL \
+            )",
+            "\
+            CREATE TABLE invite_redemption ( \
+                id serial NOT NULL PRIMARY KEY, \
+                invite_id integer NOT NULL REFERENCES invite (id), \
+                invitee_uid integer NOT NULL UNIQUE REFERENCES user_email_password (id), \
+                redeemed_at timestamp with time zone NOT NULL \
+            )",
+            // how many invites a non-admin member may create
+            "ALTER TABLE user_attributes ADD COLUMN invite_quota integer NOT NULL DEFAULT 0",
+        ];
+
+        for sql in statements {
+            let stmt = Statement::from_string(manager.get_database_backend(), sql.to_owned());
+            manager.get_connection().execute(stmt).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let statements = vec![
+            "ALTER TABLE user_attributes DROP COLUMN invite_quota",
+            "DROP TABLE invite_redemption",
+            "DROP TABLE invite",
+        ];
+
+        for sql in statements {
+            let stmt = Statement::from_string(manager.get_database_backend(), sql.to_owned());
+            manager.get_connection().execute(stmt).await?;
+        }
+
+        Ok(())
+    }
+}
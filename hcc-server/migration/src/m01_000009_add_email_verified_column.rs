@@ -0,0 +1,37 @@
+use sea_orm::Statement;
+use sea_schema::migration::prelude::*;
+use sea_schema::migration::sea_orm::ConnectionTrait;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m01_000009_add_email_verified_column"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // active can also mean deactivated by the member or suspended by an admin,
+        // so whether the email link came back is tracked on its own
+        let statements = vec![
+            "ALTER TABLE user_email_password ADD COLUMN email_verified_at timestamp with time zone NULL",
+            // anyone who got active before this column existed came in through the link or is the super user
+            "UPDATE user_email_password SET email_verified_at = now() WHERE active",
+        ];
+
+        for sql in statements {
+            let stmt = Statement::from_string(manager.get_database_backend(), sql.to_owned());
+            manager.get_connection().execute(stmt).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let sql = "ALTER TABLE user_email_password DROP COLUMN email_verified_at";
+        let stmt = Statement::from_string(manager.get_database_backend(), sql.to_owned());
+        manager.get_connection().execute(stmt).await.map(|_| ())
+    }
+}
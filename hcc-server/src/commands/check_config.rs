@@ -0,0 +1,12 @@
+use crate::util::config_check;
+
+pub const NAME: &str = "check-config";
+
+/// Runs the same checks the server starts with and lists every problem, without starting it.
+pub async fn run() -> tide::Result<()> {
+    // the problems go back through main, which writes them to stderr and exits with 1
+    config_check::checked_config().await?;
+
+    println!("configuration OK");
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use std::io::Write;
+
+pub const NAME: &str = "generate-keys";
+
+// SubjectPublicKeyInfo headers, the raw public key follows as the bit string contents
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+const P256_SPKI_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
+/// Writes a new JWT signing keypair as `<dir>/<kid>.key` (pkcs8) and `<dir>/<kid>.key.pub` (spki).
+/// Runs before the config is loaded, so a fresh install can make its first key with it.
+///
+/// RS256 keys come from openssl, for those it prints the commands instead.
+/// Existing files are never overwritten and the private key is only readable by its owner.
+///
+/// `hcc-server generate-keys <EdDSA|ES256|RS256> <kid> [dir]`
+pub fn run(args: &[String]) -> tide::Result<()> {
+    let (algorithm, kid) = match args {
+        [algorithm, kid, ..] => (algorithm.as_str(), kid.as_str()),
+        _ => {
+            println!("usage: hcc-server {} <EdDSA|ES256|RS256> <kid> [dir]", NAME);
+            return Ok(());
+        }
+    };
+    let dir = args.get(2).map(String::as_str).unwrap_or(".");
+
+    let key_path = format!("{}/{}.key", dir, kid);
+    let pubkey_path = format!("{}/{}.key.pub", dir, kid);
+
+    let rng = SystemRandom::new();
+    let (private_der, public_der) = match algorithm {
+        "EdDSA" => {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).expect("generate ed25519 key");
+            let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).expect("read back ed25519 key");
+            (pkcs8.as_ref().to_vec(), [&ED25519_SPKI_PREFIX[..], pair.public_key().as_ref()].concat())
+        }
+        "ES256" => {
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                .expect("generate p-256 key");
+            let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref())
+                .expect("read back p-256 key");
+            (pkcs8.as_ref().to_vec(), [&P256_SPKI_PREFIX[..], pair.public_key().as_ref()].concat())
+        }
+        "RS256" => {
+            // ring only signs with rsa keys, it can't make them
+            println!("openssl genrsa -out {} 4096", key_path);
+            println!("openssl rsa -in {} -pubout -out {}", key_path, pubkey_path);
+            return Ok(());
+        }
+        unknown => {
+            return Err(tide::Error::from_str(
+                400,
+                format!(
+                    "Unknown algorithm: {}, expected EdDSA, ES256 or RS256",
+                    unknown
+                ),
+            ))
+        }
+    };
+
+    let private_pem = pem::encode(&pem::Pem {
+        tag: String::from("PRIVATE KEY"),
+        contents: private_der,
+    });
+    let public_pem = pem::encode(&pem::Pem {
+        tag: String::from("PUBLIC KEY"),
+        contents: public_der,
+    });
+
+    write_new(&key_path, private_pem.as_bytes(), 0o600)?;
+    write_new(&pubkey_path, public_pem.as_bytes(), 0o644)?;
+
+    println!("HCC_JWT_SIGNING_KID={}", kid);
+    println!("HCC_JWT_SIGNING_ALG={}", algorithm);
+    println!("HCC_JWT_PRIVATE_KEY_PATH={}", key_path);
+    println!("HCC_JWT_PUBLIC_KEY_PATH={}", pubkey_path);
+
+    Ok(())
+}
+
+// fails instead of replacing a key that is already there, the mode applies from the moment the file exists
+fn write_new(path: &str, contents: &[u8], mode: u32) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    options.open(path)?.write_all(contents)
+}
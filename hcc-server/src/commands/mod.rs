@@ -0,0 +1,4 @@
+// one-off maintenance run instead of the server: `hcc-server <command>`
+pub mod check_config;
+pub mod generate_keys;
+pub mod reindex_emails;
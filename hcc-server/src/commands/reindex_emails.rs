@@ -0,0 +1,22 @@
+use crate::dao::user::UserDao;
+use crate::wiring::ServerWiring;
+
+pub const NAME: &str = "reindex-emails";
+
+/// Moves stored emails onto randomized encryption plus the keyed blind index.
+/// Run it once after upgrading, and again whenever HCC_BLIND_INDEX_KEY_EMOJI changes.
+pub async fn run(wiring: &ServerWiring) -> tide::Result<()> {
+    let report = UserDao::reindex_emails(wiring).await?;
+
+    println!("re-encrypted and re-indexed {} member emails", report.members);
+
+    if report.unreadable > 0 {
+        println!(
+            "{} emails could not be opened with HCC_ENCRYPTION_KEY_EMOJI and were left alone, \
+             the server won't start with the new index key until they are fixed",
+            report.unreadable
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use crate::dao::user_attributes::UserAttributesDao;
+use crate::wiring::ServerWiring;
+
+use domain::permission::Role;
+use domain::sea_orm::entities::prelude::{Invite, InviteRedemption, UserAttributes};
+use domain::sea_orm::entities::{invite, invite_redemption, user_attributes};
+
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::sea_query::Expr;
+use sea_orm::*;
+
+/// Who brought in whom, for the admin console.
+pub struct InviteLineage {
+    pub inviter_display: String,
+    pub invitee_display: String,
+    pub role: String,
+    pub redeemed_at: DateTimeWithTimeZone,
+}
+
+pub struct InviteDao {}
+
+impl InviteDao {
+    pub async fn create(
+        wiring: &ServerWiring,
+        inviter_uid: i32,
+        role: Role,
+        max_uses: Option<i32>,
+        expires_at: Option<DateTimeWithTimeZone>,
+    ) -> Result<invite::Model, DbErr> {
+        invite::ActiveModel {
+            inviter_uid: Set(inviter_uid),
+            role: Set(String::from(role.name())),
+            max_uses: Set(max_uses),
+            uses: Set(0),
+            expires_at: Set(expires_at),
+            created_at: Set(chrono::Utc::now().into()),
+            ..Default::default()
+        }
+        .insert(&wiring.db)
+        .await
+    }
+
+    pub async fn find_by_id(wiring: &ServerWiring, id: i32) -> Result<Option<invite::Model>, DbErr> {
+        Invite::find_by_id(id).one(&wiring.db).await
+    }
+
+    pub async fn list_by_inviter(
+        wiring: &ServerWiring,
+        inviter_uid: i32,
+    ) -> Result<Vec<invite::Model>, DbErr> {
+        Invite::find()
+            .filter(invite::Column::InviterUid.eq(inviter_uid))
+            .order_by_desc(invite::Column::CreatedAt)
+            .all(&wiring.db)
+            .await
+    }
+
+    /// Invites that can still bring someone in, used up and expired ones no longer count against a quota.
+    pub async fn count_live_by_inviter(
+        wiring: &ServerWiring,
+        inviter_uid: i32,
+    ) -> Result<usize, DbErr> {
+        let now: DateTimeWithTimeZone = chrono::Utc::now().into();
+
+        Invite::find()
+            .filter(invite::Column::InviterUid.eq(inviter_uid))
+            .filter(
+                Condition::any()
+                    .add(invite::Column::MaxUses.is_null())
+                    .add(Expr::cust("invite.uses < invite.max_uses")),
+            )
+            .filter(
+                Condition::any()
+                    .add(invite::Column::ExpiresAt.is_null())
+                    .add(invite::Column::ExpiresAt.gt(now)),
+            )
+            .count(&wiring.db)
+            .await
+    }
+
+    pub fn is_redeemable(found: &invite::Model) -> bool {
+        let now: DateTimeWithTimeZone = chrono::Utc::now().into();
+
+        let has_uses = found.max_uses.map(|max| found.uses < max).unwrap_or(true);
+        let is_current = found.expires_at.map(|until| until > now).unwrap_or(true);
+
+        has_uses && is_current
+    }
+
+    /// Spends one use of the invite on the new member, false when it was used up or expired meanwhile.
+    pub async fn redeem<C: ConnectionTrait>(
+        db: &C,
+        invite_id: i32,
+        invitee_uid: i32,
+    ) -> Result<bool, DbErr> {
+        // the guarded update keeps two signups racing for the last use from both getting in
+        let sql = "\
+            UPDATE invite SET uses = uses + 1 \
+            WHERE id = $1 \
+            AND (max_uses IS NULL OR uses < max_uses) \
+            AND (expires_at IS NULL OR expires_at > now())";
+
+        let updated = db
+            .execute(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                sql,
+                vec![invite_id.into()],
+            ))
+            .await?;
+
+        if updated.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        invite_redemption::ActiveModel {
+            invite_id: Set(invite_id),
+            invitee_uid: Set(invitee_uid),
+            redeemed_at: Set(chrono::Utc::now().into()),
+            ..Default::default()
+        }
+        .insert(db)
+        .await?;
+
+        Ok(true)
+    }
+
+    /// The invite a member joined with, if they joined with one.
+    pub async fn find_redeemed_by(
+        wiring: &ServerWiring,
+        invitee_uid: i32,
+    ) -> Result<Option<(invite_redemption::Model, invite::Model)>, DbErr> {
+        let found = InviteRedemption::find()
+            .filter(invite_redemption::Column::InviteeUid.eq(invitee_uid))
+            .find_also_related(Invite)
+            .one(&wiring.db)
+            .await?;
+
+        Ok(found.and_then(|(redemption, invite)| invite.map(|invite| (redemption, invite))))
+    }
+
+    /// How many invites a member may create, the super user has no attributes row and gets none.
+    pub async fn quota_for(wiring: &ServerWiring, uid: i32) -> Result<i32, DbErr> {
+        let found = UserAttributesDao::find_by_uid(wiring, uid).await?;
+
+        Ok(found.map(|attributes| attributes.invite_quota).unwrap_or(0))
+    }
+
+    /// False when the member has no attributes row to hold a quota.
+    pub async fn set_quota(wiring: &ServerWiring, uid: i32, quota: i32) -> Result<bool, DbErr> {
+        let updated = UserAttributes::update_many()
+            .col_expr(user_attributes::Column::InviteQuota, Expr::value(quota))
+            .filter(user_attributes::Column::Uid.eq(uid))
+            .exec(&wiring.db)
+            .await?;
+
+        Ok(updated.rows_affected > 0)
+    }
+
+    pub async fn lineage(wiring: &ServerWiring) -> Result<Vec<InviteLineage>, DbErr> {
+        let sql = "\
+            SELECT COALESCE(inviter.display, 'uid ' || i.inviter_uid) AS inviter_display, \
+                COALESCE(invitee.display, 'uid ' || ir.invitee_uid) AS invitee_display, \
+                i.role, ir.redeemed_at \
+            FROM invite_redemption ir \
+            JOIN invite i ON i.id = ir.invite_id \
+            LEFT JOIN user_attributes inviter ON inviter.uid = i.inviter_uid \
+            LEFT JOIN user_attributes invitee ON invitee.uid = ir.invitee_uid \
+            ORDER BY ir.redeemed_at DESC";
+
+        let rows = wiring
+            .db
+            .query_all(Statement::from_string(DbBackend::Postgres, sql.to_owned()))
+            .await?;
+
+        let mut lineage = vec![];
+        for row in rows {
+            lineage.push(InviteLineage {
+                inviter_display: row.try_get("", "inviter_display")?,
+                invitee_display: row.try_get("", "invitee_display")?,
+                role: row.try_get("", "role")?,
+                redeemed_at: row.try_get("", "redeemed_at")?,
+            });
+        }
+        Ok(lineage)
+    }
+}
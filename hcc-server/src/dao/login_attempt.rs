@@ -0,0 +1,124 @@
+use crate::util::backoff::{LoginBackoff, FAILURE_WINDOW_SECONDS};
+use crate::wiring::ServerWiring;
+
+use domain::sea_orm::entities::login_attempt;
+use domain::sea_orm::entities::prelude::LoginAttempt;
+
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::sea_query::Expr;
+use sea_orm::*;
+
+pub const SCOPE_EMAIL: &str = "email";
+pub const SCOPE_ADDRESS: &str = "ip";
+
+pub struct LoginAttemptDao {}
+
+impl LoginAttemptDao {
+    pub async fn find(
+        wiring: &ServerWiring,
+        scope: &str,
+        subject: &str,
+    ) -> Result<Option<login_attempt::Model>, DbErr> {
+        LoginAttempt::find()
+            .filter(login_attempt::Column::Scope.eq(scope))
+            .filter(login_attempt::Column::Subject.eq(subject))
+            .one(&wiring.db)
+            .await
+    }
+
+    /// Seconds left on a lockout, if the subject is currently locked out.
+    pub async fn locked_for(
+        wiring: &ServerWiring,
+        scope: &str,
+        subject: &str,
+    ) -> Result<Option<i64>, DbErr> {
+        let now: DateTimeWithTimeZone = chrono::Utc::now().into();
+
+        let found = Self::find(wiring, scope, subject).await?;
+
+        Ok(found
+            .and_then(|attempt| attempt.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| (until - now).num_seconds().max(1)))
+    }
+
+    pub async fn record_failure(
+        wiring: &ServerWiring,
+        scope: &str,
+        subject: &str,
+        backoff: &LoginBackoff,
+    ) -> Result<login_attempt::Model, DbErr> {
+        // counted in one statement, parallel guesses can't read the same count and both write count + 1
+        let sql = "\
+            INSERT INTO login_attempt (scope, subject, failures, last_failure) \
+            VALUES ($1, $2, 1, now()) \
+            ON CONFLICT (scope, subject) DO UPDATE SET \
+                failures = CASE \
+                    WHEN login_attempt.last_failure < now() - $3 * interval '1 second' THEN 1 \
+                    ELSE login_attempt.failures + 1 \
+                END, \
+                last_failure = now() \
+            RETURNING *";
+
+        let attempt = LoginAttempt::find()
+            .from_raw_sql(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                sql,
+                vec![scope.into(), subject.into(), FAILURE_WINDOW_SECONDS.into()],
+            ))
+            .one(&wiring.db)
+            .await?
+            .ok_or_else(|| DbErr::Custom(String::from("login attempt upsert returned no row")))?;
+
+        let lockout = backoff.lockout_seconds(attempt.failures);
+        if lockout == 0 {
+            return Ok(attempt);
+        }
+
+        // only ever pushed further out, a slower request with a lower count can't shorten it
+        let until: DateTimeWithTimeZone =
+            (chrono::Utc::now() + chrono::Duration::seconds(lockout)).into();
+        LoginAttempt::update_many()
+            .col_expr(login_attempt::Column::LockedUntil, Expr::value(until))
+            .filter(login_attempt::Column::Id.eq(attempt.id))
+            .filter(
+                Condition::any()
+                    .add(login_attempt::Column::LockedUntil.is_null())
+                    .add(login_attempt::Column::LockedUntil.lt(until)),
+            )
+            .exec(&wiring.db)
+            .await?;
+
+        Ok(login_attempt::Model {
+            locked_until: Some(until),
+            ..attempt
+        })
+    }
+
+    pub async fn clear(wiring: &ServerWiring, scope: &str, subject: &str) -> Result<(), DbErr> {
+        LoginAttempt::delete_many()
+            .filter(login_attempt::Column::Scope.eq(scope))
+            .filter(login_attempt::Column::Subject.eq(subject))
+            .exec(&wiring.db)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn clear_by_id(wiring: &ServerWiring, id: i32) -> Result<(), DbErr> {
+        LoginAttempt::delete_many()
+            .filter(login_attempt::Column::Id.eq(id))
+            .exec(&wiring.db)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn list_locked(wiring: &ServerWiring) -> Result<Vec<login_attempt::Model>, DbErr> {
+        let now: DateTimeWithTimeZone = chrono::Utc::now().into();
+
+        LoginAttempt::find()
+            .filter(login_attempt::Column::LockedUntil.gt(now))
+            .order_by_desc(login_attempt::Column::LastFailure)
+            .all(&wiring.db)
+            .await
+    }
+}
@@ -1,2 +1,8 @@
+pub mod invite;
+pub mod login_attempt;
 pub mod media_node;
+pub mod role;
+pub mod session;
+pub mod totp;
 pub mod user;
+pub mod user_attributes;
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use crate::wiring::ServerWiring;
+
+use domain::permission::{Permission, Role};
+use domain::sea_orm::entities::prelude::{Role as RoleEntity, UserRole};
+use domain::sea_orm::entities::{role, user_role};
+
+use sea_orm::*;
+
+pub struct RoleDao {}
+
+impl RoleDao {
+    pub async fn roles_for_user(wiring: &ServerWiring, uid: i32) -> Result<Vec<Role>, DbErr> {
+        let sql = "\
+            SELECT r.name FROM user_role ur \
+            JOIN role r ON r.id = ur.role_id \
+            WHERE ur.uid = $1";
+
+        let rows = wiring
+            .db
+            .query_all(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                sql,
+                vec![uid.into()],
+            ))
+            .await?;
+
+        let mut roles = vec![];
+        for row in rows {
+            let name: String = row.try_get("", "name")?;
+            if let Some(role) = Role::from_name(&name) {
+                roles.push(role);
+            }
+        }
+        Ok(roles)
+    }
+
+    /// The roles of many members in one query, members without roles are left out.
+    pub async fn roles_for_users(
+        wiring: &ServerWiring,
+        uids: &[i32],
+    ) -> Result<HashMap<i32, Vec<Role>>, DbErr> {
+        let found = UserRole::find()
+            .find_also_related(RoleEntity)
+            .filter(user_role::Column::Uid.is_in(uids.to_vec()))
+            .all(&wiring.db)
+            .await?;
+
+        let mut roles: HashMap<i32, Vec<Role>> = HashMap::new();
+        for (user_role, role) in found {
+            if let Some(role) = role.and_then(|r| Role::from_name(&r.name)) {
+                roles.entry(user_role.uid).or_default().push(role);
+            }
+        }
+        Ok(roles)
+    }
+
+    pub async fn permissions_for_user(
+        wiring: &ServerWiring,
+        uid: i32,
+    ) -> Result<Vec<Permission>, DbErr> {
+        let sql = "\
+            SELECT DISTINCT p.name FROM user_role ur \
+            JOIN role_permission rp ON rp.role_id = ur.role_id \
+            JOIN permission p ON p.id = rp.permission_id \
+            WHERE ur.uid = $1";
+
+        let rows = wiring
+            .db
+            .query_all(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                sql,
+                vec![uid.into()],
+            ))
+            .await?;
+
+        let mut permissions = vec![];
+        for row in rows {
+            let name: String = row.try_get("", "name")?;
+            match Permission::from_name(&name) {
+                Some(permission) => permissions.push(permission),
+                None => tide::log::warn!("Ignoring unknown permission: {}", name),
+            }
+        }
+        Ok(permissions)
+    }
+
+    pub async fn assign_role<C: ConnectionTrait>(db: &C, uid: i32, role: Role) -> Result<(), DbErr> {
+        let found = RoleEntity::find()
+            .filter(role::Column::Name.eq(role.name()))
+            .one(db)
+            .await?
+            .ok_or(DbErr::RecordNotFound(String::from(role.name())))?;
+
+        let already = UserRole::find_by_id((uid, found.id)).one(db).await?;
+
+        if already.is_none() {
+            user_role::ActiveModel {
+                uid: Set(uid),
+                role_id: Set(found.id),
+            }
+            .insert(db)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn revoke_role<C: ConnectionTrait>(db: &C, uid: i32, role: Role) -> Result<(), DbErr> {
+        let found = RoleEntity::find()
+            .filter(role::Column::Name.eq(role.name()))
+            .one(db)
+            .await?;
+
+        if let Some(found) = found {
+            UserRole::delete_many()
+                .filter(user_role::Column::Uid.eq(uid))
+                .filter(user_role::Column::RoleId.eq(found.id))
+                .exec(db)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
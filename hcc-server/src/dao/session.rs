@@ -0,0 +1,160 @@
+use crate::wiring::ServerWiring;
+
+use domain::sea_orm::entities::async_sessions;
+use domain::sea_orm::entities::prelude::{AsyncSessions, UserSession};
+use domain::sea_orm::entities::user_session;
+
+use sea_orm::*;
+
+// last_seen is only written when it is older than this, so browsing doesn't write on every request
+const TOUCH_INTERVAL_SECONDS: i64 = 60;
+
+pub struct SessionDao {}
+
+impl SessionDao {
+    pub async fn record(
+        wiring: &ServerWiring,
+        uid: i32,
+        session_id: &str,
+        user_agent: &str,
+        ip: &str,
+    ) -> Result<user_session::Model, DbErr> {
+        let now: sea_orm::prelude::DateTimeWithTimeZone = chrono::Utc::now().into();
+
+        // a login on an existing anonymous session takes over any previous record for it
+        UserSession::delete_many()
+            .filter(user_session::Column::SessionId.eq(session_id))
+            .exec(&wiring.db)
+            .await?;
+
+        user_session::ActiveModel {
+            uid: Set(uid),
+            session_id: Set(String::from(session_id)),
+            user_agent: Set(String::from(user_agent)),
+            ip: Set(String::from(ip)),
+            created_at: Set(now),
+            last_seen: Set(now),
+            ..Default::default()
+        }
+        .insert(&wiring.db)
+        .await
+    }
+
+    pub async fn find_by_session_id(
+        wiring: &ServerWiring,
+        session_id: &str,
+    ) -> Result<Option<user_session::Model>, DbErr> {
+        UserSession::find()
+            .filter(user_session::Column::SessionId.eq(session_id))
+            .one(&wiring.db)
+            .await
+    }
+
+    pub async fn touch(
+        wiring: &ServerWiring,
+        found: user_session::Model,
+        ip: &str,
+    ) -> Result<(), DbErr> {
+        let now: sea_orm::prelude::DateTimeWithTimeZone = chrono::Utc::now().into();
+
+        if (now - found.last_seen).num_seconds() < TOUCH_INTERVAL_SECONDS && found.ip == ip {
+            return Ok(());
+        }
+
+        let mut model: user_session::ActiveModel = found.into();
+        model.last_seen = Set(now);
+        model.ip = Set(String::from(ip));
+        model.update(&wiring.db).await.map(|_| ())
+    }
+
+    pub async fn list_for_users(
+        wiring: &ServerWiring,
+        uids: &[i32],
+    ) -> Result<Vec<user_session::Model>, DbErr> {
+        UserSession::find()
+            .filter(user_session::Column::Uid.is_in(uids.to_vec()))
+            .all(&wiring.db)
+            .await
+    }
+
+    pub async fn list_for_user(
+        wiring: &ServerWiring,
+        uid: i32,
+    ) -> Result<Vec<user_session::Model>, DbErr> {
+        UserSession::find()
+            .filter(user_session::Column::Uid.eq(uid))
+            .order_by_desc(user_session::Column::LastSeen)
+            .all(&wiring.db)
+            .await
+    }
+
+    /// Revokes one of the user's sessions, returns false when it isn't theirs.
+    pub async fn revoke(wiring: &ServerWiring, uid: i32, id: i32) -> Result<bool, DbErr> {
+        let found = UserSession::find()
+            .filter(user_session::Column::Id.eq(id))
+            .filter(user_session::Column::Uid.eq(uid))
+            .all(&wiring.db)
+            .await?;
+
+        let revoked = !found.is_empty();
+        Self::destroy(wiring, found).await?;
+        Ok(revoked)
+    }
+
+    pub async fn revoke_others(
+        wiring: &ServerWiring,
+        uid: i32,
+        keep_session_id: &str,
+    ) -> Result<usize, DbErr> {
+        let found = UserSession::find()
+            .filter(user_session::Column::Uid.eq(uid))
+            .filter(user_session::Column::SessionId.ne(keep_session_id))
+            .all(&wiring.db)
+            .await?;
+
+        let count = found.len();
+        Self::destroy(wiring, found).await?;
+        Ok(count)
+    }
+
+    pub async fn revoke_all(wiring: &ServerWiring, uid: i32) -> Result<usize, DbErr> {
+        let found = Self::list_for_user(wiring, uid).await?;
+
+        let count = found.len();
+        Self::destroy(wiring, found).await?;
+        Ok(count)
+    }
+
+    /// Forgets a session that was ended from the browser it belongs to.
+    pub async fn forget(wiring: &ServerWiring, session_id: &str) -> Result<(), DbErr> {
+        UserSession::delete_many()
+            .filter(user_session::Column::SessionId.eq(session_id))
+            .exec(&wiring.db)
+            .await
+            .map(|_| ())
+    }
+
+    async fn destroy(wiring: &ServerWiring, found: Vec<user_session::Model>) -> Result<(), DbErr> {
+        if found.is_empty() {
+            return Ok(());
+        }
+
+        let ids: Vec<i32> = found.iter().map(|s| s.id).collect();
+        let session_ids: Vec<String> = found.into_iter().map(|s| s.session_id).collect();
+
+        // dropping the stored session is what actually logs the browser out
+        let txn = wiring.db.begin().await?;
+
+        AsyncSessions::delete_many()
+            .filter(async_sessions::Column::Id.is_in(session_ids))
+            .exec(&txn)
+            .await?;
+
+        UserSession::delete_many()
+            .filter(user_session::Column::Id.is_in(ids))
+            .exec(&txn)
+            .await?;
+
+        txn.commit().await
+    }
+}
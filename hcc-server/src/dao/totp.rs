@@ -0,0 +1,139 @@
+use crate::wiring::ServerWiring;
+
+use domain::sea_orm::entities::prelude::UserTotp;
+use domain::sea_orm::entities::user_totp;
+
+use sea_orm::*;
+
+pub struct TotpDao {}
+
+impl TotpDao {
+    pub async fn find_by_uid(
+        wiring: &ServerWiring,
+        uid: i32,
+    ) -> Result<Option<user_totp::Model>, DbErr> {
+        UserTotp::find()
+            .filter(user_totp::Column::Uid.eq(uid))
+            .limit(1)
+            .one(&wiring.db)
+            .await
+    }
+
+    pub async fn is_enabled(wiring: &ServerWiring, uid: i32) -> Result<bool, DbErr> {
+        let found = Self::find_by_uid(wiring, uid).await?;
+        Ok(found.map(|t| t.confirmed).unwrap_or(false))
+    }
+
+    /// Which of `uids` have a confirmed second factor.
+    pub async fn enabled_among(wiring: &ServerWiring, uids: &[i32]) -> Result<Vec<i32>, DbErr> {
+        let found = UserTotp::find()
+            .filter(user_totp::Column::Uid.is_in(uids.to_vec()))
+            .filter(user_totp::Column::Confirmed.eq(true))
+            .all(&wiring.db)
+            .await?;
+
+        Ok(found.into_iter().map(|t| t.uid).collect())
+    }
+
+    pub async fn start_enrollment(
+        wiring: &ServerWiring,
+        uid: i32,
+        sealed_secret: &str,
+    ) -> Result<(), DbErr> {
+        // an unconfirmed enrollment is simply replaced when the member starts over
+        UserTotp::delete_many()
+            .filter(user_totp::Column::Uid.eq(uid))
+            .filter(user_totp::Column::Confirmed.eq(false))
+            .exec(&wiring.db)
+            .await?;
+
+        user_totp::ActiveModel {
+            uid: Set(uid),
+            secret: Set(String::from(sealed_secret)),
+            confirmed: Set(false),
+            recovery_codes: Set(String::from("[]")),
+            last_used_step: Set(0),
+            created_at: Set(chrono::Utc::now().into()),
+            ..Default::default()
+        }
+        .insert(&wiring.db)
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn confirm(
+        wiring: &ServerWiring,
+        id: i32,
+        used_step: i64,
+        recovery_codes_json: &str,
+    ) -> Result<(), DbErr> {
+        user_totp::ActiveModel {
+            id: Set(id),
+            confirmed: Set(true),
+            last_used_step: Set(used_step),
+            recovery_codes: Set(String::from(recovery_codes_json)),
+            ..Default::default()
+        }
+        .update(&wiring.db)
+        .await
+        .map(|_| ())
+    }
+
+    /// Spends an authenticator time step, false when a parallel login already used it (or a later one).
+    pub async fn spend_step(wiring: &ServerWiring, id: i32, used_step: i64) -> Result<bool, DbErr> {
+        let sql = "\
+            UPDATE user_totp SET last_used_step = $2 \
+            WHERE id = $1 AND last_used_step < $2";
+
+        Self::guarded_update(wiring, sql, vec![id.into(), used_step.into()]).await
+    }
+
+    /// Swaps in the remaining recovery codes, false when they changed since `read_codes_json` was read
+    /// so the same code can't get two logins in.
+    pub async fn spend_recovery_code(
+        wiring: &ServerWiring,
+        id: i32,
+        read_codes_json: &str,
+        remaining_codes_json: &str,
+    ) -> Result<bool, DbErr> {
+        let sql = "\
+            UPDATE user_totp SET recovery_codes = $3 \
+            WHERE id = $1 AND recovery_codes = $2";
+
+        Self::guarded_update(
+            wiring,
+            sql,
+            vec![
+                id.into(),
+                read_codes_json.into(),
+                remaining_codes_json.into(),
+            ],
+        )
+        .await
+    }
+
+    async fn guarded_update(
+        wiring: &ServerWiring,
+        sql: &str,
+        values: Vec<Value>,
+    ) -> Result<bool, DbErr> {
+        let updated = wiring
+            .db
+            .execute(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                sql,
+                values,
+            ))
+            .await?;
+
+        Ok(updated.rows_affected() == 1)
+    }
+
+    pub async fn delete_for_user(wiring: &ServerWiring, uid: i32) -> Result<(), DbErr> {
+        UserTotp::delete_many()
+            .filter(user_totp::Column::Uid.eq(uid))
+            .exec(&wiring.db)
+            .await
+            .map(|_| ())
+    }
+}
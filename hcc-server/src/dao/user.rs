@@ -1,9 +1,13 @@
+use crate::dao::invite::InviteDao;
+use crate::dao::login_attempt::SCOPE_EMAIL;
+use crate::dao::role::RoleDao;
 use crate::util::encryption;
 use crate::wiring::ServerWiring;
+use domain::permission::Role;
 use domain::server_config::ServerConfig;
 
-use domain::sea_orm::entities::prelude::UserEmailPassword;
-use domain::sea_orm::entities::user_email_password;
+use domain::sea_orm::entities::prelude::{BlindIndexKey, LoginAttempt, UserAttributes, UserEmailPassword};
+use domain::sea_orm::entities::{login_attempt, user_attributes, user_email_password};
 
 use sea_orm::*;
 
@@ -13,28 +17,42 @@ impl UserDao {
     pub async fn find_by_email(
         wiring: &ServerWiring,
         email_plaintext_bytes: &[u8],
-    ) -> Result<Option<user_email_password::Model>, ()> {
-        let email = encryption::DeterministicEmojiEncrypt::new(
-            &wiring.config.encryption_key_emoji,
-            &wiring.config.encryption_salt_emoji,
-            email_plaintext_bytes,
-        )
-        .unwrap();
+    ) -> Result<Option<user_email_password::Model>, DbErr> {
+        let index = Self::email_index(&wiring.config, email_plaintext_bytes);
 
-        let matches_email = user_email_password::Column::Email.eq(email.encrypted);
+        let matches_email = user_email_password::Column::EmailHash.eq(index);
 
-        let res = UserEmailPassword::find()
+        UserEmailPassword::find()
             .filter(matches_email)
             .limit(1)
             .one(&wiring.db)
-            .await;
+            .await
+    }
 
-        if res.is_ok() {
-            let i = res.unwrap();
-            Ok(i)
-        } else {
-            Err(())
-        }
+    /// The keyed blind index we look members up by, login attempts are tracked against it too.
+    pub fn email_index(config: &ServerConfig, email_plaintext_bytes: &[u8]) -> String {
+        encryption::blind_index(&config.blind_index_key_emoji, email_plaintext_bytes)
+            .expect("Invalid configuration: HCC_BLIND_INDEX_KEY_EMOJI is not a usable key")
+    }
+
+    // the stored email is sealed with a random nonce, equal emails never share a ciphertext
+    fn email_columns(
+        config: &ServerConfig,
+        email_plaintext_bytes: &[u8],
+    ) -> Result<(String, String), DbErr> {
+        let encrypted = encryption::seal_with_key_emoji(&config.encryption_key_emoji, email_plaintext_bytes)
+            .map_err(|_| DbErr::Custom(String::from("unable to encrypt email")))?;
+        Ok((encrypted, Self::email_index(config, email_plaintext_bytes)))
+    }
+
+    pub async fn find_by_email_index(
+        wiring: &ServerWiring,
+        index: &str,
+    ) -> Result<Option<user_email_password::Model>, DbErr> {
+        UserEmailPassword::find()
+            .filter(user_email_password::Column::EmailHash.eq(index))
+            .one(&wiring.db)
+            .await
     }
 
     pub async fn insert_super_user(config: &ServerConfig, wiring: &ServerWiring) -> Result<(), ()> {
@@ -44,18 +62,14 @@ impl UserDao {
 
         if already_exists.is_some() {
             tide::log::info!("super user already exists!");
+            let existing = already_exists.unwrap();
+            RoleDao::assign_role(&wiring.db, existing.id, Role::Admin)
+                .await
+                .unwrap();
         } else {
             tide::log::info!("super user does not exist!");
 
-            let em = encryption::DeterministicEmojiEncrypt::new(
-                &config.encryption_key_emoji,
-                &config.encryption_salt_emoji,
-                plaintext_login,
-            )
-            .unwrap();
-
-            let encrypted_email = String::from(em.encrypted.clone());
-            let email_hash = String::from(em.encrypted.clone());
+            let (encrypted_email, email_hash) = Self::email_columns(config, plaintext_login).unwrap();
             let encoded_hash = String::from(config.super_user_pwhash_emoji.clone());
 
             let s = user_email_password::ActiveModel {
@@ -63,14 +77,18 @@ impl UserDao {
                 email_hash: Set(email_hash),
                 password: Set(encoded_hash),
                 active: Set(true),
+                email_verified_at: Set(Some(chrono::Utc::now().into())),
                 ..Default::default()
             };
 
             let operation = UserEmailPassword::insert(s).exec(&wiring.db).await;
 
             if operation.is_ok() {
-                let item = operation.ok();
+                let item = operation.ok().unwrap();
                 println!("INSERTED ONE: {:?}", item);
+                RoleDao::assign_role(&wiring.db, item.last_insert_id, Role::Admin)
+                    .await
+                    .unwrap();
             } else {
                 println!(
                     "Failed to insert super user... maybe it already exists?? {:?}",
@@ -81,4 +99,283 @@ impl UserDao {
 
         Ok(())
     }
+
+    pub async fn list(
+        wiring: &ServerWiring,
+        limit: u64,
+    ) -> Result<Vec<user_email_password::Model>, DbErr> {
+        UserEmailPassword::find()
+            .order_by_desc(user_email_password::Column::Id)
+            .limit(limit)
+            .all(&wiring.db)
+            .await
+    }
+
+    pub async fn search_by_display(
+        wiring: &ServerWiring,
+        fragment: &str,
+        limit: u64,
+    ) -> Result<Vec<user_email_password::Model>, DbErr> {
+        UserEmailPassword::find()
+            .inner_join(UserAttributes)
+            .filter(user_attributes::Column::Display.contains(fragment))
+            .order_by_desc(user_email_password::Column::Id)
+            .limit(limit)
+            .all(&wiring.db)
+            .await
+    }
+
+    pub async fn insert_member(
+        wiring: &ServerWiring,
+        email_plaintext_bytes: &[u8],
+        encoded_pwhash: &str,
+        display: &str,
+        role: Role,
+        invite_id: i32,
+    ) -> Result<user_email_password::Model, DbErr> {
+        let (encrypted_email, email_hash) = Self::email_columns(&wiring.config, email_plaintext_bytes)?;
+
+        // login and attributes are written together so we never end up with a half made member
+        let txn = wiring.db.begin().await?;
+
+        let login = user_email_password::ActiveModel {
+            email: Set(encrypted_email),
+            email_hash: Set(email_hash),
+            password: Set(String::from(encoded_pwhash)),
+            active: Set(false),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        let now: sea_orm::prelude::DateTimeWithTimeZone = chrono::Utc::now().into();
+
+        user_attributes::ActiveModel {
+            uid: Set(login.id),
+            display: Set(String::from(display)),
+            created_at: Set(now),
+            last_updated: Set(now),
+            settings: Set(String::from("{}")),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+
+        RoleDao::assign_role(&txn, login.id, role).await?;
+
+        // dropping the transaction without a commit rolls the new member back
+        if !InviteDao::redeem(&txn, invite_id, login.id).await? {
+            return Err(DbErr::Custom(String::from("invite is no longer redeemable")));
+        }
+
+        txn.commit().await?;
+
+        Ok(login)
+    }
+
+    pub async fn set_active(wiring: &ServerWiring, uid: i32, active: bool) -> Result<(), DbErr> {
+        user_email_password::ActiveModel {
+            id: Set(uid),
+            active: Set(active),
+            ..Default::default()
+        }
+        .update(&wiring.db)
+        .await
+        .map(|_| ())
+    }
+
+    /// Activates an account the first time its emailed link comes back. Accounts that were already
+    /// verified are left alone, so a spent link can't undo a deactivation or a suspension.
+    pub async fn verify_email(wiring: &ServerWiring, uid: i32) -> Result<bool, DbErr> {
+        let sql = "\
+            UPDATE user_email_password SET email_verified_at = now(), active = true \
+            WHERE id = $1 AND email_verified_at IS NULL";
+
+        let updated = wiring
+            .db
+            .execute(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                sql,
+                vec![uid.into()],
+            ))
+            .await?;
+
+        Ok(updated.rows_affected() == 1)
+    }
+
+    pub async fn find_by_id(
+        wiring: &ServerWiring,
+        uid: i32,
+    ) -> Result<Option<user_email_password::Model>, DbErr> {
+        UserEmailPassword::find_by_id(uid).one(&wiring.db).await
+    }
+
+    pub async fn set_password(
+        wiring: &ServerWiring,
+        uid: i32,
+        encoded_pwhash: &str,
+    ) -> Result<(), DbErr> {
+        user_email_password::ActiveModel {
+            id: Set(uid),
+            password: Set(String::from(encoded_pwhash)),
+            ..Default::default()
+        }
+        .update(&wiring.db)
+        .await
+        .map(|_| ())
+    }
+
+    /// Removes the member and everything that hangs off of them in one transaction.
+    pub async fn delete_member(wiring: &ServerWiring, uid: i32) -> Result<(), DbErr> {
+        let found = Self::find_by_id(wiring, uid)
+            .await?
+            .ok_or(DbErr::RecordNotFound(format!("uid {}", uid)))?;
+
+        // invites they handed out go too, which drops those rows from the invite lineage
+        // but leaves the members who joined with them alone
+        let statements = vec![
+            "DELETE FROM async_sessions WHERE id IN (SELECT session_id FROM user_session WHERE uid = $1)",
+            "DELETE FROM user_session WHERE uid = $1",
+            "DELETE FROM user_totp WHERE uid = $1",
+            "DELETE FROM user_role WHERE uid = $1",
+            "DELETE FROM user_attributes WHERE uid = $1",
+            "DELETE FROM invite_redemption WHERE invitee_uid = $1",
+            "DELETE FROM invite_redemption WHERE invite_id IN (SELECT id FROM invite WHERE inviter_uid = $1)",
+            "DELETE FROM invite WHERE inviter_uid = $1",
+        ];
+
+        let txn = wiring.db.begin().await?;
+
+        for sql in statements {
+            txn.execute(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                sql,
+                vec![uid.into()],
+            ))
+            .await?;
+        }
+
+        LoginAttempt::delete_many()
+            .filter(login_attempt::Column::Scope.eq(SCOPE_EMAIL))
+            .filter(login_attempt::Column::Subject.eq(found.email_hash))
+            .exec(&txn)
+            .await?;
+
+        UserEmailPassword::delete_many()
+            .filter(user_email_password::Column::Id.eq(uid))
+            .exec(&txn)
+            .await?;
+
+        txn.commit().await
+    }
+
+    /// Rows written before the blind index stored the deterministic ciphertext in both columns.
+    pub async fn count_unindexed(wiring: &ServerWiring) -> Result<u64, DbErr> {
+        let row = wiring
+            .db
+            .query_one(Statement::from_string(
+                DbBackend::Postgres,
+                String::from("SELECT COUNT(*) AS n FROM user_email_password WHERE email = email_hash"),
+            ))
+            .await?;
+
+        Ok(match row {
+            Some(row) => row.try_get::<i64>("", "n")? as u64,
+            None => 0,
+        })
+    }
+
+    // a keyed hash of a fixed input, it tells index keys apart without storing anything that opens them
+    fn index_key_fingerprint(config: &ServerConfig) -> String {
+        Self::email_index(config, b"hcc blind index key fingerprint")
+    }
+
+    async fn record_index_key<C: ConnectionTrait>(
+        db: &C,
+        config: &ServerConfig,
+    ) -> Result<(), DbErr> {
+        db.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            "INSERT INTO blind_index_key (id, fingerprint, recorded_at) VALUES (1, $1, now()) \
+             ON CONFLICT (id) DO UPDATE SET fingerprint = EXCLUDED.fingerprint, recorded_at = now()",
+            vec![Self::index_key_fingerprint(config).into()],
+        ))
+        .await
+        .map(|_| ())
+    }
+
+    /// Whether HCC_BLIND_INDEX_KEY_EMOJI is the key the stored email_hash values were computed with.
+    /// Databases that never recorded a key are checked against the super user, whose email we know,
+    /// and the current key is recorded when it finds them (or when there is nobody to find yet).
+    pub async fn index_key_matches(wiring: &ServerWiring) -> Result<bool, DbErr> {
+        let config = &wiring.config;
+
+        if let Some(recorded) = BlindIndexKey::find_by_id(1).one(&wiring.db).await? {
+            return Ok(recorded.fingerprint == Self::index_key_fingerprint(config));
+        }
+
+        let anyone = UserEmailPassword::find().one(&wiring.db).await?.is_some();
+        let super_user = Self::find_by_email(wiring, config.super_user_email.as_bytes()).await?;
+        if anyone && super_user.is_none() {
+            return Ok(false);
+        }
+
+        Self::record_index_key(&wiring.db, config).await?;
+        Ok(true)
+    }
+
+    /// Re-seals every stored email with a fresh nonce and recomputes its blind index with the
+    /// current key, then moves email scoped login attempts over to the new index.
+    /// Safe to run again, it only ever needs the encryption key to read what is there.
+    pub async fn reindex_emails(wiring: &ServerWiring) -> Result<ReindexReport, DbErr> {
+        let config = &wiring.config;
+        let mut report = ReindexReport::default();
+
+        let txn = wiring.db.begin().await?;
+
+        for found in UserEmailPassword::find().all(&txn).await? {
+            let plaintext = match encryption::open_with_key(&config.encryption_key_emoji, &found.email) {
+                Ok(plaintext) => plaintext,
+                Err(_) => {
+                    tide::log::warn!("Could not open the stored email of member {}", found.id);
+                    report.unreadable += 1;
+                    continue;
+                }
+            };
+
+            let (encrypted_email, email_hash) = Self::email_columns(config, &plaintext)?;
+            let old_hash = found.email_hash.clone();
+
+            let mut model: user_email_password::ActiveModel = found.into();
+            model.email = Set(encrypted_email);
+            model.email_hash = Set(email_hash.clone());
+            model.update(&txn).await?;
+            report.members += 1;
+
+            // the old subject was the ciphertext, lockouts carry over to the new index
+            if old_hash != email_hash {
+                LoginAttempt::update_many()
+                    .col_expr(login_attempt::Column::Subject, Expr::value(email_hash))
+                    .filter(login_attempt::Column::Scope.eq(SCOPE_EMAIL))
+                    .filter(login_attempt::Column::Subject.eq(old_hash))
+                    .exec(&txn)
+                    .await?;
+            }
+        }
+
+        // only once every readable row carries the new index does the server start with it
+        if report.unreadable == 0 {
+            Self::record_index_key(&txn, config).await?;
+        }
+
+        txn.commit().await?;
+
+        Ok(report)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReindexReport {
+    pub members: u64,
+    pub unreadable: u64,
 }
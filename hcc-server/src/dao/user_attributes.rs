@@ -0,0 +1,96 @@
+use crate::wiring::ServerWiring;
+
+use domain::sea_orm::entities::prelude::UserAttributes;
+use domain::sea_orm::entities::user_attributes;
+use domain::settings::MemberSettings;
+
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::*;
+
+pub struct UserAttributesDao {}
+
+impl UserAttributesDao {
+    pub async fn find_by_uid(
+        wiring: &ServerWiring,
+        uid: i32,
+    ) -> Result<Option<user_attributes::Model>, DbErr> {
+        UserAttributes::find()
+            .filter(user_attributes::Column::Uid.eq(uid))
+            .one(&wiring.db)
+            .await
+    }
+
+    pub async fn find_by_uids(
+        wiring: &ServerWiring,
+        uids: &[i32],
+    ) -> Result<Vec<user_attributes::Model>, DbErr> {
+        UserAttributes::find()
+            .filter(user_attributes::Column::Uid.is_in(uids.to_vec()))
+            .all(&wiring.db)
+            .await
+    }
+
+    pub async fn find_by_display(
+        wiring: &ServerWiring,
+        display: &str,
+    ) -> Result<Option<user_attributes::Model>, DbErr> {
+        UserAttributes::find()
+            .filter(user_attributes::Column::Display.eq(display))
+            .limit(1)
+            .one(&wiring.db)
+            .await
+    }
+
+    /// Settings are forgiving: unknown or missing keys fall back to defaults rather than failing.
+    pub fn settings_of(attributes: &user_attributes::Model) -> MemberSettings {
+        serde_json::from_str(&attributes.settings).unwrap_or_else(|e| {
+            tide::log::warn!("Unreadable settings for uid {}: {}", attributes.uid, e);
+            MemberSettings::default()
+        })
+    }
+
+    /// Writes the profile, creating the row for accounts that never had one (the super user).
+    pub async fn save_profile(
+        wiring: &ServerWiring,
+        uid: i32,
+        display: &str,
+        settings: &MemberSettings,
+    ) -> Result<user_attributes::Model, DbErr> {
+        let now: DateTimeWithTimeZone = chrono::Utc::now().into();
+        let settings_json =
+            serde_json::to_string(settings).map_err(|e| DbErr::Custom(e.to_string()))?;
+
+        match Self::find_by_uid(wiring, uid).await? {
+            Some(found) => {
+                let mut model: user_attributes::ActiveModel = found.into();
+                model.display = Set(String::from(display));
+                model.settings = Set(settings_json);
+                model.last_updated = Set(now);
+                model.update(&wiring.db).await
+            }
+            None => {
+                user_attributes::ActiveModel {
+                    uid: Set(uid),
+                    display: Set(String::from(display)),
+                    created_at: Set(now),
+                    last_updated: Set(now),
+                    settings: Set(settings_json),
+                    ..Default::default()
+                }
+                .insert(&wiring.db)
+                .await
+            }
+        }
+    }
+
+    pub async fn record_login(wiring: &ServerWiring, uid: i32) -> Result<(), DbErr> {
+        let now: DateTimeWithTimeZone = chrono::Utc::now().into();
+
+        UserAttributes::update_many()
+            .col_expr(user_attributes::Column::LastLogin, sea_query::Expr::value(now))
+            .filter(user_attributes::Column::Uid.eq(uid))
+            .exec(&wiring.db)
+            .await
+            .map(|_| ())
+    }
+}
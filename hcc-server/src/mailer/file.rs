@@ -0,0 +1,84 @@
+use async_std::fs::OpenOptions;
+use async_std::io::WriteExt;
+
+use super::{Email, Mailer, MailerError};
+
+// development + test transport: appends each message to a file, or prints it when the path is "-"
+
+pub struct FileMailer {
+    from: String,
+    path: String,
+}
+
+impl FileMailer {
+    pub fn new(path: &str, from: &str) -> FileMailer {
+        FileMailer {
+            from: String::from(from),
+            path: String::from(path),
+        }
+    }
+
+    fn format(&self, email: &Email) -> String {
+        format!(
+            "From: {}\nTo: {}\nSubject: {}\n\n{}\n\n",
+            self.from, email.to, email.subject, email.body
+        )
+    }
+}
+
+#[tide::utils::async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), MailerError> {
+        let formatted = self.format(&email);
+
+        if self.path == "-" {
+            println!("{}", formatted);
+            return Ok(());
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| MailerError(e.to_string()))?;
+
+        file.write_all(formatted.as_bytes())
+            .await
+            .map_err(|e| MailerError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_file_mailer_appends_messages() {
+        let path = std::env::temp_dir().join(format!("hcc-mail-{}.txt", uuid::Uuid::new_v4()));
+        let path_str = path.to_str().unwrap();
+
+        let mailer = FileMailer::new(path_str, "clubhouse@holycharisma.com");
+
+        async_std::task::block_on(async {
+            for subject in ["one", "two"] {
+                mailer
+                    .send(Email {
+                        to: String::from("human@holycharisma.com"),
+                        subject: String::from(subject),
+                        body: String::from("hello"),
+                    })
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(written.contains("To: human@holycharisma.com"));
+        assert!(written.contains("Subject: one"));
+        assert!(written.contains("Subject: two"));
+    }
+}
@@ -0,0 +1,30 @@
+pub mod file;
+pub mod smtp;
+
+use std::fmt;
+
+#[derive(Clone, Debug)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug)]
+pub struct MailerError(pub String);
+
+impl fmt::Display for MailerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mailer error: {}", self.0)
+    }
+}
+
+impl std::error::Error for MailerError {}
+
+// old fashioned E-MAIL
+// every backend gets the same plain text message, routes should never care which one is wired in
+
+#[tide::utils::async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), MailerError>;
+}
@@ -1,43 +1,100 @@
+mod commands;
 mod dao;
+mod mailer;
 mod middleware;
 mod routes;
 mod util;
 mod wiring;
 
+use domain::permission::Permission;
 use wiring::ServerWiring;
 
-#[macro_use]
-extern crate lazy_static;
-
 #[async_std::main]
 async fn main() -> tide::Result<()> {
     tide::log::start();
 
-    let config = ServerWiring::init_server_config();
+    // needs no config, it makes the keys the config points at
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some(commands::generate_keys::NAME) {
+        return commands::generate_keys::run(&args[2..]);
+    }
+    if args.get(1).map(String::as_str) == Some(commands::check_config::NAME) {
+        return commands::check_config::run().await;
+    }
+
+    // every problem with the config at once, rather than one failed start per problem
+    // returned from main they end up on stderr with a failing exit code
+    let config = util::config_check::checked_config().await?;
+    tide::log::info!("Configuration: {:?}", config);
 
-    // probably need to validate the config...
-    // is the session secret legit byte key?
-    // is the postgres url reachable?
-    // are the jwt signing keys valid?
     let server_wiring = ServerWiring::new(&config).await?;
 
+    match std::env::args().nth(1).as_deref() {
+        Some(commands::reindex_emails::NAME) => {
+            return commands::reindex_emails::run(&server_wiring).await;
+        }
+        Some(unknown) => {
+            return Err(tide::Error::from_str(
+                500,
+                format!("Unknown command: {}", unknown),
+            ));
+        }
+        None => {}
+    }
+
+    // old rows can't be found through the blind index, starting anyway would duplicate the super user
+    let unindexed = dao::user::UserDao::count_unindexed(&server_wiring).await?;
+    if unindexed > 0 {
+        return Err(tide::Error::from_str(
+            500,
+            format!(
+                "{} member emails predate the blind index, run `hcc-server {}` first",
+                unindexed,
+                commands::reindex_emails::NAME
+            ),
+        ));
+    }
+
+    // a changed index key finds nobody, the super user would be inserted a second time
+    if !dao::user::UserDao::index_key_matches(&server_wiring).await? {
+        return Err(tide::Error::from_str(
+            500,
+            format!(
+                "HCC_BLIND_INDEX_KEY_EMOJI is not the key member emails were indexed with, run `hcc-server {}` first",
+                commands::reindex_emails::NAME
+            ),
+        ));
+    }
+
     dao::user::UserDao::insert_super_user(&config, &server_wiring)
         .await
-        .unwrap();
+        .map_err(|_| tide::Error::from_str(500, "The super user could not be inserted"))?;
 
     let mut app = tide::with_state(server_wiring);
 
+    let headers_middleware = middleware::headers::SecurityHeadersMiddleware::new(
+        middleware::headers::SecurityHeaders::from_config(&config),
+    );
     let session_middleware = middleware::session::init_session_middleware(&config).await?;
     let anti_forgery_middleware = crate::middleware::security::AntiRequestForgeryMiddleware::new();
     let keyring_middleware = crate::middleware::keyring::SessionEncryptionMiddleware::new();
+    let fragment_middleware = middleware::fragment::FragmentEncryptionMiddleware::new();
     let user_ext_middleware = middleware::user::UserExtensionMiddleware::new();
 
-    let user_authorization_middleware =
-        middleware::authorization::UserAuthorizationMiddleware::new();
+    let secrets_authorization_middleware =
+        middleware::authorization::UserAuthorizationMiddleware::requiring(Permission::ViewSecrets);
+    // every admin route gets its own instance
+    let manage_users_authorization_middleware = || {
+        middleware::authorization::UserAuthorizationMiddleware::requiring(Permission::ManageUsers)
+    };
 
     // these global middlewares run on every request...
+    // outermost so the headers land on every response, the 403s of the middlewares below included
+    app.with(headers_middleware);
     app.with(session_middleware);
     app.with(keyring_middleware);
+    // inside the keyring middleware so it can seal fragments with this request's keyring
+    app.with(fragment_middleware);
     app.with(anti_forgery_middleware);
     app.with(user_ext_middleware);
 
@@ -46,16 +103,32 @@ async fn main() -> tide::Result<()> {
     // https://cheatsheetseries.owasp.org/cheatsheets/Cross-Site_Request_Forgery_Prevention_Cheat_Sheet.html
     app.at("/hcc_frame.js").get(routes::hcc_frame_js::get);
 
-    app.at("/handshake").get(routes::handshake::get);
+    app.at("/.well-known/jwks.json").get(routes::jwks::get);
+
+    app.at("/handshake")
+        .get(routes::handshake::get)
+        .post(routes::handshake::post);
 
     app.at("/login")
         .get(routes::user::login::get)
         .post(routes::user::login::post);
 
+    app.at("/login/totp").post(routes::user::login::post_second_factor);
+
     app.at("/signup")
         .get(routes::user::signup::get)
         .post(routes::user::signup::post);
 
+    app.at("/signup/verify").get(routes::user::verify::get);
+
+    app.at("/password/forgot")
+        .get(routes::password::forgot::get)
+        .post(routes::password::forgot::post);
+
+    app.at("/password/reset")
+        .get(routes::password::reset::get)
+        .post(routes::password::reset::post);
+
     app.at("/media").get(routes::media::list::get);
 
     app.at("/header").get(routes::brand::get_header);
@@ -65,12 +138,70 @@ async fn main() -> tide::Result<()> {
 
     app.at("/app").get(routes::app::get);
 
+    app.at("/account/totp")
+        .get(routes::user::totp::get)
+        .post(routes::user::totp::post);
+    app.at("/account/totp/disable").post(routes::user::totp::post_disable);
+
+    app.at("/invites")
+        .get(routes::user::invites::get)
+        .post(routes::user::invites::post);
+
+    app.at("/profile")
+        .get(routes::user::profile::get)
+        .post(routes::user::profile::post);
+
+    app.at("/account").get(routes::user::account::get);
+    app.at("/account/export").post(routes::user::account::post_export);
+    app.at("/account/deactivate").post(routes::user::account::post_deactivate);
+    app.at("/account/delete").post(routes::user::account::post_delete);
+
+    app.at("/account/sessions").get(routes::user::sessions::get);
+    app.at("/account/sessions/revoke").post(routes::user::sessions::post_revoke);
+    app.at("/account/sessions/revoke-others")
+        .post(routes::user::sessions::post_revoke_others);
+
     app.at("/disconnect").post(routes::disconnect::post);
 
     app.at("/api/secret")
-        .with(user_authorization_middleware)
+        .with(secrets_authorization_middleware)
         .get(routes::dummy_secret::get);
 
+    app.at("/admin/lockouts")
+        .with(manage_users_authorization_middleware())
+        .get(routes::admin::lockouts::get);
+    app.at("/admin/lockouts/clear")
+        .with(manage_users_authorization_middleware())
+        .post(routes::admin::lockouts::post_clear);
+
+    app.at("/admin/users")
+        .with(manage_users_authorization_middleware())
+        .get(routes::admin::users::get);
+    app.at("/admin/users/search")
+        .with(manage_users_authorization_middleware())
+        .post(routes::admin::users::post_search);
+    app.at("/admin/users/active")
+        .with(manage_users_authorization_middleware())
+        .post(routes::admin::users::post_active);
+    app.at("/admin/users/reset")
+        .with(manage_users_authorization_middleware())
+        .post(routes::admin::users::post_reset);
+    app.at("/admin/users/role")
+        .with(manage_users_authorization_middleware())
+        .post(routes::admin::users::post_role);
+
+    app.at("/admin/invites")
+        .with(manage_users_authorization_middleware())
+        .get(routes::admin::invites::get);
+    app.at("/admin/invites/quota")
+        .with(manage_users_authorization_middleware())
+        .post(routes::admin::invites::post_quota);
+
+    app.at("/admin/logout")
+        .with(manage_users_authorization_middleware())
+        .get(routes::admin::sessions::get)
+        .post(routes::admin::sessions::post);
+
     // TODO: all these assets shouldn't be served by this server....
     // they should probably be through some kind of CDN or somethin
     app.at("/hcc/*")
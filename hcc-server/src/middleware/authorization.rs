@@ -1,13 +1,23 @@
 use crate::wiring::ServerWiring;
 use crate::util::encryption::{SharedKeyring, UserEncryptedBase64Message};
+use domain::permission::Permission;
 use domain::session::SessionUser;
 
 #[derive(Default)]
-pub struct UserAuthorizationMiddleware {}
+pub struct UserAuthorizationMiddleware {
+    required: Option<Permission>,
+}
 
 impl UserAuthorizationMiddleware {
     pub fn new() -> Self {
-        Self {}
+        Self { required: None }
+    }
+
+    /// Only lets through users whose roles grant the given permission.
+    pub fn requiring(permission: Permission) -> Self {
+        Self {
+            required: Some(permission),
+        }
     }
 
     fn unauthorized() -> tide::Result<tide::Response> {
@@ -36,11 +46,28 @@ impl tide::Middleware<ServerWiring> for UserAuthorizationMiddleware {
                         message: maybe_token_text.unwrap().as_str().to_owned()
                     };
 
-                    let decrypted = message.decrypt(secrets).unwrap();
+                    let decrypted = match message.decrypt(secrets) {
+                        Ok(decrypted) => decrypted,
+                        Err(e) if e.is_replay() => {
+                            tide::log::info!("Rejecting replayed authorization token: {}", e);
+                            return Ok(tide::Response::builder(403).body_string(e.to_string()).build());
+                        }
+                        Err(_) => {
+                            tide::log::info!("Rejecting undecryptable authorization token");
+                            return UserAuthorizationMiddleware::unauthorized();
+                        }
+                    };
 
                     let verification = jwt_util.verify_auth_token(&decrypted, &user.email);
-                    if verification.is_ok() {
+                    let is_permitted = match self.required {
+                        Some(permission) => user.has_permission(permission),
+                        None => true,
+                    };
+                    if verification.is_ok() && is_permitted {
                         Ok(next.run(req).await)
+                    } else if verification.is_ok() {
+                        tide::log::info!("Missing required permission: {:?}", self.required);
+                        UserAuthorizationMiddleware::unauthorized()
                     } else {
                         tide::log::info!("Invalid authorization token");
                         UserAuthorizationMiddleware::unauthorized()
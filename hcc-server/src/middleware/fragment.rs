@@ -0,0 +1,106 @@
+use crate::util::encryption::SharedKeyring;
+use crate::wiring::ServerWiring;
+
+use askama::Template;
+use hcc_common::wire::{ENCODING_HEADER, PUBLIC_ENCODING};
+use tide::http::mime;
+use tide::Response;
+
+/// An html fragment for the session's eyes only, sealed by `FragmentEncryptionMiddleware` on the way out.
+/// The html rides along as a response extension, the body stays empty until it is encrypted,
+/// so a route mounted without the middleware sends nothing rather than plaintext.
+#[derive(Clone)]
+pub struct EncryptedFragment {
+    html: String,
+}
+
+impl EncryptedFragment {
+    pub fn new(html: String) -> Self {
+        EncryptedFragment { html: html }
+    }
+
+    pub fn render<T: Template>(view: &T) -> tide::Result<Self> {
+        Ok(EncryptedFragment::new(view.render()?))
+    }
+}
+
+impl From<EncryptedFragment> for Response {
+    fn from(fragment: EncryptedFragment) -> Self {
+        let mut response = Response::new(200);
+        response.insert_ext(fragment);
+        response
+    }
+}
+
+/// A fragment with nothing private in it, sent as plain html.
+/// The encoding header tells the client there is nothing to open.
+pub struct PublicFragment {
+    html: String,
+}
+
+impl PublicFragment {
+    pub fn render<T: Template>(view: &T) -> tide::Result<Self> {
+        Ok(PublicFragment { html: view.render()? })
+    }
+}
+
+impl From<PublicFragment> for Response {
+    fn from(fragment: PublicFragment) -> Self {
+        Response::builder(200)
+            .content_type(mime::HTML)
+            .header(ENCODING_HEADER, PUBLIC_ENCODING)
+            .body_string(fragment.html)
+            .build()
+    }
+}
+
+#[derive(Default)]
+pub struct FragmentEncryptionMiddleware {
+
+}
+
+impl FragmentEncryptionMiddleware {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[tide::utils::async_trait]
+impl tide::Middleware<ServerWiring> for FragmentEncryptionMiddleware {
+    async fn handle(
+        &self,
+        req: tide::Request<ServerWiring>,
+        next: tide::Next<'_, ServerWiring>,
+    ) -> tide::Result {
+        // the keyring middleware runs first, this clone already carries the negotiated encoding
+        let keyring = req.ext::<SharedKeyring>().cloned();
+        let path = String::from(req.url().path());
+
+        let mut response = next.run(req).await;
+
+        let fragment = match response.ext::<EncryptedFragment>() {
+            Some(fragment) => fragment.clone(),
+            None => return Ok(response),
+        };
+
+        let keyring = match keyring {
+            Some(keyring) => keyring,
+            None => {
+                tide::log::info!("Refusing to send an encrypted fragment for {} without a session keyring", path);
+                return Ok(Response::builder(403).build());
+            }
+        };
+
+        match keyring.encrypt_broadcast(&fragment.html).await {
+            Ok(message) => {
+                response.insert_header(ENCODING_HEADER, message.encoding.name());
+                response.set_body(message.into_body());
+                Ok(response)
+            }
+            Err(_) => {
+                tide::log::error!("Could not encrypt the fragment for {}", path);
+                Ok(Response::builder(500).build())
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::wiring::ServerWiring;
+
+use domain::server_config::ServerConfig;
+use tide::Request;
+
+// only the client's frame is ever embedded, and only by the shell page at "/"
+const FRAMED_PATH_PREFIX: &str = "/hcc/";
+
+const NONCE_SIZE: usize = 16;
+
+/// The response headers every page and fragment goes out with.
+#[derive(Clone, Debug)]
+pub struct SecurityHeaders {
+    /// `{nonce}` is replaced by the request's `CspNonce`
+    pub content_security_policy: String,
+    /// who may embed the client frame, everything else can't be framed at all
+    pub frame_ancestors: String,
+    /// zero leaves Strict-Transport-Security off
+    pub hsts_max_age_seconds: u64,
+    pub referrer_policy: String,
+    pub permissions_policy: String,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        SecurityHeaders {
+            // the wasm client needs wasm-unsafe-eval, style-loader and the templates need inline styles
+            content_security_policy: String::from(
+                "default-src 'self'; script-src 'self' 'nonce-{nonce}' 'wasm-unsafe-eval'; \
+                 style-src 'self' 'unsafe-inline'; img-src 'self' data: https:; media-src 'self' https:; \
+                 connect-src 'self'; object-src 'none'; base-uri 'none'; form-action 'self'",
+            ),
+            frame_ancestors: String::from("'self'"),
+            hsts_max_age_seconds: 365 * 24 * 60 * 60,
+            // the frame learns its parent's origin from document.referrer, no-referrer would break the handshake
+            referrer_policy: String::from("same-origin"),
+            permissions_policy: String::from(
+                "camera=(), microphone=(), geolocation=(), payment=(), usb=(), autoplay=(self)",
+            ),
+        }
+    }
+}
+
+impl SecurityHeaders {
+    pub fn from_config(config: &ServerConfig) -> Self {
+        SecurityHeaders {
+            content_security_policy: config.content_security_policy.clone(),
+            frame_ancestors: config.frame_ancestors.clone(),
+            hsts_max_age_seconds: config.hsts_max_age_seconds,
+            referrer_policy: config.referrer_policy.clone(),
+            permissions_policy: config.permissions_policy.clone(),
+        }
+    }
+}
+
+/// A fresh random value per request, scripts in a template only run if they carry it.
+#[derive(Clone)]
+pub struct CspNonce(String);
+
+impl CspNonce {
+    fn generate() -> tide::Result<Self> {
+        let mut bytes = [0u8; NONCE_SIZE];
+        orion::util::secure_rand_bytes(&mut bytes)?;
+        Ok(CspNonce(base64::encode(bytes)))
+    }
+
+    /// The nonce for a template's `<script nonce="...">`, empty outside the middleware.
+    pub fn of(req: &Request<ServerWiring>) -> String {
+        req.ext::<CspNonce>()
+            .map(|nonce| nonce.0.clone())
+            .unwrap_or_default()
+    }
+}
+
+pub struct SecurityHeadersMiddleware {
+    headers: SecurityHeaders,
+}
+
+impl SecurityHeadersMiddleware {
+    pub fn new(headers: SecurityHeaders) -> Self {
+        Self { headers: headers }
+    }
+}
+
+#[tide::utils::async_trait]
+impl tide::Middleware<ServerWiring> for SecurityHeadersMiddleware {
+    async fn handle(
+        &self,
+        mut req: tide::Request<ServerWiring>,
+        next: tide::Next<'_, ServerWiring>,
+    ) -> tide::Result {
+        let nonce = CspNonce::generate()?;
+        let framed = req.url().path().starts_with(FRAMED_PATH_PREFIX);
+        req.set_ext(nonce.clone());
+
+        let mut response = next.run(req).await;
+
+        let frame_ancestors = if framed {
+            self.headers.frame_ancestors.as_str()
+        } else {
+            "'none'"
+        };
+        let policy = format!(
+            "{}; frame-ancestors {}",
+            self.headers.content_security_policy.replace("{nonce}", &nonce.0),
+            frame_ancestors
+        );
+        response.insert_header("Content-Security-Policy", policy);
+
+        // older browsers only know X-Frame-Options, which can't name other origins
+        if !framed {
+            response.insert_header("X-Frame-Options", "DENY");
+        } else if self.headers.frame_ancestors == "'self'" {
+            response.insert_header("X-Frame-Options", "SAMEORIGIN");
+        }
+
+        if self.headers.hsts_max_age_seconds > 0 {
+            response.insert_header(
+                "Strict-Transport-Security",
+                format!("max-age={}; includeSubDomains", self.headers.hsts_max_age_seconds),
+            );
+        }
+        response.insert_header("Referrer-Policy", self.headers.referrer_policy.as_str());
+        response.insert_header("Permissions-Policy", self.headers.permissions_policy.as_str());
+        response.insert_header("X-Content-Type-Options", "nosniff");
+
+        Ok(response)
+    }
+}
@@ -1,5 +1,15 @@
 use crate::wiring::ServerWiring;
-use crate::util::encryption::{SharedKeyring, EncryptedKeyring};
+use crate::util::encryption::{EncryptedKeyring, KeyringRotation, SharedKeyring};
+use crate::util::replay::ReplayWindow;
+
+use hcc_common::wire::{WireEncoding, ENCODING_HEADER};
+
+// these run before the client has posted its public key to /handshake, so there is no keyring yet
+// everything else renders encrypted fragments and can't do anything useful without one
+const KEYLESS_PATHS: [&str; 5] = ["/", "/hcc_frame.js", "/handshake", "/favicon.svg", "/signup/verify"];
+const KEYLESS_PREFIXES: [&str; 2] = ["/hcc/", "/.well-known/"];
+
+pub const REPLAY_SESSION_KEY: &str = "replay";
 
 #[derive(Default)]
 pub struct SessionEncryptionMiddleware {
@@ -10,6 +20,18 @@ impl SessionEncryptionMiddleware {
     pub fn new() -> Self {
         Self {}
     }
+
+    fn allows_missing_keyring(req: &tide::Request<ServerWiring>) -> bool {
+        let path = req.url().path();
+
+        // a reset link from an email only stashes its token and redirects
+        let is_reset_link = path == "/password/reset"
+            && req.url().query_pairs().any(|(key, _)| key == "token");
+
+        KEYLESS_PATHS.contains(&path)
+            || KEYLESS_PREFIXES.iter().any(|prefix| path.starts_with(prefix))
+            || is_reset_link
+    }
 }
 
 #[tide::utils::async_trait]
@@ -20,20 +42,74 @@ impl tide::Middleware<ServerWiring> for SessionEncryptionMiddleware {
         next: tide::Next<'_, ServerWiring>,
     ) -> tide::Result {
         let s = req.session();
-        let secrets = match s.get::<EncryptedKeyring>("keyring") {
+        match s.get::<EncryptedKeyring>("keyring") {
             Some(secrets) => {
-                secrets.open(&req.state().config).expect("decrypted keyring")
+                let config = req.state().config.clone();
+                let mut secrets = match SharedKeyring::open(&secrets, &config) {
+                    Ok(secrets) => secrets,
+                    Err(_) => {
+                        // sealed under an earlier HCC_ENCRYPTION_KEY_EMOJI or a damaged session row,
+                        // dropping it puts the session back to before the handshake
+                        tide::log::info!("Session keyring could not be opened, the client needs to handshake again");
+                        let session = req.session_mut();
+                        session.remove("keyring");
+                        session.remove(REPLAY_SESSION_KEY);
+
+                        if SessionEncryptionMiddleware::allows_missing_keyring(&req) {
+                            return Ok(next.run(req).await);
+                        }
+                        return Ok(tide::Response::builder(403).build());
+                    }
+                };
+
+                // rotate before the route runs so its response already carries the new epoch,
+                // the request itself was sealed under the old one which stays in the grace window
+                let rotation = KeyringRotation::from_config(&config);
+                let now = chrono::Utc::now().timestamp();
+                if secrets.rotation_due(&rotation, now) {
+                    secrets
+                        .ratchet(&rotation, now)
+                        .map_err(|_| tide::Error::from_str(500, "session keyring could not be ratcheted"))?;
+                    tide::log::debug!("Session keyring moved to epoch {}", secrets.keys.epoch);
+                }
+                secrets.epoch_messages += 1;
+
+                let sealed = secrets
+                    .seal(&config)
+                    .map_err(|_| tide::Error::from_str(500, "session keyring could not be sealed"))?;
+                req.session_mut().insert("keyring", sealed)?;
+
+                // routes record what they decrypt into the window every request on this keyring shares,
+                // so parallel requests can't both accept one nonce. the session only keeps a copy for
+                // after a restart, the session clone shares its data with the one the session middleware saves
+                // the keyring's message count is still last writer wins, it only decides when to rotate
+                let replay = req.state().services.replay_windows.shared(
+                    &secrets.broadcast,
+                    chrono::Utc::now().timestamp_millis(),
+                    || req.session().get(REPLAY_SESSION_KEY).unwrap_or_default(),
+                );
+                secrets.replay = replay.clone();
+                let mut session = req.session().clone();
+
+                // fragments come back in the first encoding the client listed that we know
+                secrets.encoding = WireEncoding::negotiate(req.header(ENCODING_HEADER).map(|h| h.as_str()));
+
+                req.set_ext(secrets);
+                let response = next.run(req).await;
+
+                let window: ReplayWindow = replay.lock().expect("replay window").clone();
+                if window.is_changed() {
+                    session.insert(REPLAY_SESSION_KEY, &window)?;
+                }
+                Ok(response)
+            },
+            None if SessionEncryptionMiddleware::allows_missing_keyring(&req) => {
+                Ok(next.run(req).await)
             },
             None => {
-                let secrets = SharedKeyring::new().await.unwrap();
-                let config = req.state().config.clone();
-                let m = req.session_mut();
-                let e = EncryptedKeyring::seal(&secrets, &config).expect("encrypted keyring");
-                m.insert("keyring", e).expect("serializable");
-                secrets
+                tide::log::info!("Missing session keyring, the client needs to handshake first");
+                Ok(tide::Response::builder(403).build())
             }
-        };
-        req.set_ext(secrets);
-        Ok(next.run(req).await)
+        }
     }
 }
@@ -2,4 +2,6 @@ pub mod security;
 pub mod session;
 pub mod user;
 pub mod keyring;
-pub mod authorization;
\ No newline at end of file
+pub mod authorization;
+pub mod fragment;
+pub mod headers;
@@ -1,7 +1,7 @@
 use tide::http::Method;
 
 use crate::wiring::ServerWiring;
-use crate::util::encryption::SharedKeyring;
+use crate::util::encryption::{SharedKeyring, UserEncryptedBase64Message};
 
 #[derive(Default)]
 pub struct AntiRequestForgeryMiddleware {}
@@ -14,6 +14,10 @@ impl AntiRequestForgeryMiddleware {
     fn unauthorized() -> tide::Result<tide::Response> {
         Ok(tide::Response::builder(403).build())
     }
+
+    fn replayed(reason: &str) -> tide::Result<tide::Response> {
+        Ok(tide::Response::builder(403).body_string(reason.to_owned()).build())
+    }
 }
 
 #[tide::utils::async_trait]
@@ -23,7 +27,10 @@ impl tide::Middleware<ServerWiring> for AntiRequestForgeryMiddleware {
         req: tide::Request<ServerWiring>,
         next: tide::Next<'_, ServerWiring>,
     ) -> tide::Result {
-        let should_protect_route = match req.method() {
+        // the handshake can't send an encrypted token yet, it checks the plaintext one itself
+        let is_handshake = req.url().path() == "/handshake";
+
+        let should_protect_route = !is_handshake && match req.method() {
             Method::Get => false,
             Method::Post | Method::Put | Method::Patch | Method::Delete => true,
             _ => false,
@@ -38,7 +45,24 @@ impl tide::Middleware<ServerWiring> for AntiRequestForgeryMiddleware {
                     let jwt_util = &req.state().services.jwt_util;
                     let session = req.session();
                     let secrets: &SharedKeyring = req.ext().unwrap();
-                    let verification = jwt_util.verify_csrf_token(maybe_token_text.unwrap().as_str(), session.id(), secrets);
+
+                    let message = UserEncryptedBase64Message {
+                        message: maybe_token_text.unwrap().as_str().to_owned()
+                    };
+
+                    let decrypted = match message.decrypt(secrets) {
+                        Ok(decrypted) => decrypted,
+                        Err(e) if e.is_replay() => {
+                            tide::log::info!("Rejecting replayed anti forgery token: {}", e);
+                            return AntiRequestForgeryMiddleware::replayed(&e.to_string());
+                        }
+                        Err(_) => {
+                            tide::log::info!("Rejecting undecryptable anti forgery token");
+                            return AntiRequestForgeryMiddleware::unauthorized();
+                        }
+                    };
+
+                    let verification = jwt_util.verify_csrf_token(&decrypted, session.id());
                     if verification.is_ok() {
                         Ok(next.run(req).await)
                     } else {
@@ -1,5 +1,8 @@
+use crate::dao::role::RoleDao;
+use crate::dao::session::SessionDao;
 use crate::wiring::ServerWiring;
 use crate::util::encryption::SharedKeyring;
+use crate::util::request;
 use domain::session::SessionUser;
 
 #[derive(Default)]
@@ -24,8 +27,36 @@ impl tide::Middleware<ServerWiring> for UserExtensionMiddleware {
         // - sign an auth token for them 
         //   - give it back to the client in our response header
 
+        // only a fully logged in user lives under "user"
+        // a login waiting on its second factor sits under its own key and never reaches here
+
         let maybe_user: Option<SessionUser> = req.session().get("user");
 
+        // a session revoked from elsewhere no longer has its tracking row, so drop the user from it
+        let maybe_user = match maybe_user {
+            Some(mut user) => {
+                let session_id = String::from(req.session().id());
+                let tracked = SessionDao::find_by_session_id(req.state(), &session_id).await?;
+
+                match tracked {
+                    Some(found) if found.uid == user.uid => {
+                        let ip = request::client_address(&req);
+                        SessionDao::touch(req.state(), found, &ip).await?;
+                        // roles change under live sessions, a revoked admin must lose access right away
+                        user.roles = RoleDao::roles_for_user(req.state(), user.uid).await?;
+                        user.permissions = RoleDao::permissions_for_user(req.state(), user.uid).await?;
+                        Some(user)
+                    }
+                    _ => {
+                        tide::log::info!("Dropping user from revoked session");
+                        req.session_mut().remove("user");
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
         let auth_token = 
             if maybe_user.is_some() {
                 let user = maybe_user.unwrap();
@@ -0,0 +1,100 @@
+use tide::prelude::*;
+use tide::{Request, Result};
+
+use crate::dao;
+use crate::dao::invite::InviteDao;
+use crate::middleware::fragment::EncryptedFragment;
+use crate::util::encryption;
+use crate::wiring::ServerWiring;
+
+use askama::Template; // bring trait in scope
+
+struct LineageRow {
+    inviter: String,
+    invitee: String,
+    role: String,
+    redeemed_at: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/invites.html.j2")]
+struct InviteLineageViewModel {
+    lineage: Vec<LineageRow>,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct InviteQuotaDto {
+    email: String, // emoji encrypted fields
+    quota: String,
+}
+
+async fn render_lineage(req: &Request<ServerWiring>, message: &str) -> Result {
+    let lineage = InviteDao::lineage(req.state())
+        .await?
+        .into_iter()
+        .map(|l| LineageRow {
+            inviter: l.inviter_display,
+            invitee: l.invitee_display,
+            role: l.role,
+            redeemed_at: l.redeemed_at.to_rfc3339(),
+        })
+        .collect();
+
+    let view = InviteLineageViewModel {
+        lineage: lineage,
+        message: String::from(message),
+    };
+
+    Ok(EncryptedFragment::render(&view)?.into())
+}
+
+pub async fn get(req: Request<ServerWiring>) -> Result {
+    render_lineage(&req, "").await
+}
+
+pub async fn post_quota(mut req: Request<ServerWiring>) -> Result {
+    let form = {
+        let encrypted_form: InviteQuotaDto = req.body_form().await?;
+
+        let secrets: &encryption::SharedKeyring = req.ext().unwrap();
+
+        let sender = &secrets.user;
+
+        let decrypt = |message: String| {
+            encryption::UserEncryptedEmojiMessage {
+                sender: sender.to_owned(),
+                message: message,
+            }
+            .decrypt(secrets)
+            .map_err(|e| tide::Error::new(403, e))
+        };
+
+        InviteQuotaDto {
+            email: decrypt(encrypted_form.email)?,
+            quota: decrypt(encrypted_form.quota)?,
+        }
+    };
+
+    let quota = match form.quota.trim().parse::<i32>() {
+        Ok(quota) if quota >= 0 => quota,
+        _ => return render_lineage(&req, "Quota must be zero or more.").await,
+    };
+
+    let wiring: &ServerWiring = req.state();
+
+    let found = dao::user::UserDao::find_by_email(wiring, form.email.trim().as_bytes())
+        .await
+        .unwrap();
+
+    let updated = match found {
+        Some(user) => InviteDao::set_quota(wiring, user.id, quota).await?,
+        None => false,
+    };
+
+    if updated {
+        render_lineage(&req, &format!("Invite quota set to {}.", quota)).await
+    } else {
+        render_lineage(&req, "No member has that email.").await
+    }
+}
@@ -0,0 +1,96 @@
+use tide::prelude::*;
+use tide::{Request, Result};
+
+use crate::dao::login_attempt::{LoginAttemptDao, SCOPE_EMAIL};
+use crate::dao::user::UserDao;
+use crate::middleware::fragment::EncryptedFragment;
+use crate::util::encryption;
+use crate::wiring::ServerWiring;
+
+use askama::Template; // bring trait in scope
+
+struct LockoutRow {
+    id: i32,
+    scope: String,
+    subject: String,
+    failures: i32,
+    locked_until: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/lockouts.html.j2")]
+struct LockoutsViewModel {
+    lockouts: Vec<LockoutRow>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClearLockoutDto {
+    id: String, // emoji encrypted fields
+}
+
+async fn render_lockouts(req: &Request<ServerWiring>) -> Result {
+    let wiring: &ServerWiring = req.state();
+
+    let locked = LoginAttemptDao::list_locked(wiring).await?;
+
+    let mut lockouts = vec![];
+    for attempt in locked {
+        // email subjects are the blind index, which can't be reversed, so show the member's
+        // stored email instead. attempts against addresses nobody has signed up with stay unnamed
+        let subject = if attempt.scope == SCOPE_EMAIL {
+            UserDao::find_by_email_index(wiring, &attempt.subject)
+                .await?
+                .and_then(|found| {
+                    encryption::open_with_key(&wiring.config.encryption_key_emoji, &found.email).ok()
+                })
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .unwrap_or_else(|| String::from("(not a member)"))
+        } else {
+            attempt.subject
+        };
+
+        lockouts.push(LockoutRow {
+            id: attempt.id,
+            scope: attempt.scope,
+            subject: subject,
+            failures: attempt.failures,
+            locked_until: attempt
+                .locked_until
+                .map(|until| until.to_rfc3339())
+                .unwrap_or_default(),
+        });
+    }
+
+    let view = LockoutsViewModel { lockouts: lockouts };
+
+    Ok(EncryptedFragment::render(&view)?.into())
+}
+
+pub async fn get(req: Request<ServerWiring>) -> Result {
+    render_lockouts(&req).await
+}
+
+pub async fn post_clear(mut req: Request<ServerWiring>) -> Result {
+    let id = {
+        let encrypted_form: ClearLockoutDto = req.body_form().await?;
+
+        let secrets: &encryption::SharedKeyring = req.ext().unwrap();
+
+        let encrypted_id = encryption::UserEncryptedEmojiMessage {
+            sender: secrets.user.to_owned(),
+            message: encrypted_form.id,
+        };
+
+        encrypted_id.decrypt(secrets).map_err(|e| tide::Error::new(403, e))?
+    };
+
+    match id.trim().parse::<i32>() {
+        Ok(id) => {
+            tide::log::info!("Clearing login lockout: {}", id);
+            LoginAttemptDao::clear_by_id(req.state(), id).await?;
+        }
+        Err(_) => tide::log::info!("Ignoring malformed lockout id"),
+    }
+
+    render_lockouts(&req).await
+}
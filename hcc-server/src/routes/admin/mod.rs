@@ -0,0 +1,4 @@
+pub mod invites;
+pub mod lockouts;
+pub mod sessions;
+pub mod users;
@@ -0,0 +1,61 @@
+use tide::prelude::*;
+use tide::{Request, Result};
+
+use crate::dao;
+use crate::dao::session::SessionDao;
+use crate::middleware::fragment::EncryptedFragment;
+use crate::util::encryption;
+use crate::wiring::ServerWiring;
+
+use askama::Template; // bring trait in scope
+
+#[derive(Template)]
+#[template(path = "admin/force_logout.html.j2")]
+struct ForceLogoutViewModel {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ForceLogoutDto {
+    email: String, // emoji encrypted fields
+}
+
+async fn render_force_logout(message: &str) -> Result {
+    let view = ForceLogoutViewModel {
+        message: String::from(message),
+    };
+
+    Ok(EncryptedFragment::render(&view)?.into())
+}
+
+pub async fn get(_req: Request<ServerWiring>) -> Result {
+    render_force_logout("").await
+}
+
+pub async fn post(mut req: Request<ServerWiring>) -> Result {
+    let email = {
+        let encrypted_form: ForceLogoutDto = req.body_form().await?;
+
+        let secrets: &encryption::SharedKeyring = req.ext().unwrap();
+
+        let encrypted_email = encryption::UserEncryptedEmojiMessage {
+            sender: secrets.user.to_owned(),
+            message: encrypted_form.email,
+        };
+
+        encrypted_email.decrypt(secrets).map_err(|e| tide::Error::new(403, e))?
+    };
+
+    let wiring: &ServerWiring = req.state();
+
+    let found = dao::user::UserDao::find_by_email(wiring, email.trim().as_bytes()).await?;
+
+    match found {
+        Some(user) => {
+            let count = SessionDao::revoke_all(wiring, user.id).await?;
+            tide::log::info!("Forced logout of uid {} from {} session(s)", user.id, count);
+            render_force_logout(&format!("Signed out {} session(s).", count)).await
+        }
+        None => render_force_logout("No account has that email.").await,
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+
+use tide::prelude::*;
+use tide::{Request, Result};
+
+use crate::dao;
+use crate::dao::user_attributes::UserAttributesDao;
+use crate::middleware::fragment::EncryptedFragment;
+use crate::routes::password::reset;
+use crate::util::encryption;
+use crate::wiring::ServerWiring;
+use domain::permission::Role;
+use domain::sea_orm::entities::{user_attributes, user_email_password};
+use domain::session::SessionUser;
+
+use askama::Template; // bring trait in scope
+
+const PAGE_SIZE: u64 = 50;
+
+struct UserRow {
+    uid: i32,
+    email: String,
+    display: String,
+    active: bool,
+    roles: Vec<String>,
+    second_factor: bool,
+    last_login: String,
+    sessions: usize,
+}
+
+#[derive(Template)]
+#[template(path = "admin/users.html.j2")]
+struct UsersViewModel {
+    users: Vec<UserRow>,
+    all_roles: Vec<String>,
+    query: String,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchDto {
+    query: String, // emoji encrypted fields
+}
+
+#[derive(Debug, Deserialize)]
+struct UserActionDto {
+    uid: String, // emoji encrypted fields
+    value: Option<String>,
+}
+
+// a field that doesn't open, or was replayed, is refused with a 403 before the route acts on it
+fn decrypt_field(req: &Request<ServerWiring>, message: String) -> Result<String> {
+    let secrets: &encryption::SharedKeyring = req.ext().unwrap();
+
+    encryption::UserEncryptedEmojiMessage {
+        sender: secrets.user.to_owned(),
+        message: message,
+    }
+    .decrypt(secrets)
+    .map_err(|e| tide::Error::new(403, e))
+}
+
+fn plaintext_email(wiring: &ServerWiring, found: &user_email_password::Model) -> Option<String> {
+    encryption::open_with_key(&wiring.config.encryption_key_emoji, &found.email)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+}
+
+async fn render_users(
+    req: &Request<ServerWiring>,
+    found: Vec<user_email_password::Model>,
+    query: &str,
+    message: &str,
+) -> Result {
+    let wiring: &ServerWiring = req.state();
+
+    // a page is a handful of queries however many members are on it
+    let uids: Vec<i32> = found.iter().map(|f| f.id).collect();
+    let mut attributes: HashMap<i32, user_attributes::Model> =
+        UserAttributesDao::find_by_uids(wiring, &uids)
+            .await?
+            .into_iter()
+            .map(|a| (a.uid, a))
+            .collect();
+    let mut roles = dao::role::RoleDao::roles_for_users(wiring, &uids).await?;
+    let second_factors = dao::totp::TotpDao::enabled_among(wiring, &uids).await?;
+    let mut sessions: HashMap<i32, usize> = HashMap::new();
+    for session in dao::session::SessionDao::list_for_users(wiring, &uids).await? {
+        *sessions.entry(session.uid).or_default() += 1;
+    }
+
+    let users = found
+        .into_iter()
+        .map(|f| {
+            let attributes = attributes.remove(&f.id);
+            UserRow {
+                uid: f.id,
+                email: plaintext_email(wiring, &f).unwrap_or_else(|| String::from("(unreadable)")),
+                display: attributes
+                    .as_ref()
+                    .map(|a| a.display.clone())
+                    .unwrap_or_default(),
+                active: f.active,
+                roles: roles
+                    .remove(&f.id)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|r| String::from(r.name()))
+                    .collect(),
+                second_factor: second_factors.contains(&f.id),
+                last_login: attributes
+                    .and_then(|a| a.last_login)
+                    .map(|l| l.to_rfc3339())
+                    .unwrap_or_else(|| String::from("never")),
+                sessions: sessions.get(&f.id).copied().unwrap_or(0),
+            }
+        })
+        .collect();
+
+    let view = UsersViewModel {
+        users: users,
+        all_roles: Role::ALL.iter().map(|r| String::from(r.name())).collect(),
+        query: String::from(query),
+        message: String::from(message),
+    };
+
+    Ok(EncryptedFragment::render(&view)?.into())
+}
+
+async fn render_one(req: &Request<ServerWiring>, uid: i32, message: &str) -> Result {
+    let found = dao::user::UserDao::find_by_id(req.state(), uid).await?;
+    render_users(req, found.into_iter().collect(), "", message).await
+}
+
+pub async fn get(req: Request<ServerWiring>) -> Result {
+    let found = dao::user::UserDao::list(req.state(), PAGE_SIZE).await?;
+    render_users(&req, found, "", "").await
+}
+
+pub async fn post_search(mut req: Request<ServerWiring>) -> Result {
+    let form: SearchDto = req.body_form().await?;
+    let query = decrypt_field(&req, form.query)?;
+    let query = query.trim();
+
+    let wiring: &ServerWiring = req.state();
+
+    // emails can only be matched whole through the blind index, anything else is a display name
+    let found = if query.is_empty() {
+        dao::user::UserDao::list(wiring, PAGE_SIZE).await?
+    } else if query.contains('@') {
+        dao::user::UserDao::find_by_email(wiring, query.as_bytes())
+            .await
+            .unwrap()
+            .into_iter()
+            .collect()
+    } else {
+        dao::user::UserDao::search_by_display(wiring, query, PAGE_SIZE).await?
+    };
+
+    render_users(&req, found, query, "").await
+}
+
+/// Reads the target uid and the action's value. What an admin may do to their own account
+/// is up to each action: no deactivating themselves, no dropping their own admin role.
+async fn action_target(
+    req: &mut Request<ServerWiring>,
+) -> Result<(SessionUser, Option<i32>, String)> {
+    let admin = req.ext::<SessionUser>().unwrap().to_owned();
+
+    let form: UserActionDto = req.body_form().await?;
+    let uid = decrypt_field(req, form.uid)?.trim().parse::<i32>().ok();
+    let value = form
+        .value
+        .map(|v| decrypt_field(req, v))
+        .transpose()?
+        .unwrap_or_default();
+
+    Ok((admin, uid, value))
+}
+
+pub async fn post_active(mut req: Request<ServerWiring>) -> Result {
+    let (admin, uid, value) = action_target(&mut req).await?;
+
+    let uid = match uid {
+        Some(uid) if uid != admin.uid => uid,
+        _ => return render_one(&req, admin.uid, "You can't change your own account state here.").await,
+    };
+
+    let active = value == "activate";
+
+    let wiring: &ServerWiring = req.state();
+
+    dao::user::UserDao::set_active(wiring, uid, active).await?;
+
+    if !active {
+        // a deactivated member shouldn't keep browsing on an old session
+        dao::session::SessionDao::revoke_all(wiring, uid).await?;
+    }
+
+    tide::log::info!("uid {} set uid {} active={}", admin.uid, uid, active);
+
+    let message = if active { "Account activated." } else { "Account deactivated." };
+    render_one(&req, uid, message).await
+}
+
+pub async fn post_reset(mut req: Request<ServerWiring>) -> Result {
+    let (admin, uid, _) = action_target(&mut req).await?;
+
+    let wiring: &ServerWiring = req.state();
+
+    let found = match uid {
+        Some(uid) => dao::user::UserDao::find_by_id(wiring, uid).await?,
+        None => None,
+    };
+
+    let message = match found.as_ref().and_then(|f| plaintext_email(wiring, f).map(|e| (f, e))) {
+        Some((f, email)) => match reset::send_reset(wiring, f, &email).await {
+            Ok(_) => {
+                tide::log::info!("uid {} sent a password reset to uid {}", admin.uid, f.id);
+                "Password reset email sent."
+            }
+            Err(e) => {
+                tide::log::error!("Failed to send password reset email: {:?}", e);
+                "Unable to send the reset email right now."
+            }
+        },
+        None => "No such member.",
+    };
+
+    match found {
+        Some(f) => render_one(&req, f.id, message).await,
+        None => render_users(&req, vec![], "", message).await,
+    }
+}
+
+pub async fn post_role(mut req: Request<ServerWiring>) -> Result {
+    let (admin, uid, value) = action_target(&mut req).await?;
+
+    // value looks like "grant:patron" or "revoke:moderator"
+    let change = value
+        .split_once(':')
+        .and_then(|(action, name)| Role::from_name(name).map(|role| (action == "grant", role)));
+
+    let (uid, (grant, role)) = match (uid, change) {
+        (Some(uid), Some(change)) => (uid, change),
+        _ => return render_users(&req, vec![], "", "Unknown role change.").await,
+    };
+
+    if uid == admin.uid && role == Role::Admin && !grant {
+        return render_one(&req, uid, "You can't remove your own admin role.").await;
+    }
+
+    let wiring: &ServerWiring = req.state();
+
+    if grant {
+        dao::role::RoleDao::assign_role(&wiring.db, uid, role).await?;
+    } else {
+        dao::role::RoleDao::revoke_role(&wiring.db, uid, role).await?;
+    }
+
+    tide::log::info!("uid {} {} role {} for uid {}", admin.uid, if grant { "granted" } else { "revoked" }, role.name(), uid);
+
+    // sessions look their roles up on every request, so this applies to the member's next click
+    render_one(&req, uid, "Roles updated.").await
+}
@@ -1,8 +1,10 @@
 use tide::prelude::*;
-use tide::{http::mime, Request, Response, Result, Redirect};
+use tide::{Request, Response, Result, Redirect};
 
-use crate::util::encryption::{self, UserEncryptedEmojiMessage};
+use crate::dao;
+use crate::middleware::fragment::EncryptedFragment;
 use crate::wiring::ServerWiring;
+use domain::permission::Permission;
 use domain::session::SessionUser;
 
 use askama::Template; // bring trait in scope
@@ -11,6 +13,8 @@ use askama::Template; // bring trait in scope
 #[template(path = "app.html.j2")] // using the template in this path, relative
 struct AppView {
     user: SessionUser,
+    can_view_admin: bool,
+    second_factor_enabled: bool,
 }
 
 pub async fn get(req: Request<ServerWiring>) -> Result {
@@ -19,21 +23,20 @@ pub async fn get(req: Request<ServerWiring>) -> Result {
     if maybe_user.is_some() {
         let user = maybe_user.unwrap().to_owned();
 
-        let secrets: &encryption::SharedKeyring = req.ext().unwrap();
-        
-        let app_view = AppView { user: user };
+        if !user.has_permission(Permission::ViewApp) {
+            return Ok(Response::builder(403).build());
+        }
 
-        let encrypted_body =secrets.encrypt_broadcast_emoji(&app_view.render().unwrap())
-            .await
-            .unwrap()
-            .message;
+        let second_factor_enabled =
+            dao::totp::TotpDao::is_enabled(req.state(), user.uid).await?;
 
-        let response = Response::builder(200)
-            .content_type(mime::PLAIN)
-            .body_string(encrypted_body)
-            .build();
+        let app_view = AppView {
+            can_view_admin: user.has_permission(Permission::ViewAdmin),
+            user: user,
+            second_factor_enabled: second_factor_enabled,
+        };
 
-        Ok(response)
+        Ok(EncryptedFragment::render(&app_view)?.into())
     } else {
         Ok(Redirect::new("/login").into())
     }
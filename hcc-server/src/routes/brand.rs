@@ -1,9 +1,9 @@
 use tide::prelude::*;
-use tide::{http::mime, Redirect, Request, Response, Result};
+use tide::{Redirect, Request, Result};
 
 use crate::dao;
+use crate::middleware::fragment::PublicFragment;
 use crate::util::emoji;
-use crate::util::encryption;
 use crate::util::password::PasswordUtil;
 use crate::wiring::ServerWiring;
 
@@ -27,78 +27,26 @@ struct BrandSplashViewModel {}
 #[template(path = "brand/footer.html.j2")]
 struct BrandFooterViewModel {}
 
-pub async fn get_header(req: Request<ServerWiring>) -> Result {
+pub async fn get_header(_req: Request<ServerWiring>) -> Result {
     let view_context = BrandHeaderViewModel {};
 
-    let secrets: &encryption::SharedKeyring = req.ext().unwrap();
-
-    let encrypted_body = secrets
-        .encrypt_broadcast_emoji(&view_context.render().unwrap())
-        .await
-        .unwrap()
-        .message;
-
-    let response = Response::builder(200)
-        .content_type(mime::HTML)
-        .body_string(encrypted_body)
-        .build();
-
-    Ok(response)
+    Ok(PublicFragment::render(&view_context)?.into())
 }
 
-pub async fn get_sidebar(req: Request<ServerWiring>) -> Result {
+pub async fn get_sidebar(_req: Request<ServerWiring>) -> Result {
     let view_context = BrandSidebarViewModel {};
 
-    let secrets: &encryption::SharedKeyring = req.ext().unwrap();
-
-    let encrypted_body = secrets
-        .encrypt_broadcast_emoji(&view_context.render().unwrap())
-        .await
-        .unwrap()
-        .message;
-
-    let response = Response::builder(200)
-        .content_type(mime::HTML)
-        .body_string(encrypted_body)
-        .build();
-
-    Ok(response)
+    Ok(PublicFragment::render(&view_context)?.into())
 }
 
-pub async fn get_splash(req: Request<ServerWiring>) -> Result {
+pub async fn get_splash(_req: Request<ServerWiring>) -> Result {
     let view_context = BrandSplashViewModel {};
 
-    let secrets: &encryption::SharedKeyring = req.ext().unwrap();
-
-    let encrypted_body = secrets
-        .encrypt_broadcast_emoji(&view_context.render().unwrap())
-        .await
-        .unwrap()
-        .message;
-
-    let response = Response::builder(200)
-        .content_type(mime::HTML)
-        .body_string(encrypted_body)
-        .build();
-
-    Ok(response)
+    Ok(PublicFragment::render(&view_context)?.into())
 }
 
-pub async fn get_footer(req: Request<ServerWiring>) -> Result {
+pub async fn get_footer(_req: Request<ServerWiring>) -> Result {
     let view_context = BrandFooterViewModel {};
 
-    let secrets: &encryption::SharedKeyring = req.ext().unwrap();
-
-    let encrypted_body = secrets
-        .encrypt_broadcast_emoji(&view_context.render().unwrap())
-        .await
-        .unwrap()
-        .message;
-
-    let response = Response::builder(200)
-        .content_type(mime::HTML)
-        .body_string(encrypted_body)
-        .build();
-
-    Ok(response)
+    Ok(PublicFragment::render(&view_context)?.into())
 }
@@ -1,9 +1,13 @@
 use tide::{Request, Response, Result};
 
+use crate::dao::session::SessionDao;
 use crate::wiring::ServerWiring;
 
 
 pub async fn post(mut req: Request<ServerWiring>) -> Result {
+    let session_id = String::from(req.session().id());
+    SessionDao::forget(req.state(), &session_id).await?;
+
     let session = req.session_mut();
 
     session.destroy();
@@ -1,7 +1,9 @@
 use tide::prelude::*;
 use tide::{Redirect, Request, Response, Result};
 
-use crate::util::encryption::{self, UserEncryptedEmojiMessage};
+use crate::middleware::keyring::REPLAY_SESSION_KEY;
+use crate::util::encryption::SharedKeyring;
+use crate::routes;
 use crate::wiring::ServerWiring;
 use domain::session::SessionUser;
 
@@ -13,7 +15,74 @@ pub async fn get(req: Request<ServerWiring>) -> Result {
 
     if maybe_user.is_some() {
         Ok(Redirect::new("/app").into())
+    } else if req
+        .session()
+        .get::<String>(routes::password::reset::RESET_SESSION_KEY)
+        .is_some()
+    {
+        // arrived from a password reset email
+        Ok(Redirect::new("/password/reset").into())
     } else {
         Ok(Redirect::new("/login").into())
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientHelloDto {
+    client_public_key: String, // emoji encoded
+}
+
+#[derive(Debug, Serialize)]
+struct ServerHelloDto {
+    server_public_key: String, // emoji encoded
+    grace_epochs: usize,       // how many retired epochs the client should keep too
+}
+
+pub async fn post(mut req: Request<ServerWiring>) -> Result {
+    // the client generated its own ephemeral keypair, we only ever see the public half
+    // proving the client holds this session's csrf token stands in for the usual anti forgery check
+
+    let token = req
+        .header("x-handshake-token")
+        .map(|values| values.last().as_str().to_owned());
+
+    let verification = match token {
+        Some(token) => req
+            .state()
+            .services
+            .jwt_util
+            .verify_csrf_token(&token, req.session().id())
+            .is_ok(),
+        None => false,
+    };
+
+    if !verification {
+        tide::log::info!("Rejecting handshake without a valid session token");
+        return Ok(Response::builder(403).build());
+    }
+
+    let hello: ClientHelloDto = req.body_json().await?;
+
+    let keyring = match SharedKeyring::establish_with_client(&hello.client_public_key).await {
+        Ok(keyring) => keyring,
+        Err(_) => {
+            tide::log::info!("Rejecting handshake with a malformed client public key");
+            return Ok(Response::builder(400).build());
+        }
+    };
+
+    let sealed = keyring.seal(&req.state().config).expect("encrypted keyring");
+    let session = req.session_mut();
+    session.insert("keyring", sealed)?;
+    // a fresh keyring starts a fresh replay window
+    session.remove(REPLAY_SESSION_KEY);
+
+    let response = Response::builder(200)
+        .body(json!(ServerHelloDto {
+            server_public_key: keyring.broadcast,
+            grace_epochs: req.state().config.keyring_grace_epochs,
+        }))
+        .build();
+
+    Ok(response)
+}
@@ -1,7 +1,6 @@
 
 use tide::{http::mime, Request, Response, Result};
 use crate::wiring::ServerWiring;
-use crate::util::encryption::SharedKeyring;
 
 // for now - maybe forever:
 // just serve the relative dist folder index as an iframe from the rs-wasm sibling project
@@ -24,11 +23,9 @@ pub async fn get(req: Request<ServerWiring>) -> Result {
 
     let session_id = req.session().id();
 
-    let secrets = req.ext::<SharedKeyring>().expect("do not have session secrets...");
-
     let config = &req.state().config;
 
-    let csrf_token = jwt_util.sign_csrf_token(session_id, &secrets).unwrap();
+    let csrf_token = jwt_util.sign_csrf_token(session_id).unwrap();
     
     let origin_domain = String::from(&config.domain);
 
@@ -1,5 +1,6 @@
 
 use tide::{http::mime, Request, Response, Result};
+use crate::middleware::headers::CspNonce;
 use crate::wiring::ServerWiring;
 
 // for now - maybe forever:
@@ -9,11 +10,15 @@ use askama::Template; // bring trait in scope
 
 #[derive(Template)] // this will generate the code...
 #[template(path = "index.html.j2")] // using the template in this path, relative
-struct IndexView {}
+struct IndexView {
+    csp_nonce: String,
+}
 
-pub async fn get(_req: Request<ServerWiring>) -> Result {
+pub async fn get(req: Request<ServerWiring>) -> Result {
 
-    let view = IndexView {};
+    let view = IndexView {
+        csp_nonce: CspNonce::of(&req),
+    };
 
     let response_body = view.render().unwrap();
 
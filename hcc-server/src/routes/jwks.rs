@@ -0,0 +1,26 @@
+use tide::prelude::*;
+use tide::{http::mime, Request, Response, Result};
+
+use crate::wiring::ServerWiring;
+
+// public keys for anything else we run that wants to check an hcc token
+// served in the clear, outside the frame and its keyring
+pub async fn get(req: Request<ServerWiring>) -> Result {
+    let secrets = &req.state().services.jwt_util.secrets;
+
+    let mut keys = vec![];
+    for key in secrets.live_keys() {
+        match key.to_jwk() {
+            Ok(jwk) => keys.push(jwk),
+            Err(e) => tide::log::warn!("Leaving jwt key {} out of the jwks: {}", key.kid, e),
+        }
+    }
+
+    let response = Response::builder(200)
+        .content_type(mime::JSON)
+        .header("cache-control", "public, max-age=300")
+        .body(json!({ "keys": keys }))
+        .build();
+
+    Ok(response)
+}
@@ -1,65 +1,35 @@
 use tide::prelude::*;
-use tide::{http::mime, Redirect, Request, Response, Result};
+use tide::{Redirect, Request, Result};
 
 use crate::dao;
+use crate::middleware::fragment::EncryptedFragment;
 use crate::util::emoji;
-use crate::util::encryption;
 use crate::util::password::PasswordUtil;
 use crate::wiring::ServerWiring;
 
 use domain::session::SessionUser;
+use hcc_common::media::{AudioMedia, ImageMedia, MediaType, TextMedia};
 
 use tinytemplate::TinyTemplate;
 
 use askama::Template; // bring trait in scope
 
-#[derive(Debug)]
-enum MediaType {
-    Image,
-    Text,
-    Audio,
-}
-
-impl std::fmt::Display for MediaType {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:?}", self)
-    }
-}
 trait MediaRenderer {
     fn render_json(&self) -> String;
 }
 
-#[derive(Serialize, Deserialize)]
-struct ImageMedia {
-    url: String,
-}
-
 impl MediaRenderer for ImageMedia {
     fn render_json(&self) -> String {
         serde_json::to_string(self).unwrap()
     }
 }
 
-#[derive(Serialize, Deserialize)]
-struct TextMedia {
-    body: String,
-}
-
 impl MediaRenderer for TextMedia {
     fn render_json(&self) -> String {
         serde_json::to_string(self).unwrap()
     }
 }
 
-#[derive(Serialize, Deserialize)]
-struct AudioMedia {
-    title: String,
-    duration: i32,
-    khz: i32,
-    kbps: i32,
-    url: String,
-}
-
 impl MediaRenderer for AudioMedia {
     fn render_json(&self) -> String {
         serde_json::to_string(self).unwrap()
@@ -138,7 +108,7 @@ fn render_bundle(bundle: &MediaNodeBundle) -> MediaNodeHtml {
 
     let media_context = MediaNodeViewModel {
         slug: bundle.slug.to_owned(),
-        medium: bundle.media_type.to_string().to_ascii_lowercase(),
+        medium: bundle.media_type.name().to_owned(),
         media: media_json_base64,
     };
 
@@ -296,24 +266,12 @@ impl FakeMediaDatabase {
     }
 }
 
-pub async fn get(req: Request<ServerWiring>) -> Result {
+pub async fn get(_req: Request<ServerWiring>) -> Result {
     let rendered_media: Vec<MediaNodeHtml> = FakeMediaDatabase::get_media();
 
     let view_context = ListGetViewModel {
         media: rendered_media,
     };
 
-    let secrets: &encryption::SharedKeyring = req.ext().unwrap();
-
-    let encrypted_body = secrets
-        .encrypt_broadcast_emoji(&view_context.render().unwrap())
-        .await
-        .unwrap()
-        .message;
-
-    let response = Response::builder(200)
-        .content_type(mime::HTML)
-        .body_string(encrypted_body)
-        .build();
-    Ok(response)
+    Ok(EncryptedFragment::render(&view_context)?.into())
 }
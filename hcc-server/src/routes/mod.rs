@@ -3,7 +3,11 @@ pub mod index;
 
 pub mod handshake;
 
+pub mod jwks;
+
+pub mod admin;
 pub mod app;
+pub mod password;
 pub mod user;
 
 pub mod media;
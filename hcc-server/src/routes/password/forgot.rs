@@ -0,0 +1,81 @@
+use tide::prelude::*;
+use tide::{Redirect, Request, Result};
+
+use crate::dao;
+use crate::middleware::fragment::EncryptedFragment;
+use crate::util::encryption;
+use crate::wiring::ServerWiring;
+use domain::session::SessionUser;
+
+use super::reset;
+
+use askama::Template; // bring trait in scope
+
+#[derive(Template)]
+#[template(path = "password/forgot.html.j2")]
+struct ForgotGetViewModel {}
+
+#[derive(Template)]
+#[template(path = "password/forgot_sent.html.j2")]
+struct ForgotSentViewModel {}
+
+pub async fn get(req: Request<ServerWiring>) -> Result {
+    let maybe_user: Option<&SessionUser> = req.ext();
+
+    if maybe_user.is_some() {
+        Ok(Redirect::new("/app").into())
+    } else {
+        let view_context = ForgotGetViewModel {};
+
+        Ok(EncryptedFragment::render(&view_context)?.into())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ForgotPasswordDto {
+    email: String, // emoji encrypted fields
+}
+
+pub async fn post(mut req: Request<ServerWiring>) -> Result {
+    let form = {
+        let encrypted_form: ForgotPasswordDto = req.body_form().await?;
+
+        let secrets: &encryption::SharedKeyring = req.ext().unwrap();
+
+        let encrypted_email = encryption::UserEncryptedEmojiMessage {
+            sender: secrets.user.to_owned(),
+            message: encrypted_form.email,
+        };
+
+        ForgotPasswordDto {
+            email: encrypted_email.decrypt(secrets).map_err(|e| tide::Error::new(403, e))?,
+        }
+    };
+
+    let email = form.email.trim();
+    let wiring: &ServerWiring = &req.state();
+
+    let search = dao::user::UserDao::find_by_email(wiring, email.as_bytes())
+        .await
+        .unwrap();
+
+    // always answer the same way so this form can't be used to find out who is a member,
+    // the mail goes out in the background so a slow smtp server doesn't give it away either
+    match search {
+        Some(u) if u.active => {
+            let wiring = wiring.clone();
+            let email = String::from(email);
+            async_std::task::spawn(async move {
+                let sent = reset::send_reset(&wiring, &u, &email).await;
+                if sent.is_err() {
+                    tide::log::error!("Failed to send password reset email: {:?}", sent.err());
+                }
+            });
+        }
+        _ => {}
+    }
+
+    let view_context = ForgotSentViewModel {};
+
+    Ok(EncryptedFragment::render(&view_context)?.into())
+}
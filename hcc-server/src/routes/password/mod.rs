@@ -0,0 +1,13 @@
+pub mod forgot;
+pub mod reset;
+
+use orion::hazardous::hash::blake2::blake2b::Hasher;
+
+pub fn reset_binding(encoded_pwhash: &str) -> String {
+    // reset tokens carry a fingerprint of the password hash they were minted against
+    // once the password changes the fingerprint no longer matches, so each link only works once
+    let digest = Hasher::Blake2b256
+        .digest(encoded_pwhash.as_bytes())
+        .expect("blake digest");
+    base64::encode_config(digest.as_ref(), base64::URL_SAFE_NO_PAD)
+}
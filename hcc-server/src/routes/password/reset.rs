@@ -0,0 +1,203 @@
+use tide::prelude::*;
+use tide::{Redirect, Request, Response, Result};
+
+use crate::dao;
+use crate::mailer::{Email, MailerError};
+use crate::middleware::fragment::EncryptedFragment;
+use crate::util::emoji;
+use crate::util::encryption;
+use crate::wiring::ServerWiring;
+
+use domain::sea_orm::entities::user_email_password;
+
+use super::reset_binding;
+
+use askama::Template; // bring trait in scope
+
+const RESET_ACTION: &str = "reset-password";
+const RESET_TTL_MINUTES: i64 = 30;
+
+pub const RESET_SESSION_KEY: &str = "password_reset";
+
+#[derive(Template)]
+#[template(path = "email/reset.txt.j2")]
+struct ResetEmailView {
+    link: String,
+    ttl_minutes: i64,
+}
+
+#[derive(Template)]
+#[template(path = "password/reset.html.j2")]
+struct ResetGetViewModel {
+    error: String,
+}
+
+#[derive(Template)]
+#[template(path = "password/reset_done.html.j2")]
+struct ResetDoneViewModel {}
+
+pub async fn send_reset(
+    wiring: &ServerWiring,
+    user: &user_email_password::Model,
+    email: &str,
+) -> std::result::Result<(), MailerError> {
+    let binding = reset_binding(&user.password);
+
+    let token = wiring
+        .services
+        .jwt_util
+        .sign_action_token(
+            RESET_ACTION,
+            user.id,
+            RESET_TTL_MINUTES * 60,
+            Some(&binding),
+        )
+        .map_err(|e| MailerError(e.to_string()))?;
+
+    let view = ResetEmailView {
+        link: format!("{}/password/reset?token={}", wiring.config.domain, token),
+        ttl_minutes: RESET_TTL_MINUTES,
+    };
+
+    wiring
+        .services
+        .mailer
+        .send(Email {
+            to: String::from(email),
+            subject: String::from("reset your holy charisma password"),
+            body: view.render().unwrap(),
+        })
+        .await
+}
+
+async fn verify_reset_token(
+    wiring: &ServerWiring,
+    token: &str,
+) -> Option<user_email_password::Model> {
+    let claims = wiring
+        .services
+        .jwt_util
+        .verify_action_token(token, RESET_ACTION)
+        .ok()?;
+
+    let uid = claims["uid"].as_i64()?;
+
+    let user = dao::user::UserDao::find_by_id(wiring, uid as i32)
+        .await
+        .ok()??;
+
+    if claims["bnd"].as_str()? == reset_binding(&user.password) {
+        Some(user)
+    } else {
+        None
+    }
+}
+
+async fn render_reset(error: &str) -> Result {
+    let view_context = ResetGetViewModel {
+        error: String::from(error),
+    };
+
+    Ok(EncryptedFragment::render(&view_context)?.into())
+}
+
+#[derive(Debug, Deserialize)]
+struct ResetQuery {
+    token: Option<String>,
+}
+
+pub async fn get(mut req: Request<ServerWiring>) -> Result {
+    let query: ResetQuery = req.query()?;
+
+    match query.token {
+        Some(token) => {
+            // opened straight from the email: park the token on the session
+            // and send them home, the frame picks it up from /handshake with a keyring in hand
+            if verify_reset_token(req.state(), &token).await.is_none() {
+                tide::log::info!("Rejecting bad password reset token");
+                return Ok(Response::builder(403).build());
+            }
+
+            let session = req.session_mut();
+            session.insert(RESET_SESSION_KEY, token).unwrap();
+
+            Ok(Redirect::new("/").into())
+        }
+        None => {
+            let pending: Option<String> = req.session().get(RESET_SESSION_KEY);
+            if pending.is_some() {
+                render_reset("").await
+            } else {
+                Ok(Redirect::new("/login").into())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ResetPasswordDto {
+    password: String, // emoji encrypted fields
+    password_bcrypt: String, // the client renames password_confirm on the way out
+}
+
+pub async fn post(mut req: Request<ServerWiring>) -> Result {
+    let form = {
+        let encrypted_form: ResetPasswordDto = req.body_form().await?;
+
+        let secrets: &encryption::SharedKeyring = req.ext().unwrap();
+
+        let sender = &secrets.user;
+
+        let decrypt = |message: String| {
+            encryption::UserEncryptedEmojiMessage {
+                sender: sender.to_owned(),
+                message: message,
+            }
+            .decrypt(secrets)
+            .map_err(|e| tide::Error::new(403, e))
+        };
+
+        ResetPasswordDto {
+            password: decrypt(encrypted_form.password)?,
+            password_bcrypt: decrypt(encrypted_form.password_bcrypt)?,
+        }
+    };
+
+    let pending: Option<String> = req.session().get(RESET_SESSION_KEY);
+
+    let token = match pending {
+        Some(token) => token,
+        None => return Ok(Response::builder(403).build()),
+    };
+
+    if form.password.is_empty() || form.password != form.password_bcrypt {
+        return render_reset("Passwords do not match.").await;
+    }
+
+    let user = match verify_reset_token(req.state(), &token).await {
+        Some(user) => user,
+        None => {
+            req.session_mut().remove(RESET_SESSION_KEY);
+            return render_reset("This reset link has expired or was already used.").await;
+        }
+    };
+
+    let pwhash = emoji::encode(
+        &req.state()
+            .services
+            .password_util
+            .into_password_hash(&form.password)
+            .await?,
+    );
+
+    dao::user::UserDao::set_password(req.state(), user.id, &pwhash).await?;
+
+    // whoever knew the old password shouldn't stay logged in anywhere
+    dao::session::SessionDao::revoke_all(req.state(), user.id).await?;
+
+    req.session_mut().remove(RESET_SESSION_KEY);
+
+    let view_context = ResetDoneViewModel {};
+
+    Ok(EncryptedFragment::render(&view_context)?.into())
+}
@@ -0,0 +1,287 @@
+use tide::prelude::*;
+use tide::{Redirect, Request, Response, Result};
+
+use crate::dao;
+use crate::dao::login_attempt::{LoginAttemptDao, SCOPE_EMAIL};
+use crate::middleware::fragment::EncryptedFragment;
+use crate::util::emoji;
+use crate::util::encryption;
+use crate::wiring::ServerWiring;
+use domain::session::SessionUser;
+
+use askama::Template; // bring trait in scope
+
+// typed out by the member so a stray click can't delete an account
+const DELETE_CONFIRMATION: &str = "delete my account";
+
+#[derive(Template)]
+#[template(path = "user/account.html.j2")]
+struct AccountViewModel {
+    error: String,
+    delete_confirmation: String,
+}
+
+#[derive(Template)]
+#[template(path = "user/export.html.j2")]
+struct ExportViewModel {
+    json: String,
+    data_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportDto {
+    password: String, // emoji encrypted fields
+}
+
+#[derive(Debug, Deserialize)]
+struct DeactivateDto {
+    password: String, // emoji encrypted fields
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteDto {
+    password: String, // emoji encrypted fields
+    confirmation: String,
+}
+
+async fn render_account(error: &str) -> Result {
+    let view = AccountViewModel {
+        error: String::from(error),
+        delete_confirmation: String::from(DELETE_CONFIRMATION),
+    };
+
+    Ok(EncryptedFragment::render(&view)?.into())
+}
+
+// a field that doesn't open, or was replayed, is refused with a 403 before the route acts on it
+fn decrypt_field(req: &Request<ServerWiring>, message: String) -> Result<String> {
+    let secrets: &encryption::SharedKeyring = req.ext().unwrap();
+
+    encryption::UserEncryptedEmojiMessage {
+        sender: secrets.user.to_owned(),
+        message: message,
+    }
+    .decrypt(secrets)
+    .map_err(|e| tide::Error::new(403, e))
+}
+
+async fn password_matches(wiring: &ServerWiring, uid: i32, password: &str) -> Result<bool> {
+    let found = dao::user::UserDao::find_by_id(wiring, uid).await?;
+
+    match found {
+        Some(u) => Ok(wiring
+            .services
+            .password_util
+            .verify_hashed_bytes(password, &emoji::decode(&u.password))
+            .await
+            .is_valid()),
+        None => Ok(false),
+    }
+}
+
+/// Ends every session including this one and sends the frame to the goodbye page.
+async fn sign_out_everywhere(req: &mut Request<ServerWiring>, uid: i32) -> Result {
+    dao::session::SessionDao::revoke_all(req.state(), uid).await?;
+
+    req.session_mut().destroy();
+
+    Ok(Response::builder(200)
+        .header("HX-Redirect", "/hcc/disconnect.html")
+        .build())
+}
+
+pub async fn get(req: Request<ServerWiring>) -> Result {
+    match req.ext::<SessionUser>() {
+        Some(_) => render_account("").await,
+        None => Ok(Redirect::new("/login").into()),
+    }
+}
+
+pub async fn post_deactivate(mut req: Request<ServerWiring>) -> Result {
+    let user = match req.ext::<SessionUser>() {
+        Some(user) => user.to_owned(),
+        None => return Ok(Redirect::new("/login").into()),
+    };
+
+    let form: DeactivateDto = req.body_form().await?;
+    let password = decrypt_field(&req, form.password)?;
+
+    if !password_matches(req.state(), user.uid, &password).await? {
+        return render_account("That password is incorrect.").await;
+    }
+
+    dao::user::UserDao::set_active(req.state(), user.uid, false).await?;
+    tide::log::info!("uid {} deactivated their account", user.uid);
+
+    sign_out_everywhere(&mut req, user.uid).await
+}
+
+pub async fn post_delete(mut req: Request<ServerWiring>) -> Result {
+    let user = match req.ext::<SessionUser>() {
+        Some(user) => user.to_owned(),
+        None => return Ok(Redirect::new("/login").into()),
+    };
+
+    let form: DeleteDto = req.body_form().await?;
+    let password = decrypt_field(&req, form.password)?;
+    let confirmation = decrypt_field(&req, form.confirmation)?;
+
+    if confirmation.trim() != DELETE_CONFIRMATION {
+        return render_account(&format!("Type \"{}\" to confirm.", DELETE_CONFIRMATION)).await;
+    }
+
+    if !password_matches(req.state(), user.uid, &password).await? {
+        return render_account("That password is incorrect.").await;
+    }
+
+    // the session rows go with the account, so this also signs out every other device
+    dao::user::UserDao::delete_member(req.state(), user.uid).await?;
+    tide::log::info!("uid {} deleted their account", user.uid);
+
+    req.session_mut().destroy();
+
+    Ok(Response::builder(200)
+        .header("HX-Redirect", "/hcc/disconnect.html")
+        .build())
+}
+
+fn timestamp(value: Option<sea_orm::prelude::DateTimeWithTimeZone>) -> serde_json::Value {
+    value
+        .map(|v| json!(v.to_rfc3339()))
+        .unwrap_or(serde_json::Value::Null)
+}
+
+async fn export_member(wiring: &ServerWiring, uid: i32) -> Result<serde_json::Value> {
+    let account = dao::user::UserDao::find_by_id(wiring, uid)
+        .await?
+        .ok_or_else(|| tide::Error::from_str(404, "no such member"))?;
+
+    // the email is only stored encrypted, the owner gets it back in the clear
+    let email = encryption::open_with_key(&wiring.config.encryption_key_emoji, &account.email)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok());
+
+    let attributes = dao::user_attributes::UserAttributesDao::find_by_uid(wiring, uid)
+        .await?
+        .map(|a| {
+            json!({
+                "display": a.display,
+                "created_at": a.created_at.to_rfc3339(),
+                "last_login": timestamp(a.last_login),
+                "last_updated": a.last_updated.to_rfc3339(),
+                "settings": dao::user_attributes::UserAttributesDao::settings_of(&a),
+                "invite_quota": a.invite_quota,
+            })
+        });
+
+    let roles: Vec<&str> = dao::role::RoleDao::roles_for_user(wiring, uid)
+        .await?
+        .iter()
+        .map(|r| r.name())
+        .collect();
+
+    let two_factor = dao::totp::TotpDao::find_by_uid(wiring, uid)
+        .await?
+        .map(|t| {
+            let recovery_codes: Vec<String> =
+                serde_json::from_str(&t.recovery_codes).unwrap_or_default();
+            // the secret stays behind, a leaked archive must not be a second factor
+            json!({
+                "enabled": t.confirmed,
+                "created_at": t.created_at.to_rfc3339(),
+                // only hashes of the recovery codes are kept, so just the count is useful
+                "recovery_codes_remaining": recovery_codes.len(),
+            })
+        });
+
+    let sessions: Vec<serde_json::Value> = dao::session::SessionDao::list_for_user(wiring, uid)
+        .await?
+        .into_iter()
+        .map(|s| {
+            json!({
+                "user_agent": s.user_agent,
+                "ip": s.ip,
+                "created_at": s.created_at.to_rfc3339(),
+                "last_seen": s.last_seen.to_rfc3339(),
+            })
+        })
+        .collect();
+
+    let invites: Vec<serde_json::Value> = dao::invite::InviteDao::list_by_inviter(wiring, uid)
+        .await?
+        .into_iter()
+        .map(|i| {
+            json!({
+                "role": i.role,
+                "max_uses": i.max_uses,
+                "uses": i.uses,
+                "expires_at": timestamp(i.expires_at),
+                "created_at": i.created_at.to_rfc3339(),
+            })
+        })
+        .collect();
+
+    let invited_by = dao::invite::InviteDao::find_redeemed_by(wiring, uid)
+        .await?
+        .map(|(redemption, invite)| {
+            json!({
+                "inviter_uid": invite.inviter_uid,
+                "role": invite.role,
+                "redeemed_at": redemption.redeemed_at.to_rfc3339(),
+            })
+        });
+
+    let login_failures = LoginAttemptDao::find(wiring, SCOPE_EMAIL, &account.email_hash)
+        .await?
+        .map(|a| {
+            json!({
+                "failures": a.failures,
+                "last_failure": a.last_failure.to_rfc3339(),
+                "locked_until": timestamp(a.locked_until),
+            })
+        });
+
+    // the password hash is left out on purpose, it is of no use to the owner
+    Ok(json!({
+        "exported_at": chrono::Utc::now().to_rfc3339(),
+        "account": {
+            "uid": account.id,
+            "email": email,
+            "active": account.active,
+            "email_verified_at": timestamp(account.email_verified_at),
+        },
+        "attributes": attributes,
+        "roles": roles,
+        "two_factor": two_factor,
+        "sessions": sessions,
+        "invites": invites,
+        "invited_by": invited_by,
+        "login_failures": login_failures,
+    }))
+}
+
+pub async fn post_export(mut req: Request<ServerWiring>) -> Result {
+    let user = match req.ext::<SessionUser>() {
+        Some(user) => user.to_owned(),
+        None => return Ok(Redirect::new("/login").into()),
+    };
+
+    // a session left open on a shared machine shouldn't be enough to walk off with everything
+    let form: ExportDto = req.body_form().await?;
+    let password = decrypt_field(&req, form.password)?;
+
+    if !password_matches(req.state(), user.uid, &password).await? {
+        return render_account("That password is incorrect.").await;
+    }
+
+    let export = export_member(req.state(), user.uid).await?;
+    let json = serde_json::to_string_pretty(&export)?;
+
+    // the archive rides inside the encrypted fragment, the link just saves it client side
+    let view = ExportViewModel {
+        data_uri: format!("data:application/json;base64,{}", base64::encode(&json)),
+        json: json,
+    };
+
+    Ok(EncryptedFragment::render(&view)?.into())
+}
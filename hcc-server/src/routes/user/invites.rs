@@ -0,0 +1,160 @@
+use tide::prelude::*;
+use tide::{Redirect, Request, Result};
+
+use crate::dao::invite::InviteDao;
+use crate::middleware::fragment::EncryptedFragment;
+use crate::util::encryption;
+use crate::wiring::ServerWiring;
+use domain::permission::{Permission, Role};
+use domain::session::SessionUser;
+
+use askama::Template; // bring trait in scope
+
+struct InviteRow {
+    code: String,
+    role: String,
+    uses: String,
+    expires_at: String,
+    is_redeemable: bool,
+}
+
+#[derive(Template)]
+#[template(path = "user/invites.html.j2")]
+struct InvitesViewModel {
+    invites: Vec<InviteRow>,
+    // admins pick the role and have no quota
+    can_assign_roles: bool,
+    roles: Vec<String>,
+    remaining: i32,
+    error: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateInviteDto {
+    role: String, // emoji encrypted fields
+    max_uses: String,
+    expires_days: String,
+}
+
+fn can_assign_roles(user: &SessionUser) -> bool {
+    user.has_permission(Permission::ManageUsers)
+}
+
+async fn remaining_quota(wiring: &ServerWiring, user: &SessionUser) -> Result<i32> {
+    let quota = InviteDao::quota_for(wiring, user.uid).await?;
+    let used = InviteDao::count_live_by_inviter(wiring, user.uid).await? as i32;
+    Ok((quota - used).max(0))
+}
+
+async fn render_invites(req: &Request<ServerWiring>, user: &SessionUser, error: &str) -> Result {
+    let wiring: &ServerWiring = req.state();
+
+    let mut invites = vec![];
+    for found in InviteDao::list_by_inviter(wiring, user.uid).await? {
+        let code = wiring.services.jwt_util.sign_invite_token(
+            found.id,
+            found.inviter_uid,
+            found.expires_at.map(|until| until.timestamp()),
+        )?;
+
+        invites.push(InviteRow {
+            code: code,
+            is_redeemable: InviteDao::is_redeemable(&found),
+            role: found.role,
+            uses: match found.max_uses {
+                Some(max) => format!("{} / {}", found.uses, max),
+                None => format!("{} / unlimited", found.uses),
+            },
+            expires_at: found
+                .expires_at
+                .map(|until| until.to_rfc3339())
+                .unwrap_or_else(|| String::from("never")),
+        });
+    }
+
+    let view = InvitesViewModel {
+        invites: invites,
+        can_assign_roles: can_assign_roles(user),
+        roles: Role::ALL.iter().map(|r| String::from(r.name())).collect(),
+        remaining: remaining_quota(wiring, user).await?,
+        error: String::from(error),
+    };
+
+    Ok(EncryptedFragment::render(&view)?.into())
+}
+
+pub async fn get(req: Request<ServerWiring>) -> Result {
+    match req.ext::<SessionUser>() {
+        Some(user) => {
+            let user = user.to_owned();
+            render_invites(&req, &user, "").await
+        }
+        None => Ok(Redirect::new("/login").into()),
+    }
+}
+
+pub async fn post(mut req: Request<ServerWiring>) -> Result {
+    let user = match req.ext::<SessionUser>() {
+        Some(user) => user.to_owned(),
+        None => return Ok(Redirect::new("/login").into()),
+    };
+
+    let form = {
+        let encrypted_form: CreateInviteDto = req.body_form().await?;
+
+        let secrets: &encryption::SharedKeyring = req.ext().unwrap();
+
+        let sender = &secrets.user;
+
+        let decrypt = |message: String| {
+            encryption::UserEncryptedEmojiMessage {
+                sender: sender.to_owned(),
+                message: message,
+            }
+            .decrypt(secrets)
+            .map_err(|e| tide::Error::new(403, e))
+        };
+
+        CreateInviteDto {
+            role: decrypt(encrypted_form.role)?,
+            max_uses: decrypt(encrypted_form.max_uses)?,
+            expires_days: decrypt(encrypted_form.expires_days)?,
+        }
+    };
+
+    let wiring: &ServerWiring = req.state();
+
+    let role = if can_assign_roles(&user) {
+        match Role::from_name(form.role.trim()) {
+            Some(role) => role,
+            None => return render_invites(&req, &user, "Please pick a role.").await,
+        }
+    } else {
+        if remaining_quota(wiring, &user).await? < 1 {
+            return render_invites(&req, &user, "You have no invites left.").await;
+        }
+        Role::Member
+    };
+
+    // blank means unlimited
+    let max_uses = match form.max_uses.trim() {
+        "" => None,
+        value => match value.parse::<i32>() {
+            Ok(max) if max > 0 => Some(max),
+            _ => return render_invites(&req, &user, "Uses must be a positive number.").await,
+        },
+    };
+
+    let expires_at = match form.expires_days.trim() {
+        "" => None,
+        value => match value.parse::<i64>() {
+            Ok(days) if days > 0 => Some((chrono::Utc::now() + chrono::Duration::days(days)).into()),
+            _ => return render_invites(&req, &user, "Expiry must be a positive number of days.").await,
+        },
+    };
+
+    let created = InviteDao::create(wiring, user.uid, role, max_uses, expires_at).await?;
+    tide::log::info!("uid {} created invite {} for role {}", user.uid, created.id, created.role);
+
+    render_invites(&req, &user, "").await
+}
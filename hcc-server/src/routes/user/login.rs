@@ -1,18 +1,83 @@
 use tide::prelude::*;
-use tide::{http::mime, Redirect, Request, Response, Result};
+use tide::{Redirect, Request, Response, Result};
 
 use crate::dao;
+use crate::dao::login_attempt::{LoginAttemptDao, SCOPE_ADDRESS, SCOPE_EMAIL};
+use crate::middleware::fragment::EncryptedFragment;
+use crate::util::backoff::{ACCOUNT_BACKOFF, ADDRESS_BACKOFF};
 use crate::util::emoji;
 use crate::util::encryption;
-use crate::util::password::PasswordUtil;
+use crate::util::password::PasswordVerification;
+use crate::util::request;
 use crate::wiring::ServerWiring;
-use domain::session::SessionUser;
+use domain::session::{PendingSecondFactor, SessionUser};
+
+use super::totp;
 
 use askama::Template; // bring trait in scope
 
 #[derive(Template)] // this will generate the code...
 #[template(path = "user/login.html.j2")] // using the template in this path, relative
-struct LoginGetView {}
+struct LoginGetView {
+    error: String,
+}
+
+#[derive(Template)]
+#[template(path = "user/login_totp.html.j2")]
+struct LoginSecondFactorView {
+    error: String,
+}
+
+pub const PENDING_SECOND_FACTOR_KEY: &str = "pending_second_factor";
+
+// how long the password step stays good for while we wait on the authenticator code
+const SECOND_FACTOR_TTL_SECONDS: i64 = 5 * 60;
+
+async fn render_second_factor(error: &str) -> Result {
+    let view = LoginSecondFactorView {
+        error: String::from(error),
+    };
+
+    Ok(EncryptedFragment::render(&view)?.into())
+}
+
+async fn render_login(error: &str) -> Result {
+    let login_get_view = LoginGetView {
+        error: String::from(error),
+    };
+
+    Ok(EncryptedFragment::render(&login_get_view)?.into())
+}
+
+fn lockout_message(seconds: i64) -> String {
+    let minutes = (seconds + 59) / 60;
+    format!(
+        "Too many failed attempts. Try again in {} minute{}.",
+        minutes,
+        if minutes == 1 { "" } else { "s" }
+    )
+}
+
+/// Logs the user into this session and records the device so it shows up in their session list.
+async fn begin_session(req: &mut Request<ServerWiring>, user: SessionUser) -> Result<()> {
+    let user_agent = req
+        .header("user-agent")
+        .map(|values| values.last().as_str().to_owned())
+        .unwrap_or_default();
+    let ip = request::client_address(req);
+    let session_id = String::from(req.session().id());
+
+    dao::session::SessionDao::record(req.state(), user.uid, &session_id, &user_agent, &ip).await?;
+    dao::user_attributes::UserAttributesDao::record_login(req.state(), user.uid).await?;
+
+    let _res = req.session_mut().insert("user", user).unwrap();
+
+    Ok(())
+}
+
+fn email_index(wiring: &ServerWiring, plaintext_email: &[u8]) -> String {
+    dao::user::UserDao::email_index(&wiring.config, plaintext_email)
+}
 
 pub async fn get(req: Request<ServerWiring>) -> Result {
     let maybe_user: Option<&SessionUser> = req.ext();
@@ -20,20 +85,7 @@ pub async fn get(req: Request<ServerWiring>) -> Result {
     if maybe_user.is_some() {
         Ok(Redirect::new("/app").into())
     } else {
-        let login_get_view = LoginGetView {};
-        let secrets: &encryption::SharedKeyring = req.ext().unwrap();
-
-        let encrypted_body = secrets
-            .encrypt_broadcast_emoji(&login_get_view.render().unwrap())
-            .await
-            .unwrap()
-            .message;
-
-        let response = Response::builder(200)
-            .content_type(mime::HTML)
-            .body_string(encrypted_body)
-            .build();
-        Ok(response)
+        render_login("").await
     }
 }
 
@@ -61,61 +113,181 @@ pub async fn post(mut req: Request<ServerWiring>) -> Result {
             message: encrypted_form.password,
         };
 
-        UserLoginDto {
-            email: encrypted_email.decrypt(secrets).unwrap(),
-            password: encrypted_password.decrypt(secrets).unwrap(),
+        // a replayed login form is refused outright, it never counts as a failed attempt
+        match (encrypted_email.decrypt(secrets), encrypted_password.decrypt(secrets)) {
+            (Ok(email), Ok(password)) => UserLoginDto { email, password },
+            (Err(e), _) | (_, Err(e)) => {
+                tide::log::info!("Rejecting login form: {}", e);
+                return Ok(Response::builder(403).body_string(e.to_string()).build());
+            }
         }
     };
 
     let plaintext_email = &form.email.as_bytes();
     let wiring: &ServerWiring = &req.state();
+
+    // failures are tracked against the blind index, never the plaintext email
+    let indexed_email = email_index(wiring, plaintext_email);
+    let address = request::client_address(&req);
+
+    // check lockouts before the password so a locked account can't be probed
+    let account_lock = LoginAttemptDao::locked_for(wiring, SCOPE_EMAIL, &indexed_email).await?;
+    let address_lock = LoginAttemptDao::locked_for(wiring, SCOPE_ADDRESS, &address).await?;
+
+    if let Some(seconds) = account_lock.max(address_lock) {
+        tide::log::info!("Refusing login during lockout");
+        return render_login(&lockout_message(seconds)).await;
+    }
+
     let search = dao::user::UserDao::find_by_email(wiring, plaintext_email)
         .await
         .unwrap();
 
-    if search.is_none() {
-        let response = Response::builder(403).build();
-        Ok(response)
-    } else {
-        let u = search.unwrap();
+    let password_util = &wiring.services.password_util;
 
-        let user_pwhash = emoji::decode(&u.password);
-        let expected_email_hash = u.email_hash;
+    let verified = match search {
+        Some(u) => {
+            let verification = password_util
+                .verify_hashed_bytes(&form.password, &emoji::decode(&u.password))
+                .await;
+            if verification.is_valid() {
+                Some((u, verification))
+            } else {
+                None
+            }
+        }
+        None => None,
+    };
 
-        let form_email_hash = {
-            encryption::DeterministicEmojiEncrypt::new(
-                &req.state().config.encryption_key_emoji,
-                &req.state().config.encryption_salt_emoji,
-                plaintext_email.to_owned(),
+    match verified {
+        None => {
+            tide::log::info!("Failed login attempt");
+            LoginAttemptDao::record_failure(wiring, SCOPE_EMAIL, &indexed_email, &ACCOUNT_BACKOFF)
+                .await?;
+            LoginAttemptDao::record_failure(wiring, SCOPE_ADDRESS, &address, &ADDRESS_BACKOFF)
+                .await?;
+            render_login("Email or password is incorrect.").await
+        }
+        Some((u, _)) if u.email_verified_at.is_none() => {
+            tide::log::info!("Refusing login for unverified account");
+            render_login(
+                "This account is not verified yet. Check your email for a verification link.",
             )
+            .await
         }
-        .unwrap();
-
-        let email_is_valid = form_email_hash.encrypted == expected_email_hash;
-
-        let pass_is_valid =
-            email_is_valid && { PasswordUtil::verify_hashed_bytes(&form.password, &user_pwhash) };
+        Some((u, _)) if !u.active => {
+            tide::log::info!("Refusing login for inactive account");
+            render_login("This account is not active. Ask an admin to turn it back on.").await
+        }
+        Some((u, verification)) => {
+            LoginAttemptDao::clear(wiring, SCOPE_EMAIL, &indexed_email).await?;
 
-        if email_is_valid && pass_is_valid {
-            let super_email = &wiring.config.super_user_email.as_bytes();
+            // this is the only time we see the plaintext, so old bcrypt or weaker argon2 hashes get upgraded here
+            if verification == PasswordVerification::ValidNeedsRehash {
+                let pwhash = emoji::encode(&password_util.into_password_hash(&form.password).await?);
+                dao::user::UserDao::set_password(wiring, u.id, &pwhash).await?;
+                tide::log::info!("Upgraded password hash for uid: {}", u.id);
+            }
 
-            let is_admin_user = plaintext_email == super_email;
+            let needs_second_factor = dao::totp::TotpDao::is_enabled(wiring, u.id).await?;
 
-            let session = req.session_mut();
+            let display = dao::user_attributes::UserAttributesDao::find_by_uid(wiring, u.id)
+                .await?
+                .map(|attributes| attributes.display)
+                .unwrap_or_default();
 
             let user = SessionUser {
+                uid: u.id,
                 email: String::from(&form.email),
-                is_admin: is_admin_user,
+                display: display,
+                roles: dao::role::RoleDao::roles_for_user(wiring, u.id).await?,
+                permissions: dao::role::RoleDao::permissions_for_user(wiring, u.id).await?,
             };
 
-            let _res = session.insert("user", user.clone()).unwrap();
+            if needs_second_factor {
+                let session = req.session_mut();
+
+                let pending = PendingSecondFactor {
+                    user: user,
+                    started_at: chrono::Utc::now().timestamp(),
+                };
+
+                let _res = session.insert(PENDING_SECOND_FACTOR_KEY, pending).unwrap();
+
+                render_second_factor("").await
+            } else {
+                begin_session(&mut req, user).await?;
+
+                // redirect to app now that we have set user
+                Ok(Redirect::new("/app").into())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SecondFactorDto {
+    code: String, // emoji encrypted fields
+}
+
+pub async fn post_second_factor(mut req: Request<ServerWiring>) -> Result {
+    let code = {
+        let encrypted_form: SecondFactorDto = req.body_form().await?;
+
+        let secrets: &encryption::SharedKeyring = req.ext().unwrap();
+
+        let encrypted_code = encryption::UserEncryptedEmojiMessage {
+            sender: secrets.user.to_owned(),
+            message: encrypted_form.code,
+        };
+
+        match encrypted_code.decrypt(secrets) {
+            Ok(code) => code,
+            Err(e) => {
+                tide::log::info!("Rejecting second factor form: {}", e);
+                return Ok(Response::builder(403).body_string(e.to_string()).build());
+            }
+        }
+    };
 
-            // redirect to app now that we have set user
-            Ok(Redirect::new("/app").into())
-        } else {
-            tide::log::info!("Failed login for user: {}", form.email);
-            let response = Response::builder(403).build();
-            Ok(response)
+    let pending: Option<PendingSecondFactor> = req.session().get(PENDING_SECOND_FACTOR_KEY);
+
+    let pending = match pending {
+        Some(p) if chrono::Utc::now().timestamp() - p.started_at < SECOND_FACTOR_TTL_SECONDS => p,
+        _ => {
+            req.session_mut().remove(PENDING_SECOND_FACTOR_KEY);
+            return Ok(Redirect::new("/login").into());
         }
+    };
+
+    let wiring: &ServerWiring = &req.state();
+
+    // authenticator codes are only six digits, so they share the account lockout with passwords
+    let indexed_email = email_index(wiring, pending.user.email.as_bytes());
+
+    if let Some(seconds) = LoginAttemptDao::locked_for(wiring, SCOPE_EMAIL, &indexed_email).await? {
+        tide::log::info!("Refusing second factor during lockout");
+        return render_second_factor(&lockout_message(seconds)).await;
+    }
+
+    let enrollment = dao::totp::TotpDao::find_by_uid(wiring, pending.user.uid).await?;
+
+    let is_valid = match enrollment {
+        Some(row) if row.confirmed => totp::verify_second_factor(wiring, &row, &code).await?,
+        _ => false,
+    };
+
+    if is_valid {
+        LoginAttemptDao::clear(wiring, SCOPE_EMAIL, &indexed_email).await?;
+
+        req.session_mut().remove(PENDING_SECOND_FACTOR_KEY);
+        begin_session(&mut req, pending.user).await?;
+
+        Ok(Redirect::new("/app").into())
+    } else {
+        tide::log::info!("Failed second factor for uid: {}", pending.user.uid);
+        LoginAttemptDao::record_failure(wiring, SCOPE_EMAIL, &indexed_email, &ACCOUNT_BACKOFF)
+            .await?;
+        render_second_factor("That code didn't match, try again.").await
     }
 }
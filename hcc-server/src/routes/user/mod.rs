@@ -1,2 +1,8 @@
+pub mod account;
+pub mod invites;
 pub mod login;
-pub mod signup;
\ No newline at end of file
+pub mod profile;
+pub mod sessions;
+pub mod signup;
+pub mod totp;
+pub mod verify;
@@ -0,0 +1,132 @@
+use tide::prelude::*;
+use tide::{Redirect, Request, Result};
+
+use crate::dao::user_attributes::UserAttributesDao;
+use crate::middleware::fragment::EncryptedFragment;
+use crate::util::encryption;
+use crate::wiring::ServerWiring;
+use domain::session::SessionUser;
+use domain::settings::MemberSettings;
+
+use askama::Template; // bring trait in scope
+
+pub const DISPLAY_MAX_CHARS: usize = 32;
+
+#[derive(Template)]
+#[template(path = "user/profile.html.j2")]
+struct ProfileViewModel {
+    display: String,
+    settings: MemberSettings,
+    message: String,
+    error: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfileDto {
+    display: String, // emoji encrypted fields
+    // unchecked boxes are left out of the form entirely
+    email_notifications: Option<String>,
+    media_autoplay: Option<String>,
+    reduced_motion: Option<String>,
+}
+
+async fn render_profile(
+    req: &Request<ServerWiring>,
+    user: &SessionUser,
+    message: &str,
+    error: &str,
+) -> Result {
+    let found = UserAttributesDao::find_by_uid(req.state(), user.uid).await?;
+
+    let view = ProfileViewModel {
+        display: found
+            .as_ref()
+            .map(|a| a.display.clone())
+            .unwrap_or_default(),
+        settings: found
+            .as_ref()
+            .map(UserAttributesDao::settings_of)
+            .unwrap_or_default(),
+        message: String::from(message),
+        error: String::from(error),
+    };
+
+    Ok(EncryptedFragment::render(&view)?.into())
+}
+
+pub async fn get(req: Request<ServerWiring>) -> Result {
+    match req.ext::<SessionUser>() {
+        Some(user) => {
+            let user = user.to_owned();
+            render_profile(&req, &user, "", "").await
+        }
+        None => Ok(Redirect::new("/login").into()),
+    }
+}
+
+pub async fn post(mut req: Request<ServerWiring>) -> Result {
+    let mut user = match req.ext::<SessionUser>() {
+        Some(user) => user.to_owned(),
+        None => return Ok(Redirect::new("/login").into()),
+    };
+
+    let form = {
+        let encrypted_form: ProfileDto = req.body_form().await?;
+
+        let secrets: &encryption::SharedKeyring = req.ext().unwrap();
+
+        let sender = &secrets.user;
+
+        let decrypt = |message: String| {
+            encryption::UserEncryptedEmojiMessage {
+                sender: sender.to_owned(),
+                message: message,
+            }
+            .decrypt(secrets)
+            .map_err(|e| tide::Error::new(403, e))
+        };
+
+        ProfileDto {
+            display: decrypt(encrypted_form.display)?,
+            email_notifications: encrypted_form.email_notifications.map(decrypt).transpose()?,
+            media_autoplay: encrypted_form.media_autoplay.map(decrypt).transpose()?,
+            reduced_motion: encrypted_form.reduced_motion.map(decrypt).transpose()?,
+        }
+    };
+
+    let display = form.display.trim();
+
+    if display.is_empty() || display.chars().count() > DISPLAY_MAX_CHARS {
+        let error = format!("Display names are 1 to {} characters.", DISPLAY_MAX_CHARS);
+        return render_profile(&req, &user, "", &error).await;
+    }
+
+    let wiring: &ServerWiring = req.state();
+
+    let taken = UserAttributesDao::find_by_display(wiring, display)
+        .await?
+        .map(|other| other.uid != user.uid)
+        .unwrap_or(false);
+
+    if taken {
+        return render_profile(&req, &user, "", "That display name is already taken.").await;
+    }
+
+    let settings = MemberSettings {
+        email_notifications: form.email_notifications.is_some(),
+        media_autoplay: form.media_autoplay.is_some(),
+        reduced_motion: form.reduced_motion.is_some(),
+    };
+
+    // the unique index still has the final word if two members race for a name
+    if let Err(e) = UserAttributesDao::save_profile(wiring, user.uid, display, &settings).await {
+        tide::log::info!("Failed to save profile for uid {}: {:?}", user.uid, e);
+        return render_profile(&req, &user, "", "That display name is already taken.").await;
+    }
+
+    // keep the session greeting in step without waiting for the next login
+    user.display = String::from(display);
+    let _res = req.session_mut().insert("user", user.clone()).unwrap();
+
+    render_profile(&req, &user, "Profile saved.", "").await
+}
@@ -0,0 +1,109 @@
+use tide::prelude::*;
+use tide::{Redirect, Request, Result};
+
+use crate::dao::session::SessionDao;
+use crate::middleware::fragment::EncryptedFragment;
+use crate::util::encryption;
+use crate::wiring::ServerWiring;
+use domain::session::SessionUser;
+
+use askama::Template; // bring trait in scope
+
+struct SessionRow {
+    id: i32,
+    user_agent: String,
+    ip: String,
+    created_at: String,
+    last_seen: String,
+    is_current: bool,
+}
+
+#[derive(Template)]
+#[template(path = "user/sessions.html.j2")]
+struct SessionsViewModel {
+    sessions: Vec<SessionRow>,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RevokeSessionDto {
+    id: String, // emoji encrypted fields
+}
+
+async fn render_sessions(req: &Request<ServerWiring>, user: &SessionUser, message: &str) -> Result {
+    let current_session_id = req.session().id();
+
+    let sessions = SessionDao::list_for_user(req.state(), user.uid)
+        .await?
+        .into_iter()
+        .map(|s| SessionRow {
+            id: s.id,
+            is_current: s.session_id == current_session_id,
+            user_agent: s.user_agent,
+            ip: s.ip,
+            created_at: s.created_at.to_rfc3339(),
+            last_seen: s.last_seen.to_rfc3339(),
+        })
+        .collect();
+
+    let view = SessionsViewModel {
+        sessions: sessions,
+        message: String::from(message),
+    };
+
+    Ok(EncryptedFragment::render(&view)?.into())
+}
+
+pub async fn get(req: Request<ServerWiring>) -> Result {
+    match req.ext::<SessionUser>() {
+        Some(user) => {
+            let user = user.to_owned();
+            render_sessions(&req, &user, "").await
+        }
+        None => Ok(Redirect::new("/login").into()),
+    }
+}
+
+pub async fn post_revoke(mut req: Request<ServerWiring>) -> Result {
+    let user = match req.ext::<SessionUser>() {
+        Some(user) => user.to_owned(),
+        None => return Ok(Redirect::new("/login").into()),
+    };
+
+    let id = {
+        let encrypted_form: RevokeSessionDto = req.body_form().await?;
+
+        let secrets: &encryption::SharedKeyring = req.ext().unwrap();
+
+        let encrypted_id = encryption::UserEncryptedEmojiMessage {
+            sender: secrets.user.to_owned(),
+            message: encrypted_form.id,
+        };
+
+        encrypted_id.decrypt(secrets).map_err(|e| tide::Error::new(403, e))?
+    };
+
+    let revoked = match id.trim().parse::<i32>() {
+        Ok(id) => SessionDao::revoke(req.state(), user.uid, id).await?,
+        Err(_) => false,
+    };
+
+    if revoked {
+        render_sessions(&req, &user, "Session signed out.").await
+    } else {
+        render_sessions(&req, &user, "That session was already gone.").await
+    }
+}
+
+pub async fn post_revoke_others(req: Request<ServerWiring>) -> Result {
+    let user = match req.ext::<SessionUser>() {
+        Some(user) => user.to_owned(),
+        None => return Ok(Redirect::new("/login").into()),
+    };
+
+    let current_session_id = String::from(req.session().id());
+
+    let count = SessionDao::revoke_others(req.state(), user.uid, &current_session_id).await?;
+
+    render_sessions(&req, &user, &format!("Signed out {} other session(s).", count)).await
+}
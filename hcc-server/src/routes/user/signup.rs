@@ -1,18 +1,36 @@
 use tide::prelude::*;
-use tide::{http::mime, Redirect, Request, Response, Result};
+use tide::{Redirect, Request, Result};
 
 use crate::dao;
+use crate::middleware::fragment::EncryptedFragment;
 use crate::util::emoji;
 use crate::util::encryption;
-use crate::util::password::PasswordUtil;
 use crate::wiring::ServerWiring;
+use domain::permission::Role;
 use domain::session::SessionUser;
 
+use super::profile::DISPLAY_MAX_CHARS;
+use super::verify;
+
 use askama::Template; // bring trait in scope
 
 #[derive(Template)] // this will generate the code...
 #[template(path = "user/signup.html.j2")] // using the template in this path, relative
-struct SignupGetViewModel {}
+struct SignupGetViewModel {
+    error: String,
+}
+
+#[derive(Template)]
+#[template(path = "user/verify_sent.html.j2")]
+struct VerifySentViewModel {}
+
+async fn render_signup(error: &str) -> Result {
+    let view_context = SignupGetViewModel {
+        error: String::from(error),
+    };
+
+    Ok(EncryptedFragment::render(&view_context)?.into())
+}
 
 pub async fn get(req: Request<ServerWiring>) -> Result {
     let maybe_user: Option<&SessionUser> = req.ext();
@@ -20,33 +38,20 @@ pub async fn get(req: Request<ServerWiring>) -> Result {
     if maybe_user.is_some() {
         Ok(Redirect::new("/app").into())
     } else {
-        let view_context = SignupGetViewModel {};
-
-        let secrets: &encryption::SharedKeyring = req.ext().unwrap();
-
-        let encrypted_body = secrets
-            .encrypt_broadcast_emoji(&view_context.render().unwrap())
-            .await
-            .unwrap()
-            .message;
-
-        let response = Response::builder(200)
-            .content_type(mime::HTML)
-            .body_string(encrypted_body)
-            .build();
-        Ok(response)
+        render_signup("").await
     }
 }
 
 #[derive(Debug, Deserialize)]
 struct UserSignupDto {
     email: String, // emoji encrypted fields
-    password_bcrypt: String,
+    display: String,
+    password: String,
+    password_bcrypt: String, // the client renames password_confirm on the way out
+    invite: String,
 }
 
 pub async fn post(mut req: Request<ServerWiring>) -> Result {
-    return Ok(Redirect::new("/").into());
-    /*
     let form = {
         let encrypted_form: UserSignupDto = req.body_form().await?;
 
@@ -54,66 +59,119 @@ pub async fn post(mut req: Request<ServerWiring>) -> Result {
 
         let sender = &secrets.user;
 
-        let encrypted_email = encryption::UserEncryptedEmojiMessage {
-            sender: sender.to_owned(),
-            message: encrypted_form.email,
-        };
-
-        let encrypted_password = encryption::UserEncryptedEmojiMessage {
-            sender: sender.to_owned(),
-            message: encrypted_form.password,
+        let decrypt = |message: String| {
+            encryption::UserEncryptedEmojiMessage {
+                sender: sender.to_owned(),
+                message: message,
+            }
+            .decrypt(secrets)
+            .map_err(|e| tide::Error::new(403, e))
         };
 
-        UserLoginDto {
-            email: encrypted_email.decrypt(secrets).unwrap(),
-            password: encrypted_password.decrypt(secrets).unwrap(),
+        UserSignupDto {
+            email: decrypt(encrypted_form.email)?,
+            display: decrypt(encrypted_form.display)?,
+            password: decrypt(encrypted_form.password)?,
+            password_bcrypt: decrypt(encrypted_form.password_bcrypt)?,
+            invite: decrypt(encrypted_form.invite)?,
         }
     };
 
-    let search = dao::user::UserDao::find_by_email(&req.state(), &form.email.as_bytes())
-        .await
-        .unwrap();
+    let email = String::from(form.email.trim());
+    let display = form.display.trim();
 
-    if search.is_none() {
-        let response = Response::builder(403).build();
-        Ok(response)
-    } else {
-        let u = search.unwrap();
+    if email.is_empty() || !email.contains('@') {
+        return render_signup("Please enter a valid email address.").await;
+    }
+
+    if display.is_empty() {
+        return render_signup("Please choose a display name.").await;
+    }
 
-        let user_pwhash = emoji::decode(&u.password);
-        let expected_email_hash = u.email_hash;
+    if display.chars().count() > DISPLAY_MAX_CHARS {
+        let error = format!("Display names are 1 to {} characters.", DISPLAY_MAX_CHARS);
+        return render_signup(&error).await;
+    }
+
+    if form.password.is_empty() || form.password != form.password_bcrypt {
+        return render_signup("Passwords do not match.").await;
+    }
 
-        let form_email_hash = {
-            encryption::DeterministicEmojiEncrypted::new(
-                &req.state().config.encryption_key_emoji,
-                &req.state().config.encryption_salt_emoji,
-                form.email.as_bytes()
-            )
-        }.unwrap();
+    let wiring: &ServerWiring = &req.state();
 
-        let email_is_valid = form_email_hash == expected_email_hash;
+    // membership is invite only, the code is a signed token naming the invite row
+    let invite_id = wiring
+        .services
+        .jwt_util
+        .verify_invite_token(form.invite.trim())
+        .ok()
+        .and_then(|claims| claims["inv"].as_i64());
 
-        let pass_is_valid = email_is_valid && {
-            PasswordUtil::verify_hashed_bytes(&form.password, &user_pwhash)
-        };
+    let invite = match invite_id {
+        Some(id) => dao::invite::InviteDao::find_by_id(wiring, id as i32).await?,
+        None => None,
+    };
+
+    let invite = match invite {
+        Some(found) if dao::invite::InviteDao::is_redeemable(&found) => found,
+        Some(_) => {
+            return render_signup("That invite code has expired or been used up.").await;
+        }
+        None => return render_signup("Please enter a valid invite code.").await,
+    };
 
-        if email_is_valid && pass_is_valid {
-            let session = req.session_mut();
+    let role = Role::from_name(&invite.role).unwrap_or(Role::Member);
 
-            let user = SessionUser {
-                email: String::from(&form.email),
-            };
+    let email_taken = dao::user::UserDao::find_by_email(wiring, email.as_bytes())
+        .await?
+        .is_some();
 
-            let _res = session.insert("user", user.clone()).unwrap();
+    if email_taken {
+        return render_signup("That email is already registered.").await;
+    }
+
+    let display_taken = dao::user_attributes::UserAttributesDao::find_by_display(wiring, display)
+        .await?
+        .is_some();
+
+    if display_taken {
+        return render_signup("That display name is already taken.").await;
+    }
 
-            // redirect to app now that we have set user
-            Ok(Redirect::new("/app").into())
-        } else {
-            tide::log::info!("Failed login for user: {}", form.email);
-            let response = Response::builder(403).build();
-            Ok(response)
+    let pwhash = emoji::encode(
+        &wiring
+            .services
+            .password_util
+            .into_password_hash(&form.password)
+            .await?,
+    );
+
+    let inserted = dao::user::UserDao::insert_member(
+        wiring,
+        email.as_bytes(),
+        &pwhash,
+        display,
+        role,
+        invite.id,
+    )
+    .await;
+
+    let member = match inserted {
+        Ok(member) => member,
+        Err(e) => {
+            tide::log::info!("Failed to insert new member: {:?}", e);
+            return render_signup("Unable to create your account right now.").await;
         }
+    };
+
+    // accounts stay inactive until the emailed link comes back to us
+    let sent = verify::send_verification(wiring, member.id, &email).await;
+
+    if sent.is_err() {
+        tide::log::error!("Failed to send verification email: {:?}", sent.err());
     }
 
-        */
+    let view_context = VerifySentViewModel {};
+
+    Ok(EncryptedFragment::render(&view_context)?.into())
 }
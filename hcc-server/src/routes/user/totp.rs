@@ -0,0 +1,214 @@
+use tide::prelude::*;
+use tide::{Redirect, Request, Result};
+
+use crate::dao;
+use crate::middleware::fragment::EncryptedFragment;
+use crate::util::encryption;
+use crate::util::totp::{RecoveryCodes, Totp};
+use crate::wiring::ServerWiring;
+use domain::sea_orm::entities::user_totp;
+use domain::server_config::ServerConfig;
+use domain::session::SessionUser;
+
+use askama::Template; // bring trait in scope
+
+const TOTP_ISSUER: &str = "holy charisma";
+
+#[derive(Template)]
+#[template(path = "user/totp.html.j2")]
+struct TotpViewModel {
+    enabled: bool,
+    qr_svg: String,
+    otpauth_uri: String,
+    secret: String,
+    error: String,
+}
+
+#[derive(Template)]
+#[template(path = "user/totp_recovery.html.j2")]
+struct TotpRecoveryViewModel {
+    codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TotpCodeDto {
+    code: String, // emoji encrypted fields
+}
+
+// totp secrets are sealed with the server key before they touch the database
+// a secret that won't open means HCC_ENCRYPTION_KEY_EMOJI changed under it, that is a server error not a panic
+
+fn seal_secret(config: &ServerConfig, secret: &[u8]) -> Result<String> {
+    encryption::seal_with_key_emoji(&config.encryption_key_emoji, secret)
+        .map_err(|_| tide::Error::from_str(500, "totp secret could not be sealed"))
+}
+
+fn open_secret(config: &ServerConfig, sealed: &str) -> Result<Vec<u8>> {
+    encryption::open_with_key(&config.encryption_key_emoji, sealed)
+        .map_err(|_| tide::Error::from_str(500, "totp secret could not be opened"))
+}
+
+fn now_unix() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+/// Checks an authenticator or recovery code against a confirmed enrollment, spending it on success.
+pub async fn verify_second_factor(
+    wiring: &ServerWiring,
+    row: &user_totp::Model,
+    code: &str,
+) -> Result<bool> {
+    let totp = Totp::new(&open_secret(&wiring.config, &row.secret)?);
+
+    if let Some(step) = totp.verify(code, now_unix(), row.last_used_step) {
+        return Ok(dao::totp::TotpDao::spend_step(wiring, row.id, step).await?);
+    }
+
+    let hashes: Vec<String> = serde_json::from_str(&row.recovery_codes).unwrap_or_default();
+
+    match RecoveryCodes::redeem(code, &hashes) {
+        Some(remaining) => {
+            let json = serde_json::to_string(&remaining).unwrap();
+            let spent =
+                dao::totp::TotpDao::spend_recovery_code(wiring, row.id, &row.recovery_codes, &json)
+                    .await?;
+            if spent {
+                tide::log::info!("Recovery code used, {} remaining", remaining.len());
+            }
+            Ok(spent)
+        }
+        None => Ok(false),
+    }
+}
+
+async fn decrypt_code(req: &mut Request<ServerWiring>) -> Result<String> {
+    let encrypted_form: TotpCodeDto = req.body_form().await?;
+
+    let secrets: &encryption::SharedKeyring = req.ext().unwrap();
+
+    let encrypted_code = encryption::UserEncryptedEmojiMessage {
+        sender: secrets.user.to_owned(),
+        message: encrypted_form.code,
+    };
+
+    // replayed or stale codes are refused with a 403 rather than a panic
+    encrypted_code
+        .decrypt(secrets)
+        .map_err(|e| tide::Error::new(403, e))
+}
+
+async fn render_enrollment(req: &Request<ServerWiring>, user: &SessionUser, error: &str) -> Result {
+    let wiring: &ServerWiring = req.state();
+
+    let existing = dao::totp::TotpDao::find_by_uid(wiring, user.uid).await?;
+
+    let view = match existing {
+        Some(row) if row.confirmed => TotpViewModel {
+            enabled: true,
+            qr_svg: String::new(),
+            otpauth_uri: String::new(),
+            secret: String::new(),
+            error: String::from(error),
+        },
+        pending => {
+            // keep showing the same secret until it is confirmed so a rescan isn't needed on a typo
+            let secret = match pending {
+                Some(row) => open_secret(&wiring.config, &row.secret)?,
+                None => {
+                    let secret = Totp::generate_secret();
+                    dao::totp::TotpDao::start_enrollment(
+                        wiring,
+                        user.uid,
+                        &seal_secret(&wiring.config, &secret)?,
+                    )
+                    .await?;
+                    secret
+                }
+            };
+
+            let totp = Totp::new(&secret);
+
+            TotpViewModel {
+                enabled: false,
+                qr_svg: totp.qr_svg(TOTP_ISSUER, &user.email),
+                otpauth_uri: totp.otpauth_uri(TOTP_ISSUER, &user.email),
+                secret: totp.encoded_secret(),
+                error: String::from(error),
+            }
+        }
+    };
+
+    Ok(EncryptedFragment::render(&view)?.into())
+}
+
+pub async fn get(req: Request<ServerWiring>) -> Result {
+    let maybe_user: Option<&SessionUser> = req.ext();
+
+    match maybe_user {
+        Some(user) => {
+            let user = user.to_owned();
+            render_enrollment(&req, &user, "").await
+        }
+        None => Ok(Redirect::new("/login").into()),
+    }
+}
+
+pub async fn post(mut req: Request<ServerWiring>) -> Result {
+    let user = match req.ext::<SessionUser>() {
+        Some(user) => user.to_owned(),
+        None => return Ok(Redirect::new("/login").into()),
+    };
+
+    let code = decrypt_code(&mut req).await?;
+
+    let wiring: &ServerWiring = req.state();
+
+    let pending = match dao::totp::TotpDao::find_by_uid(wiring, user.uid).await? {
+        Some(row) if !row.confirmed => row,
+        _ => return render_enrollment(&req, &user, "").await,
+    };
+
+    let totp = Totp::new(&open_secret(&wiring.config, &pending.secret)?);
+
+    match totp.verify(&code, now_unix(), pending.last_used_step) {
+        Some(step) => {
+            let codes = RecoveryCodes::generate();
+            let hashes: Vec<String> = codes.iter().map(|c| RecoveryCodes::hash(c)).collect();
+
+            dao::totp::TotpDao::confirm(
+                wiring,
+                pending.id,
+                step,
+                &serde_json::to_string(&hashes).unwrap(),
+            )
+            .await?;
+
+            let view = TotpRecoveryViewModel { codes: codes };
+            Ok(EncryptedFragment::render(&view)?.into())
+        }
+        None => render_enrollment(&req, &user, "That code didn't match, try again.").await,
+    }
+}
+
+pub async fn post_disable(mut req: Request<ServerWiring>) -> Result {
+    let user = match req.ext::<SessionUser>() {
+        Some(user) => user.to_owned(),
+        None => return Ok(Redirect::new("/login").into()),
+    };
+
+    let code = decrypt_code(&mut req).await?;
+
+    let wiring: &ServerWiring = req.state();
+
+    let confirmed = match dao::totp::TotpDao::find_by_uid(wiring, user.uid).await? {
+        Some(row) if row.confirmed => row,
+        _ => return render_enrollment(&req, &user, "").await,
+    };
+
+    if verify_second_factor(wiring, &confirmed, &code).await? {
+        dao::totp::TotpDao::delete_for_user(wiring, user.uid).await?;
+        render_enrollment(&req, &user, "").await
+    } else {
+        render_enrollment(&req, &user, "That code didn't match, try again.").await
+    }
+}
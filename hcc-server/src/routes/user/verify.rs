@@ -0,0 +1,79 @@
+use tide::prelude::*;
+use tide::{Redirect, Request, Response, Result};
+
+use crate::dao;
+use crate::mailer::{Email, MailerError};
+use crate::wiring::ServerWiring;
+
+use askama::Template; // bring trait in scope
+
+const VERIFY_ACTION: &str = "verify-email";
+const VERIFY_TTL_HOURS: i64 = 48;
+
+#[derive(Template)]
+#[template(path = "email/verify.txt.j2")]
+struct VerifyEmailView {
+    link: String,
+    ttl_hours: i64,
+}
+
+pub async fn send_verification(
+    wiring: &ServerWiring,
+    uid: i32,
+    email: &str,
+) -> std::result::Result<(), MailerError> {
+    let token = wiring
+        .services
+        .jwt_util
+        .sign_action_token(VERIFY_ACTION, uid, VERIFY_TTL_HOURS * 60 * 60, None)
+        .map_err(|e| MailerError(e.to_string()))?;
+
+    let view = VerifyEmailView {
+        link: format!("{}/signup/verify?token={}", wiring.config.domain, token),
+        ttl_hours: VERIFY_TTL_HOURS,
+    };
+
+    wiring
+        .services
+        .mailer
+        .send(Email {
+            to: String::from(email),
+            subject: String::from("verify your holy charisma account"),
+            body: view.render().unwrap(),
+        })
+        .await
+}
+
+#[derive(Debug, Deserialize)]
+struct VerifyQuery {
+    token: String,
+}
+
+pub async fn get(req: Request<ServerWiring>) -> Result {
+    // this link is opened straight from an email client, outside of the frame
+    // so there is no keyring to encrypt with: just activate and send them home.
+    // the link only ever works once, it can't turn a deactivated account back on
+
+    let query: VerifyQuery = req.query()?;
+
+    let verification = req
+        .state()
+        .services
+        .jwt_util
+        .verify_action_token(&query.token, VERIFY_ACTION);
+
+    match verification.ok().and_then(|claims| claims["uid"].as_i64()) {
+        Some(uid) => {
+            if dao::user::UserDao::verify_email(req.state(), uid as i32).await? {
+                Ok(Redirect::new("/").into())
+            } else {
+                tide::log::info!("Rejecting spent email verification token for uid: {}", uid);
+                Ok(Response::builder(403).build())
+            }
+        }
+        None => {
+            tide::log::info!("Rejecting bad email verification token");
+            Ok(Response::builder(403).build())
+        }
+    }
+}
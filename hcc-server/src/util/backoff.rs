@@ -0,0 +1,54 @@
+// exponential backoff for failed logins
+// the first few failures are free, after that each failure doubles the lockout up to a ceiling
+
+pub struct LoginBackoff {
+    pub free_attempts: i32,
+    pub base_seconds: i64,
+    pub max_seconds: i64,
+}
+
+// a single account is locked quickly, an address gets more room since people share them
+pub const ACCOUNT_BACKOFF: LoginBackoff = LoginBackoff {
+    free_attempts: 5,
+    base_seconds: 30,
+    max_seconds: 60 * 60,
+};
+
+pub const ADDRESS_BACKOFF: LoginBackoff = LoginBackoff {
+    free_attempts: 20,
+    base_seconds: 60,
+    max_seconds: 6 * 60 * 60,
+};
+
+// failures older than this no longer count against anyone
+pub const FAILURE_WINDOW_SECONDS: i64 = 24 * 60 * 60;
+
+impl LoginBackoff {
+    pub fn lockout_seconds(&self, failures: i32) -> i64 {
+        if failures < self.free_attempts {
+            return 0;
+        }
+
+        let doublings = (failures - self.free_attempts).min(32) as u32;
+        self.base_seconds
+            .saturating_mul(2i64.saturating_pow(doublings))
+            .min(self.max_seconds)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        assert_eq!(ACCOUNT_BACKOFF.lockout_seconds(0), 0);
+        assert_eq!(ACCOUNT_BACKOFF.lockout_seconds(4), 0);
+        assert_eq!(ACCOUNT_BACKOFF.lockout_seconds(5), 30);
+        assert_eq!(ACCOUNT_BACKOFF.lockout_seconds(6), 60);
+        assert_eq!(ACCOUNT_BACKOFF.lockout_seconds(7), 120);
+        assert_eq!(ACCOUNT_BACKOFF.lockout_seconds(50), 60 * 60);
+        assert_eq!(ACCOUNT_BACKOFF.lockout_seconds(i32::MAX), 60 * 60);
+    }
+}
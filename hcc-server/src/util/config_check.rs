@@ -0,0 +1,224 @@
+use std::fmt;
+use std::net::ToSocketAddrs;
+
+use async_sqlx_session::PostgresSessionStore;
+use domain::server_config::ServerConfig;
+
+use super::emoji;
+use super::password::{PasswordParams, PasswordUtil};
+use crate::mailer::smtp::SmtpMailer;
+use crate::wiring::{ServerWiring, ServiceWiring};
+
+// the aead key and the blind index key are both 32 random bytes
+const EMOJI_KEY_SIZE: usize = 32;
+
+const CHECK_SESSION_ID: &str = "check-config";
+
+/// Everything wrong with the configuration, gathered in one pass instead of one panic per restart.
+#[derive(Debug, Default)]
+pub struct ConfigProblems(Vec<String>);
+
+impl ConfigProblems {
+    pub fn push(&mut self, problem: impl Into<String>) {
+        self.0.push(problem.into());
+    }
+
+    pub fn into_result<T>(self, value: T) -> Result<T, ConfigProblems> {
+        if self.0.is_empty() {
+            Ok(value)
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ConfigProblems {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigProblems {}
+
+/// Reads the configuration and checks it can actually run a server: keys decode, the jwt keys sign
+/// and verify, the database answers. Empty settings were already reported as missing and are skipped.
+pub async fn checked_config() -> Result<ServerConfig, ConfigProblems> {
+    let (config, mut problems) = ServerWiring::init_server_config();
+
+    check_emoji_key(&mut problems, "HCC_ENCRYPTION_KEY_EMOJI", &config.encryption_key_emoji);
+    check_emoji_key(&mut problems, "HCC_BLIND_INDEX_KEY_EMOJI", &config.blind_index_key_emoji);
+    check_emoji_key(&mut problems, "HCC_COLUMN_KEY_EMOJI", &config.column_key_emoji);
+    check_super_user_hash(&mut problems, &config.super_user_pwhash_emoji);
+    check_column_keys(&mut problems, &config);
+    check_jwt_keys(&mut problems, &config);
+    check_services(&mut problems, &config);
+    check_bind_url(&mut problems, &config.bind_url);
+    check_database(&mut problems, &config).await;
+
+    problems.into_result(config)
+}
+
+fn check_emoji_key(problems: &mut ConfigProblems, name: &str, value: &str) {
+    if value.is_empty() {
+        return;
+    }
+
+    match emoji::try_decode(value) {
+        Some(bytes) if bytes.len() == EMOJI_KEY_SIZE => {}
+        Some(bytes) => problems.push(format!(
+            "{} must be {} bytes, it decodes to {}",
+            name,
+            EMOJI_KEY_SIZE,
+            bytes.len()
+        )),
+        None => problems.push(format!("{} is not emoji encoded bytes", name)),
+    }
+}
+
+fn check_super_user_hash(problems: &mut ConfigProblems, value: &str) {
+    if value.is_empty() {
+        return;
+    }
+
+    match emoji::try_decode(value) {
+        Some(hash) if PasswordUtil::is_recognized_hash(&hash) => {}
+        Some(_) => problems.push("HCC_SUPER_USER_PWHASH_EMOJI is not an argon2 or bcrypt hash"),
+        None => problems.push("HCC_SUPER_USER_PWHASH_EMOJI is not emoji encoded bytes"),
+    }
+}
+
+fn check_column_keys(problems: &mut ConfigProblems, config: &ServerConfig) {
+    // the current and blind index keys were looked at on their own, this is about the retired ones
+    let usable = |value: &str| emoji::try_decode(value).map(|bytes| bytes.len() == EMOJI_KEY_SIZE);
+    if usable(&config.column_key_emoji) != Some(true)
+        || usable(&config.blind_index_key_emoji) != Some(true)
+    {
+        return;
+    }
+
+    // sharing it would tie column rotation to emails, totp secrets and session keyrings, which can't rotate
+    if config.column_key_emoji == config.encryption_key_emoji {
+        problems.push("HCC_COLUMN_KEY_EMOJI must not be the same key as HCC_ENCRYPTION_KEY_EMOJI");
+    }
+
+    if let Err(e) = ServiceWiring::column_keys(config) {
+        problems.push(e);
+    }
+}
+
+fn check_jwt_keys(problems: &mut ConfigProblems, config: &ServerConfig) {
+    if config.jwt_private_key_path.is_empty() || config.jwt_public_key_path.is_empty() {
+        return;
+    }
+
+    let jwt_util = match ServiceWiring::jwt_util(config) {
+        Ok(jwt_util) => jwt_util,
+        Err(e) => {
+            problems.push(e);
+            return;
+        }
+    };
+
+    // two halves of different keypairs both parse, only a round trip shows they don't belong together
+    match jwt_util.sign_csrf_token(CHECK_SESSION_ID) {
+        Ok(token) => {
+            if jwt_util.verify_csrf_token(&token, CHECK_SESSION_ID).is_err() {
+                problems.push("HCC_JWT_PUBLIC_KEY_PATH doesn't verify tokens signed with HCC_JWT_PRIVATE_KEY_PATH");
+            }
+        }
+        Err(e) => problems.push(format!("HCC_JWT_PRIVATE_KEY_PATH can't sign tokens: {}", e)),
+    }
+}
+
+fn check_services(problems: &mut ConfigProblems, config: &ServerConfig) {
+    let params = PasswordParams {
+        memory_kib: config.password_argon2_memory_kib,
+        iterations: config.password_argon2_iterations,
+        parallelism: config.password_argon2_parallelism,
+    };
+    if let Err(e) = PasswordUtil::new(params) {
+        problems.push(format!("HCC_PASSWORD_ARGON2_* parameters rejected by argon2: {}", e));
+    }
+
+    match config.mailer_transport.as_str() {
+        "smtp" => {
+            if let Err(e) = SmtpMailer::new(&config.mailer_smtp_url, &config.mailer_from) {
+                problems.push(format!("HCC_MAILER_SMTP_URL must be a valid smtp url: {}", e));
+            }
+        }
+        "file" => {}
+        other => problems.push(format!("HCC_MAILER_TRANSPORT must be smtp or file, got {}", other)),
+    }
+}
+
+fn check_bind_url(problems: &mut ConfigProblems, bind_url: &str) {
+    if bind_url.is_empty() {
+        return;
+    }
+
+    // the forms tide's listener accepts
+    let valid = match tide::http::Url::parse(bind_url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "tcp" => {
+            url.host_str().is_some() && url.port_or_known_default().is_some()
+        }
+        Ok(url) if url.scheme() == "http+unix" => !url.path().is_empty(),
+        _ => bind_url.to_socket_addrs().is_ok(),
+    };
+
+    if !valid {
+        problems.push(format!(
+            "HCC_BIND_URL must look like 127.0.0.1:8080 or http://localhost:8080, got {}",
+            bind_url
+        ));
+    }
+}
+
+async fn check_database(problems: &mut ConfigProblems, config: &ServerConfig) {
+    if config.postgres_sql_connection_url.is_empty() {
+        return;
+    }
+
+    if let Err(e) = ServerWiring::database(config).await {
+        problems.push(format!("HCC_POSTGRES_SQL_CONNECTION_URL is not reachable: {}", e));
+        // the session store would only repeat the same failure
+        return;
+    }
+
+    if let Err(e) = PostgresSessionStore::new(&config.postgres_sql_connection_url).await {
+        problems.push(format!("the session store can't connect to HCC_POSTGRES_SQL_CONNECTION_URL: {}", e));
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_problems_are_collected() {
+        let mut problems = ConfigProblems::default();
+        check_emoji_key(&mut problems, "HCC_ENCRYPTION_KEY_EMOJI", &emoji::encode(&[7u8; 32]));
+        check_bind_url(&mut problems, "127.0.0.1:8080");
+        check_bind_url(&mut problems, "http://localhost:8080");
+        assert!(problems.0.is_empty());
+
+        check_emoji_key(&mut problems, "HCC_ENCRYPTION_KEY_EMOJI", &emoji::encode(&[7u8; 16]));
+        check_emoji_key(&mut problems, "HCC_BLIND_INDEX_KEY_EMOJI", "not a key");
+        check_super_user_hash(&mut problems, &emoji::encode(b"hunter23"));
+        check_bind_url(&mut problems, "localhost");
+
+        let report = problems.into_result(()).unwrap_err().to_string();
+        assert_eq!(
+            report,
+            "Invalid configuration:\
+             \n  - HCC_ENCRYPTION_KEY_EMOJI must be 32 bytes, it decodes to 16\
+             \n  - HCC_BLIND_INDEX_KEY_EMOJI is not emoji encoded bytes\
+             \n  - HCC_SUPER_USER_PWHASH_EMOJI is not an argon2 or bcrypt hash\
+             \n  - HCC_BIND_URL must look like 127.0.0.1:8080 or http://localhost:8080, got localhost"
+        );
+    }
+}
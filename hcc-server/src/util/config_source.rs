@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::config_check::ConfigProblems;
+
+// where a setting comes from, first one wins:
+//
+//   HCC_X                  the environment (and .env)
+//   HCC_X_FILE             a file holding the value, for secrets mounted by docker, systemd, etc
+//   x = ...                the toml config file
+//   x_file = ...           the toml config file pointing at a secret file
+//
+// toml keys are the variable names without HCC_, tables join with an underscore,
+// so `[jwt] signing_kid = "2024-06"` sets HCC_JWT_SIGNING_KID
+
+pub const CONFIG_FILE_VAR: &str = "HCC_CONFIG_FILE";
+pub const DEFAULT_CONFIG_FILE: &str = "hcc.toml";
+
+const VAR_PREFIX: &str = "HCC_";
+const FILE_SUFFIX: &str = "_FILE";
+
+/// The layered settings. Nothing here panics: whatever can't be read is noted in `problems`
+/// and comes back empty or defaulted, so the rest can still be looked at.
+pub struct ConfigSource {
+    file: HashMap<String, String>,
+    pub problems: ConfigProblems,
+}
+
+impl ConfigSource {
+    pub fn load() -> ConfigSource {
+        let mut problems = ConfigProblems::default();
+
+        let file = match env::var(CONFIG_FILE_VAR) {
+            Ok(path) => read_config_file(&path, &mut problems),
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                read_config_file(DEFAULT_CONFIG_FILE, &mut problems)
+            }
+            Err(_) => HashMap::new(),
+        };
+
+        ConfigSource {
+            file: file,
+            problems: problems,
+        }
+    }
+
+    /// The value of a setting, `None` when no layer sets it.
+    pub fn get(&mut self, name: &str) -> Option<String> {
+        let file_name = format!("{}{}", name, FILE_SUFFIX);
+
+        let layers = [
+            (env::var(name).ok(), env::var(&file_name).ok()),
+            (self.file.get(name).cloned(), self.file.get(&file_name).cloned()),
+        ];
+
+        for layer in layers {
+            match layer {
+                (Some(value), Some(_)) => {
+                    self.problems.push(format!("{} and {} are both set, use one", name, file_name));
+                    return Some(value);
+                }
+                (Some(value), None) => return Some(value),
+                (None, Some(path)) => return self.read_secret(&file_name, &path),
+                (None, None) => {}
+            }
+        }
+
+        None
+    }
+
+    fn read_secret(&mut self, name: &str, path: &str) -> Option<String> {
+        match fs::read_to_string(path) {
+            // editors and `echo` leave a newline behind
+            Ok(value) => Some(String::from(value.trim_end())),
+            Err(e) => {
+                self.problems.push(format!("{} can't be read from {}: {}", name, path, e));
+                None
+            }
+        }
+    }
+
+    pub fn string(&mut self, name: &str, default: &str) -> String {
+        self.get(name).unwrap_or_else(|| String::from(default))
+    }
+
+    pub fn required(&mut self, name: &str) -> String {
+        self.get(name).unwrap_or_else(|| {
+            self.problems.push(format!("{} required", name));
+            String::new()
+        })
+    }
+
+    /// For settings that were renamed, `legacy` is still read when `name` isn't set.
+    pub fn required_or_legacy(&mut self, name: &str, legacy: &str) -> String {
+        match self.get(name) {
+            Some(value) => value,
+            None => self.get(legacy).unwrap_or_else(|| {
+                self.problems.push(format!("{} required", name));
+                String::new()
+            }),
+        }
+    }
+
+    pub fn required_number<T: FromStr + Default>(&mut self, name: &str) -> T {
+        match self.get(name) {
+            Some(value) => self.parse(name, &value, T::default()),
+            None => {
+                self.problems.push(format!("{} required", name));
+                T::default()
+            }
+        }
+    }
+
+    pub fn number<T: FromStr>(&mut self, name: &str, default: T) -> T {
+        match self.get(name) {
+            Some(value) => self.parse(name, &value, default),
+            None => default,
+        }
+    }
+
+    pub fn flag(&mut self, name: &str, default: bool) -> bool {
+        match self.get(name).as_deref() {
+            Some("true") => true,
+            Some("false") => false,
+            Some(_) => {
+                self.problems.push(format!("{} must be true or false", name));
+                default
+            }
+            None => default,
+        }
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &str, value: &str, default: T) -> T {
+        value.parse().unwrap_or_else(|_| {
+            self.problems.push(format!("{} must be a number", name));
+            default
+        })
+    }
+}
+
+fn read_config_file(path: &str, problems: &mut ConfigProblems) -> HashMap<String, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            problems.push(format!("{} can't be read from {}: {}", CONFIG_FILE_VAR, path, e));
+            return HashMap::new();
+        }
+    };
+
+    match text.parse::<toml::Value>() {
+        Ok(toml::Value::Table(table)) => {
+            let mut settings = HashMap::new();
+            flatten(VAR_PREFIX, &table, &mut settings, problems);
+            settings
+        }
+        Ok(_) => HashMap::new(),
+        Err(e) => {
+            problems.push(format!("{} is not valid toml: {}", path, e));
+            HashMap::new()
+        }
+    }
+}
+
+/// Turns the toml into the same names as the environment variables.
+fn flatten(
+    prefix: &str,
+    table: &toml::value::Table,
+    settings: &mut HashMap<String, String>,
+    problems: &mut ConfigProblems,
+) {
+    for (key, value) in table {
+        let name = format!("{}{}", prefix, key.to_uppercase());
+
+        let setting = match value {
+            toml::Value::Table(inner) => {
+                flatten(&format!("{}_", name), inner, settings, problems);
+                continue;
+            }
+            toml::Value::String(text) => text.to_owned(),
+            // lists like HCC_JWT_VERIFY_KEYS are comma separated in the environment
+            toml::Value::Array(items) => {
+                let items: Option<Vec<&str>> = items.iter().map(toml::Value::as_str).collect();
+                match items {
+                    Some(items) => items.join(","),
+                    None => {
+                        problems.push(format!("{} in the config file must be a list of strings", name));
+                        continue;
+                    }
+                }
+            }
+            other => other.to_string(),
+        };
+
+        settings.insert(name, setting);
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    // the environment is shared by every test running at once, each test gets its own names
+    fn source(file: &[(&str, &str)]) -> ConfigSource {
+        ConfigSource {
+            file: file
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            problems: ConfigProblems::default(),
+        }
+    }
+
+    fn secret_file(contents: &str) -> String {
+        let path = env::temp_dir().join(format!("hcc-secret-{}", uuid::Uuid::new_v4()));
+        fs::write(&path, contents).unwrap();
+        String::from(path.to_str().unwrap())
+    }
+
+    #[test]
+    fn test_environment_beats_secret_files_beats_toml() {
+        let toml_secret = secret_file("from the toml secret\n");
+        let env_secret = secret_file("from the env secret\n");
+
+        let mut config = source(&[
+            ("HCC_TEST_ORDER_A", "from toml"),
+            ("HCC_TEST_ORDER_B_FILE", toml_secret.as_str()),
+        ]);
+        assert_eq!(config.get("HCC_TEST_ORDER_A").as_deref(), Some("from toml"));
+        assert_eq!(
+            config.get("HCC_TEST_ORDER_B").as_deref(),
+            Some("from the toml secret")
+        );
+        assert_eq!(config.get("HCC_TEST_ORDER_C"), None);
+
+        env::set_var("HCC_TEST_ORDER_A_FILE", &env_secret);
+        env::set_var("HCC_TEST_ORDER_B", "from env");
+        assert_eq!(
+            config.get("HCC_TEST_ORDER_A").as_deref(),
+            Some("from the env secret")
+        );
+        assert_eq!(config.get("HCC_TEST_ORDER_B").as_deref(), Some("from env"));
+
+        env::set_var("HCC_TEST_ORDER_A", "from env");
+        assert_eq!(config.get("HCC_TEST_ORDER_A").as_deref(), Some("from env"));
+
+        // a layer with both is a problem, the layers below it are not
+        let problems = config.problems.into_result(()).unwrap_err().to_string();
+        assert!(problems.contains("HCC_TEST_ORDER_A and HCC_TEST_ORDER_A_FILE are both set"));
+        assert!(!problems.contains("HCC_TEST_ORDER_B"));
+
+        for name in [
+            "HCC_TEST_ORDER_A",
+            "HCC_TEST_ORDER_A_FILE",
+            "HCC_TEST_ORDER_B",
+        ] {
+            env::remove_var(name);
+        }
+        fs::remove_file(&toml_secret).unwrap();
+        fs::remove_file(&env_secret).unwrap();
+    }
+
+    #[test]
+    fn test_both_set_in_toml_is_a_problem() {
+        let secret = secret_file("from the secret");
+        let mut config = source(&[
+            ("HCC_TEST_BOTH", "inline"),
+            ("HCC_TEST_BOTH_FILE", secret.as_str()),
+        ]);
+
+        assert_eq!(config.get("HCC_TEST_BOTH").as_deref(), Some("inline"));
+        let problems = config.problems.into_result(()).unwrap_err().to_string();
+        assert!(problems.contains("HCC_TEST_BOTH and HCC_TEST_BOTH_FILE are both set, use one"));
+
+        fs::remove_file(&secret).unwrap();
+    }
+
+    #[test]
+    fn test_secret_files_lose_trailing_whitespace_only() {
+        let secret = secret_file("  🔑 key\n\n");
+        let mut config = source(&[
+            ("HCC_TEST_SECRET_FILE", secret.as_str()),
+            ("HCC_TEST_GONE_FILE", "/nowhere/secret"),
+        ]);
+
+        assert_eq!(config.get("HCC_TEST_SECRET").as_deref(), Some("  🔑 key"));
+        assert_eq!(config.get("HCC_TEST_GONE"), None);
+
+        let problems = config.problems.into_result(()).unwrap_err().to_string();
+        assert!(problems.contains("HCC_TEST_GONE_FILE can't be read from /nowhere/secret"));
+
+        fs::remove_file(&secret).unwrap();
+    }
+
+    #[test]
+    fn test_flags_are_true_or_false() {
+        let mut config = source(&[
+            ("HCC_TEST_ON", "true"),
+            ("HCC_TEST_OFF", "false"),
+            ("HCC_TEST_ODD", "yes"),
+        ]);
+
+        assert!(config.flag("HCC_TEST_ON", false));
+        assert!(!config.flag("HCC_TEST_OFF", true));
+        assert!(config.flag("HCC_TEST_UNSET", true));
+        assert!(!config.flag("HCC_TEST_ODD", false));
+
+        let problems = config.problems.into_result(()).unwrap_err().to_string();
+        assert!(problems.contains("HCC_TEST_ODD must be true or false"));
+        assert!(!problems.contains("HCC_TEST_ON"));
+    }
+
+    #[test]
+    fn test_toml_uses_the_environment_names() {
+        let table = r#"
+            origin_domain = "holycharisma.com"
+            session_ttl_hours = 24
+
+            [jwt]
+            signing_kid = "2024-06"
+            verify_keys = ["2024-01=RS256:/keys/2024-01.key.pub", "legacy=/keys/old.key.pub"]
+
+            [encryption]
+            key_emoji_file = "/run/secrets/encryption_key"
+        "#
+        .parse::<toml::Value>()
+        .unwrap();
+
+        let mut settings = HashMap::new();
+        let mut problems = ConfigProblems::default();
+        flatten(VAR_PREFIX, table.as_table().unwrap(), &mut settings, &mut problems);
+
+        assert!(problems.into_result(()).is_ok());
+        assert_eq!(settings["HCC_ORIGIN_DOMAIN"], "holycharisma.com");
+        assert_eq!(settings["HCC_SESSION_TTL_HOURS"], "24");
+        assert_eq!(settings["HCC_JWT_SIGNING_KID"], "2024-06");
+        assert_eq!(
+            settings["HCC_JWT_VERIFY_KEYS"],
+            "2024-01=RS256:/keys/2024-01.key.pub,legacy=/keys/old.key.pub"
+        );
+        assert_eq!(settings["HCC_ENCRYPTION_KEY_EMOJI_FILE"], "/run/secrets/encryption_key");
+    }
+}
@@ -1,80 +1,51 @@
 use domain::server_config::ServerConfig;
 use orion::aead;
-use orion::aead::streaming::Nonce;
 use orion::errors::UnknownCryptoError;
-use orion::hazardous::aead::xchacha20poly1305;
-use orion::hazardous::hash::blake2::blake2b;
-use orion::hazardous::mac::poly1305::POLY1305_OUTSIZE;
-use orion::hazardous::stream::xchacha20::XCHACHA_NONCESIZE;
-use orion::kex::{EphemeralClientSession, EphemeralServerSession, SecretKey};
+use orion::hazardous::mac::blake2b as blake2b_mac;
+use orion::kex::{EphemeralServerSession, PublicKey, SecretKey};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Arc, Mutex};
 
-pub enum SmallBlakeHasher {
-    /// Blake2b with `24` as `size`.
-    Blake2b24,
-    /// Blake2b with `32` as `size`.
-    Blake2b32,
-}
-
-impl SmallBlakeHasher {
-    #[must_use = "SECURITY WARNING: Ignoring a Result can have real security implications."]
-    /// Return a digest selected by the given Blake2b variant.
-    pub fn digest(&self, data: &[u8]) -> Result<blake2b::Digest, UnknownCryptoError> {
-        let size: usize = match *self {
-            SmallBlakeHasher::Blake2b24 => 24,
-            SmallBlakeHasher::Blake2b32 => 32,
-        };
-
-        let mut state = blake2b::Blake2b::new(size)?;
-        state.update(data)?;
-        state.finalize()
-    }
-
-    #[must_use = "SECURITY WARNING: Ignoring a Result can have real security implications."]
-    /// Return a `Blake2b` state selected by the given Blake2b variant.
-    pub fn init(&self) -> Result<blake2b::Blake2b, UnknownCryptoError> {
-        match *self {
-            SmallBlakeHasher::Blake2b24 => blake2b::Blake2b::new(24),
-            SmallBlakeHasher::Blake2b32 => blake2b::Blake2b::new(32),
-        }
-    }
-}
+use hcc_common::wire::WireEncoding;
+use hcc_common::keyring::{envelope, EpochKeys, TopSecretSharedKeyring, DEFAULT_GRACE_EPOCHS};
 
 use super::emoji;
+use super::replay::{Envelope, ReplayError, ReplayWindow, ENVELOPE_NONCE_SIZE};
+
+pub use hcc_common::keyring::EncryptedKeyring;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct ServerEncryptedEmojiMessage {
+pub struct UserEncryptedEmojiMessage {
     pub sender: String,
     pub message: String,
 }
 
-impl ServerEncryptedEmojiMessage {
-    pub fn decrypt(&self, secrets: &SharedKeyring) -> Result<String, UnknownCryptoError> {
-        let secret_bytes = emoji::decode(&secrets.broadcast_secret);
-        let secret = SecretKey::from_slice(&secret_bytes)?;
-
-        let message_bytes = emoji::decode(&self.message);
-
-        let bytes = aead::open(&secret, &message_bytes)?;
-        let s = String::from_utf8(bytes).expect("invalid utf8");
-        Ok(s)
+impl UserEncryptedEmojiMessage {
+    pub fn decrypt(&self, secrets: &SharedKeyring) -> Result<String, MessageError> {
+        let bytes = emoji::try_decode(&self.message).ok_or(UnknownCryptoError)?;
+        let bytes = secrets.keys.open_user(&bytes)?;
+        let payload = secrets.accept_envelope(&bytes)?;
+        Ok(utf8(payload.to_vec())?)
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct UserEncryptedEmojiMessage {
-    pub sender: String,
-    pub message: String,
+// whatever the client sent decides these bytes, text that isn't utf8 is refused like any bad ciphertext
+fn utf8(bytes: Vec<u8>) -> Result<String, UnknownCryptoError> {
+    String::from_utf8(bytes).map_err(|_| UnknownCryptoError)
 }
 
-impl UserEncryptedEmojiMessage {
-    pub fn decrypt(&self, secrets: &SharedKeyring) -> Result<String, UnknownCryptoError> {
-        let secret_bytes = emoji::decode(&secrets.user_secret);
-        let secret = SecretKey::from_slice(&secret_bytes)?;
-        let message_bytes = emoji::decode(&self.message);
-        let bytes = aead::open(&secret, &message_bytes)?;
-        let s = String::from_utf8(bytes).expect("invalid utf8");
-        Ok(s)
+/// A broadcast fragment in the encoding the request negotiated, ready to be a response body.
+pub struct ServerEncryptedMessage {
+    pub encoding: WireEncoding,
+    pub message: Vec<u8>,
+}
+
+impl ServerEncryptedMessage {
+    pub fn into_body(self) -> tide::Body {
+        let mut body = tide::Body::from_bytes(self.message);
+        body.set_mime(self.encoding.content_type());
+        body
     }
 }
 
@@ -84,13 +55,10 @@ pub struct ServerEncryptedBase64Message {
 
 impl ServerEncryptedBase64Message {
     pub fn decrypt(&self, secrets: &SharedKeyring) -> Result<String, UnknownCryptoError> {
-        let secret_bytes = emoji::decode(&secrets.broadcast_secret);
-        let secret = SecretKey::from_slice(&secret_bytes)?;
-
-        let message_bytes = base64::decode_config(&self.message, base64::URL_SAFE_NO_PAD).unwrap();
-        let bytes = aead::open(&secret, &message_bytes)?;
-        let s = String::from_utf8(bytes).expect("invalid utf8");
-        Ok(s)
+        let bytes = base64::decode_config(&self.message, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| UnknownCryptoError)?;
+        let bytes = secrets.keys.open_broadcast(&bytes)?;
+        utf8(bytes)
     }
 }
 
@@ -99,86 +67,101 @@ pub struct UserEncryptedBase64Message {
 }
 
 impl UserEncryptedBase64Message {
-    pub fn decrypt(&self, secrets: &SharedKeyring) -> Result<String, UnknownCryptoError> {
-        let secret_bytes = emoji::decode(&secrets.user_secret);
-        let secret = SecretKey::from_slice(&secret_bytes)?;
-
-        let message_bytes = base64::decode_config(&self.message, base64::URL_SAFE_NO_PAD).unwrap();
-        let bytes = aead::open(&secret, &message_bytes)?;
-        let s = String::from_utf8(bytes).expect("invalid utf8");
-        Ok(s)
+    pub fn decrypt(&self, secrets: &SharedKeyring) -> Result<String, MessageError> {
+        let bytes = base64::decode_config(&self.message, base64::URL_SAFE_NO_PAD)
+            .map_err(|_| UnknownCryptoError)?;
+        let bytes = secrets.keys.open_user(&bytes)?;
+        let payload = secrets.accept_envelope(&bytes)?;
+        Ok(utf8(payload.to_vec())?)
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct EncryptedKeyring {
-    b: String,
+/// Why a client message was refused: it didn't open, or it opened but was seen before or is too old.
+#[derive(Debug)]
+pub enum MessageError {
+    Crypto(UnknownCryptoError),
+    Replay(ReplayError),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct TopSecretSharedKeyring {
-    a: String,
-    b: String,
-    x: String,
-    y: String,
+impl MessageError {
+    pub fn is_replay(&self) -> bool {
+        matches!(self, MessageError::Replay(_))
+    }
 }
 
-impl EncryptedKeyring {
-    pub fn open_with_emoji(&self, emoji_key: &str) -> Result<SharedKeyring, UnknownCryptoError> {
-        let bytes = open_with_key(emoji_key, &self.b).unwrap();
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::Crypto(_) => write!(f, "message could not be decrypted"),
+            MessageError::Replay(e) => write!(f, "{}", e),
+        }
+    }
+}
 
-        let json = String::from_utf8(bytes).unwrap();
-        let shared_keyring: TopSecretSharedKeyring =
-            serde_json::from_str(&json).expect("valid json");
-        Ok(SharedKeyring {
-            broadcast: shared_keyring.a,
-            user: shared_keyring.b,
-            broadcast_secret: shared_keyring.x,
-            user_secret: shared_keyring.y,
-        })
+impl std::error::Error for MessageError {}
+
+impl From<UnknownCryptoError> for MessageError {
+    fn from(e: UnknownCryptoError) -> Self {
+        MessageError::Crypto(e)
     }
+}
 
-    pub fn open(&self, config: &ServerConfig) -> Result<SharedKeyring, UnknownCryptoError> {
-        self.open_with_emoji(&config.encryption_key_emoji)
+impl From<ReplayError> for MessageError {
+    fn from(e: ReplayError) -> Self {
+        MessageError::Replay(e)
     }
+}
 
-    pub fn seal_with_emoji(
-        keyring: &SharedKeyring,
-        emoji_key: &str,
-    ) -> Result<EncryptedKeyring, UnknownCryptoError> {
-        let shared_keyring = TopSecretSharedKeyring {
-            a: keyring.broadcast.to_owned(),
-            b: keyring.user.to_owned(),
-            x: keyring.broadcast_secret.to_owned(),
-            y: keyring.user_secret.to_owned(),
-        };
-        let message = serde_json::to_string(&shared_keyring).expect("serialize");
-        let bytes = seal_with_key(emoji_key, &message.as_bytes())?;
-        Ok(EncryptedKeyring {
-            b: emoji::encode(&bytes),
-        })
+/// When the middleware should move a session keyring on to its next epoch.
+#[derive(Clone, Copy, Debug)]
+pub struct KeyringRotation {
+    pub max_messages: u32,
+    pub max_age_seconds: i64,
+    /// retired epochs whose client messages are still accepted (in flight requests)
+    pub grace_epochs: usize,
+}
+
+impl Default for KeyringRotation {
+    fn default() -> Self {
+        KeyringRotation {
+            max_messages: 200,
+            max_age_seconds: 15 * 60,
+            grace_epochs: DEFAULT_GRACE_EPOCHS,
+        }
     }
+}
 
-    pub fn seal(
-        keyring: &SharedKeyring,
-        config: &ServerConfig,
-    ) -> Result<EncryptedKeyring, UnknownCryptoError> {
-        EncryptedKeyring::seal_with_emoji(keyring, &config.encryption_key_emoji)
+impl KeyringRotation {
+    pub fn from_config(config: &ServerConfig) -> Self {
+        KeyringRotation {
+            max_messages: config.keyring_rotate_messages,
+            max_age_seconds: config.keyring_rotate_seconds,
+            grace_epochs: config.keyring_grace_epochs,
+        }
     }
 }
 
 #[derive(Clone)]
 pub struct SharedKeyring {
-    // how do we get some sort of forward secrecy? or post-compromise security?
-    // rotate your keys, orion wants these to be single use keys...
+    // forward secrecy within a session comes from the ratchet: every epoch derives fresh keys
+    // from the last one and the old ones are forgotten once they leave the grace window
+    // the epoch set itself lives in hcc-common so the wasm client walks the exact same chain
 
-    // good security practice dictates you throw these away frequently
     // we store them on our session and rely on browser http only cookie security
+    // only the derived session keys live here, the client's ephemeral secret never leaves the browser
     pub broadcast: String,
     pub user: String,
 
-    pub broadcast_secret: String,
-    pub user_secret: String,
+    /// retired epochs are kept for at most `KeyringRotation::grace_epochs`
+    pub keys: EpochKeys,
+    pub epoch_started_at: i64,
+    pub epoch_messages: u32,
+
+    /// kept on the session next to the keyring, shared by every clone handed to a route
+    pub replay: Arc<Mutex<ReplayWindow>>,
+
+    /// what this request asked its fragments to come back as, never sealed
+    pub encoding: WireEncoding,
 }
 
 pub fn open_with_key(
@@ -213,76 +196,72 @@ pub fn seal_with_key_emoji(
     Ok(message)
 }
 
-pub fn seal_hazardous(
-    secret_bytes: &Vec<u8>,
-    nonce_bytes: &Vec<u8>,
-    plaintext_bytes: &[u8],
-) -> Result<Vec<u8>, UnknownCryptoError> {
-    /*
-
-        do not re-use the same nonce on different plaintext bytes
-
-        a very clever attacker can intercept these masked messages and reverse engineer their way to the plaintext
-        the basic security relies on the fact the nonce will a number which is only used once
-
-        our actual guarantee is that for each value, we have a unique nonce: the same nonce yield the same bytes encrypting the same bytes
-        - WARNING: if the same nonce is used to decrypt two values then the values can be used to decrypt one another!
-
-    */
-
-    let _key = SecretKey::from_slice(&secret_bytes)?;
-
-    // adapted from aead::seal()
-
-    let out_len = match plaintext_bytes
-        .len()
-        .checked_add(XCHACHA_NONCESIZE + POLY1305_OUTSIZE)
-    {
-        Some(min_out_len) => min_out_len,
-        None => return Err(UnknownCryptoError),
-    };
-
-    let nonce = Nonce::from_slice(&nonce_bytes).unwrap();
-
-    let mut dst_out = vec![0u8; out_len];
+impl SharedKeyring {
+    pub fn open_with_emoji(
+        sealed: &EncryptedKeyring,
+        emoji_key: &str,
+    ) -> Result<SharedKeyring, UnknownCryptoError> {
+        let shared_keyring = sealed.open_with_emoji(emoji_key)?;
+        Ok(SharedKeyring {
+            broadcast: shared_keyring.a,
+            user: shared_keyring.b,
+            keys: EpochKeys {
+                epoch: shared_keyring.e,
+                broadcast_secret: shared_keyring.x,
+                user_secret: shared_keyring.y,
+                retired: shared_keyring.r,
+            },
+            epoch_started_at: shared_keyring.t,
+            epoch_messages: shared_keyring.m,
+            replay: Arc::new(Mutex::new(ReplayWindow::default())),
+            encoding: WireEncoding::default(),
+        })
+    }
 
-    dst_out[..XCHACHA_NONCESIZE].copy_from_slice(nonce.as_ref());
+    pub fn open(
+        sealed: &EncryptedKeyring,
+        config: &ServerConfig,
+    ) -> Result<SharedKeyring, UnknownCryptoError> {
+        SharedKeyring::open_with_emoji(sealed, &config.encryption_key_emoji)
+    }
 
-    xchacha20poly1305::seal(
-        &orion::hazardous::aead::chacha20poly1305::SecretKey::from_slice(
-            _key.unprotected_as_bytes(),
-        )?,
-        &nonce,
-        plaintext_bytes,
-        None,
-        &mut dst_out[XCHACHA_NONCESIZE..],
-    )?;
+    pub fn seal_with_emoji(&self, emoji_key: &str) -> Result<EncryptedKeyring, UnknownCryptoError> {
+        let shared_keyring = TopSecretSharedKeyring {
+            a: self.broadcast.to_owned(),
+            b: self.user.to_owned(),
+            x: self.keys.broadcast_secret.to_owned(),
+            y: self.keys.user_secret.to_owned(),
+            e: self.keys.epoch,
+            t: self.epoch_started_at,
+            m: self.epoch_messages,
+            r: self.keys.retired.to_owned(),
+        };
+        EncryptedKeyring::seal_with_emoji(&shared_keyring, emoji_key)
+    }
 
-    Ok(dst_out)
-}
+    pub fn seal(&self, config: &ServerConfig) -> Result<EncryptedKeyring, UnknownCryptoError> {
+        self.seal_with_emoji(&config.encryption_key_emoji)
+    }
 
-impl SharedKeyring {
-    pub async fn encrypt_broadcast_emoji(
+    pub async fn encrypt_broadcast(
         &self,
         plaintext: &str,
-    ) -> Result<ServerEncryptedEmojiMessage, UnknownCryptoError> {
-        let message = seal_with_key_emoji(&self.broadcast_secret, plaintext.as_bytes())?;
-
-        Ok(ServerEncryptedEmojiMessage {
-            sender: self.broadcast.to_owned(),
-            message: message,
+    ) -> Result<ServerEncryptedMessage, UnknownCryptoError> {
+        let bytes = self.keys.seal_broadcast(plaintext.as_bytes())?;
+        Ok(ServerEncryptedMessage {
+            encoding: self.encoding,
+            message: self.encoding.encode(&bytes),
         })
     }
 
+    // header values are url safe base64 without padding, in both directions
     pub async fn encrypt_broadcast_base64(
         &self,
         plaintext: &str,
     ) -> Result<ServerEncryptedBase64Message, UnknownCryptoError> {
-        let secret_bytes = emoji::decode(&self.broadcast_secret);
-        let secret = SecretKey::from_slice(&secret_bytes)?;
-        let bytes = aead::seal(&secret, plaintext.as_bytes())?;
+        let bytes = self.keys.seal_broadcast(plaintext.as_bytes())?;
         Ok(ServerEncryptedBase64Message {
-            message: base64::encode(&bytes),
+            message: base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD),
         })
     }
 
@@ -290,11 +269,9 @@ impl SharedKeyring {
         &self,
         plaintext: &str,
     ) -> Result<UserEncryptedBase64Message, UnknownCryptoError> {
-        let secret_bytes = emoji::decode(&self.user_secret);
-        let secret = SecretKey::from_slice(&secret_bytes)?;
-        let bytes = aead::seal(&secret, plaintext.as_bytes())?;
+        let bytes = self.keys.seal_user(&Self::envelope(plaintext)?)?;
         Ok(UserEncryptedBase64Message {
-            message: base64::encode(&bytes),
+            message: base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD),
         })
     }
 
@@ -302,78 +279,229 @@ impl SharedKeyring {
         &self,
         plaintext: &str,
     ) -> Result<UserEncryptedEmojiMessage, UnknownCryptoError> {
-        let message = seal_with_key_emoji(&self.user_secret, plaintext.as_bytes())?;
+        let bytes = self.keys.seal_user(&Self::envelope(plaintext)?)?;
         Ok(UserEncryptedEmojiMessage {
             sender: self.user.to_owned(),
-            message: message,
+            message: emoji::encode(&bytes),
         })
     }
 
-    pub async fn from(
-        session_server: EphemeralServerSession,
-        session_client: EphemeralClientSession,
+    // the same envelope the wasm client puts in front of everything it seals
+    fn envelope(plaintext: &str) -> Result<Vec<u8>, UnknownCryptoError> {
+        let mut nonce = [0u8; ENVELOPE_NONCE_SIZE];
+        orion::util::secure_rand_bytes(&mut nonce)?;
+
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        Ok(envelope(timestamp, &nonce, plaintext.as_bytes()))
+    }
+
+    fn accept_envelope<'a>(&self, plaintext: &'a [u8]) -> Result<&'a [u8], ReplayError> {
+        let (envelope, payload) = Envelope::split(plaintext)?;
+        self.replay.lock().expect("replay window").accept(&envelope)?;
+        Ok(payload)
+    }
+
+    pub fn rotation_due(&self, rotation: &KeyringRotation, now: i64) -> bool {
+        self.epoch_messages >= rotation.max_messages
+            || now - self.epoch_started_at >= rotation.max_age_seconds
+    }
+
+    /// Move on to the next epoch, the client follows when it sees the new epoch id on a response.
+    /// Client messages may still be in flight from the epoch we retire, anything older than the
+    /// grace window (or from an epoch we never announced) is refused.
+    pub fn ratchet(&mut self, rotation: &KeyringRotation, now: i64) -> Result<(), UnknownCryptoError> {
+        self.keys.ratchet(rotation.grace_epochs)?;
+        self.epoch_started_at = now;
+        self.epoch_messages = 0;
+        Ok(())
+    }
+
+    /// Server half of the handshake: the client generated its own keypair and only sent us the public key.
+    pub async fn establish_with_client(
+        client_public_key_emoji: &str,
     ) -> Result<SharedKeyring, UnknownCryptoError> {
+        let session_server = EphemeralServerSession::new()?;
         let session_server_pub_key = session_server.public_key().clone();
 
-        let session_client_pub_key = session_client.public_key().clone();
+        let session_client_pub_key = PublicKey::from_slice(&emoji::decode(client_public_key_emoji))?;
 
-        let client_key_pair = session_client.establish_with_server(&session_server_pub_key)?;
+        // the server's receiving key is the client's transport key and vice versa
+        let server_key_pair = session_server.establish_with_client(&session_client_pub_key)?;
 
         let server_identity = emoji::encode(&session_server_pub_key.to_bytes());
         let client_identity = emoji::encode(&session_client_pub_key.to_bytes());
 
         let client_rx_and_server_tx =
-            emoji::encode(&client_key_pair.receiving().unprotected_as_bytes());
+            emoji::encode(&server_key_pair.transport().unprotected_as_bytes());
         let client_tx_and_server_rx =
-            emoji::encode(&client_key_pair.transport().unprotected_as_bytes());
+            emoji::encode(&server_key_pair.receiving().unprotected_as_bytes());
 
         let bundle = SharedKeyring {
             broadcast: server_identity,
             user: client_identity,
-            broadcast_secret: client_rx_and_server_tx,
-            user_secret: client_tx_and_server_rx,
+            keys: EpochKeys::new(client_rx_and_server_tx, client_tx_and_server_rx),
+            epoch_started_at: chrono::Utc::now().timestamp(),
+            epoch_messages: 0,
+            replay: Arc::new(Mutex::new(ReplayWindow::default())),
+            encoding: WireEncoding::default(),
         };
 
         Ok(bundle)
     }
-
-    pub async fn new() -> Result<SharedKeyring, UnknownCryptoError> {
-        let server_session = EphemeralServerSession::new()?;
-        let client_session = EphemeralClientSession::new()?;
-        SharedKeyring::from(server_session, client_session).await
-    }
 }
 
-pub struct DeterministicEmojiEncrypt {
-    pub encrypted: String,
+/// Keyed BLAKE2b over a value we only store encrypted, so it can still be looked up by equality.
+/// Without the index key a guessed value can't be confirmed against the table, and the key is
+/// separate from the encryption key so one leaking doesn't give away the other.
+pub fn blind_index(
+    emoji_encoded_index_key: &str,
+    plaintext_bytes: &[u8],
+) -> Result<String, UnknownCryptoError> {
+    let key_bytes = emoji::decode(emoji_encoded_index_key);
+    let key = blake2b_mac::SecretKey::from_slice(&key_bytes)?;
+
+    let mut state = blake2b_mac::Blake2b::new(&key, BLIND_INDEX_SIZE)?;
+    state.update(plaintext_bytes)?;
+    let tag = state.finalize()?;
+
+    Ok(emoji::encode(tag.unprotected_as_bytes()))
 }
 
-impl DeterministicEmojiEncrypt {
-    fn get_hash_nonce(
-        salt_bytes: &Vec<u8>,
-        plaintext_bytes: &[u8],
-    ) -> Result<Vec<u8>, UnknownCryptoError> {
-        // todo: we should salt the plaintext bytes so this hash is a little harder to get to
-        let hashcode = SmallBlakeHasher::Blake2b24.digest(plaintext_bytes)?;
-        let nonce_bytes = &hashcode.as_ref();
-        Ok(nonce_bytes.to_vec())
+const BLIND_INDEX_SIZE: usize = 32;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotation() -> KeyringRotation {
+        KeyringRotation {
+            max_messages: 2,
+            max_age_seconds: 60,
+            grace_epochs: 1,
+        }
+    }
+
+    async fn keyring() -> SharedKeyring {
+        let client = orion::kex::EphemeralClientSession::new().unwrap();
+        let client_public_key = emoji::encode(&client.public_key().to_bytes());
+        SharedKeyring::establish_with_client(&client_public_key).await.unwrap()
+    }
+
+    #[async_std::test]
+    async fn rotation_is_due_by_messages_or_age() {
+        let mut keyring = keyring().await;
+        let now = keyring.epoch_started_at;
+        assert!(!keyring.rotation_due(&rotation(), now));
+        keyring.epoch_messages = 2;
+        assert!(keyring.rotation_due(&rotation(), now));
+        keyring.epoch_messages = 0;
+        assert!(keyring.rotation_due(&rotation(), now + 60));
     }
 
-    pub fn new(
-        emoji_encoded_secret: &str,
-        emoji_encoded_salt: &str,
-        plaintext_bytes: &[u8],
-    ) -> Result<DeterministicEmojiEncrypt, UnknownCryptoError> {
-        let secret_bytes = emoji::decode(emoji_encoded_secret);
-        let salt_bytes = emoji::decode(emoji_encoded_salt);
-        let nonce_bytes = Self::get_hash_nonce(&salt_bytes, plaintext_bytes)?;
+    #[async_std::test]
+    async fn opens_previous_epoch_within_grace_window() {
+        let mut keyring = keyring().await;
+        let now = keyring.epoch_started_at;
+        let early = keyring.encrypt_user_emoji("first epoch").await.unwrap();
 
-        let encrypted = seal_hazardous(&secret_bytes, &nonce_bytes, plaintext_bytes)?;
+        keyring.ratchet(&rotation(), now).unwrap();
+        assert_eq!(keyring.keys.epoch, 1);
+        assert_eq!(early.decrypt(&keyring).unwrap(), "first epoch");
+
+        let current = keyring.encrypt_user_base64("second epoch").await.unwrap();
+        assert_eq!(current.decrypt(&keyring).unwrap(), "second epoch");
+
+        keyring.ratchet(&rotation(), now).unwrap();
+        assert!(early.decrypt(&keyring).is_err());
+    }
+
+    #[async_std::test]
+    async fn broadcast_opens_the_way_the_client_does() {
+        // the wasm client only has hcc-common's framing and the raw secret bytes
+        let mut keyring = keyring().await;
+        keyring.ratchet(&rotation(), 0).unwrap();
+        let secret = SecretKey::from_slice(&emoji::decode(&keyring.keys.broadcast_secret)).unwrap();
+
+        for encoding in [WireEncoding::Emoji, WireEncoding::Base64Url, WireEncoding::Binary] {
+            keyring.encoding = encoding;
+            let message = keyring.encrypt_broadcast("hello client").await.unwrap();
+
+            let bytes = encoding.decode(&message.message).unwrap();
+            let (epoch, cipher_bytes) = hcc_common::keyring::split_epoch(&bytes).unwrap();
+            assert_eq!(epoch, 1);
+            assert_eq!(aead::open(&secret, cipher_bytes).unwrap(), b"hello client");
+        }
+    }
+
+    #[async_std::test]
+    async fn garbage_form_fields_are_refused() {
+        let keyring = keyring().await;
+        let not_utf8 = keyring
+            .keys
+            .seal_user(&envelope(1, &[1u8; ENVELOPE_NONCE_SIZE], &[0xff, 0xfe]))
+            .unwrap();
+
+        for message in [
+            String::new(),
+            String::from("hello"),
+            String::from("🔑🔑🔑"),
+            emoji::encode(b"short"),
+            emoji::encode(&not_utf8),
+        ] {
+            let field = UserEncryptedEmojiMessage {
+                sender: keyring.user.to_owned(),
+                message: message.to_owned(),
+            };
+            assert!(
+                field.decrypt(&keyring).is_err(),
+                "{:?} was accepted",
+                message
+            );
+
+            let header = UserEncryptedBase64Message {
+                message: message.to_owned(),
+            };
+            assert!(header.decrypt(&keyring).is_err());
+            let broadcast = ServerEncryptedBase64Message { message };
+            assert!(broadcast.decrypt(&keyring).is_err());
+        }
+    }
+
+    #[test]
+    fn blind_index_is_keyed_and_stable() {
+        let key = emoji::encode(&[7u8; 32]);
+        let other_key = emoji::encode(&[8u8; 32]);
+        let email = b"member@example.com";
+
+        let index = blind_index(&key, email).unwrap();
+        assert_eq!(index, blind_index(&key, email).unwrap());
+        assert_ne!(index, blind_index(&other_key, email).unwrap());
+        assert_ne!(index, blind_index(&key, b"other@example.com").unwrap());
+    }
+
+    #[async_std::test]
+    async fn ratchet_is_deterministic() {
+        let keyring = keyring().await;
+        let mut first = keyring.clone();
+        let mut second = keyring.clone();
+        first.ratchet(&rotation(), 0).unwrap();
+        second.ratchet(&rotation(), 0).unwrap();
+        assert_eq!(first.keys.broadcast_secret, second.keys.broadcast_secret);
+        assert_eq!(first.keys.user_secret, second.keys.user_secret);
+        assert_ne!(first.keys.user_secret, keyring.keys.user_secret);
+    }
 
-        let encoded = emoji::encode(&encrypted);
+    #[test]
+    fn test_blind_indexed_column_matches_member_emails() {
+        // a table searched by BlindIndexed<String> can be joined against user_email_password.email_hash
+        use domain::encrypted::{BlindIndexed, ColumnKeys};
 
-        let i = DeterministicEmojiEncrypt { encrypted: encoded };
+        let index_key = [4u8; 32];
+        let keys = ColumnKeys::new(1, vec![3u8; 32], vec![], index_key.to_vec()).unwrap();
+        let email = String::from("member@holycharisma.com");
 
-        Ok(i)
+        let column: sea_orm::Value = BlindIndexed::of(&keys, &email).unwrap().into();
+        let member_index = blind_index(&emoji::encode(&index_key), email.as_bytes()).unwrap();
+        assert_eq!(column, sea_orm::Value::String(Some(Box::new(member_index))));
     }
 }
@@ -1,12 +1,8 @@
-use super::{
-    emoji,
-    encryption::{
-        EncryptedKeyring, SharedKeyring, UserEncryptedBase64Message, UserEncryptedEmojiMessage,
-    },
-};
+use super::emoji;
 use tide::prelude::*;
 
-use jsonwebtokens::{encode, Algorithm, AlgorithmID, Verifier};
+use jsonwebtoken::errors::{Error, ErrorKind};
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 
 use orion::hazardous::hash::blake2::blake2b::Hasher;
 
@@ -16,17 +12,21 @@ use orion::hazardous::hash::blake2::blake2b::Hasher;
 
 // https://blog.logrocket.com/how-to-secure-a-rest-api-using-jwt-7efd83e71432/
 
+each key picks its own algorithm: EdDSA (ed25519), ES256 (p-256) or RS256
+a token is signed on every authenticated request, EdDSA keeps that cheap and the tokens short
 
-#from https://gist.github.com/ygotthilf/baa58da5c3dd1f69fae9
+cargo run -- generate-keys EdDSA 2024-06 ./keys
 
-ssh-keygen -t rsa -b 4096 -m PEM -f jwtRS256.key
-# Don't add passphrase
-openssl rsa -in jwtRS256.key -pubout -outform PEM -out jwtRS256.key.pub
-cat jwtRS256.key
-cat jwtRS256.key.pub
+writes ./keys/2024-06.key (pkcs8) and ./keys/2024-06.key.pub (spki), both PEM
+RS256 keys still work but come from openssl, see generate_keys
 
 */
 
+pub const INVITE_ACTION: &str = "invite";
+
+// the only algorithms a key may be configured with
+pub const SUPPORTED_ALGORITHMS: [&str; 3] = ["EdDSA", "ES256", "RS256"];
+
 #[derive(Clone)]
 pub struct JsonWebTokenUtil {
     pub secrets: JsonWebTokenSecrets,
@@ -35,125 +35,643 @@ pub struct JsonWebTokenUtil {
 }
 
 impl JsonWebTokenUtil {
-    pub fn verify_auth_token(
+    /// Checks signature, issuer and expiry, then that `claim` is exactly `expected`.
+    fn verify_with_claim(
         self: &JsonWebTokenUtil,
         token_str: &str,
-        email: &str,
-    ) -> Result<serde_json::value::Value, jsonwebtokens::error::Error> {
-        let pem_data = &self.secrets.pub_key_pem_data[..];
+        claim: &str,
+        expected: &str,
+    ) -> Result<serde_json::value::Value, Error> {
+        self.verify_with_claims(token_str, &["iss", "exp"], claim, expected)
+    }
 
-        let alg = Algorithm::new_rsa_pem_verifier(AlgorithmID::RS256, pem_data)?;
+    /// Like `verify_with_claim`, an exp is still checked when present but only `required` must be there.
+    fn verify_with_claims(
+        self: &JsonWebTokenUtil,
+        token_str: &str,
+        required: &[&str],
+        claim: &str,
+        expected: &str,
+    ) -> Result<serde_json::value::Value, Error> {
+        let key = self.secrets.verifying_key(token_str);
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_required_spec_claims(required);
+
+        let claims = decode::<serde_json::value::Value>(token_str, &key.decoding_key, &validation)?.claims;
+
+        if claims[claim].as_str() == Some(expected) {
+            Ok(claims)
+        } else {
+            Err(ErrorKind::InvalidToken.into())
+        }
+    }
 
-        let verifier = Verifier::create()
-            .issuer(&self.issuer)
-            .string_equals("email", email)
-            .build()?;
+    fn sign(self: &JsonWebTokenUtil, claims: &serde_json::value::Value) -> Result<String, Error> {
+        let mut header = Header::new(self.secrets.signing_algorithm);
+        header.kid = Some(self.secrets.signing_kid.to_owned());
 
-        verifier.verify(&token_str, &alg)
+        encode(&header, claims, &self.secrets.encoding_key)
     }
 
-    pub fn sign_auth_token(
+    pub fn verify_auth_token(
         self: &JsonWebTokenUtil,
+        token_str: &str,
         email: &str,
-    ) -> Result<String, jsonwebtokens::error::Error> {
-        let pem_data = &self.secrets.key_pem_data[..];
+    ) -> Result<serde_json::value::Value, Error> {
+        self.verify_with_claim(token_str, "email", email)
+    }
 
-        let alg = Algorithm::new_rsa_pem_signer(AlgorithmID::RS256, pem_data)?;
-        let header = json!({ "alg": alg.name() });
+    pub fn sign_auth_token(
+        self: &JsonWebTokenUtil,
+        email: &str,
+    ) -> Result<String, Error> {
         let now = chrono::Utc::now().timestamp();
         let twentyfour_hr_millis = self.expiry_duration_millis;
         let exp = now + twentyfour_hr_millis;
         let claims = json!({ "iss": &self.issuer, "exp": exp, "email": &email });
 
-        encode(&header, &claims, &alg)
+        self.sign(&claims)
     }
 
-    pub fn encode_pubkey(self: &JsonWebTokenUtil) -> String {
-        emoji::encode(&self.secrets.pub_key_pem_data)
+    pub fn sign_action_token(
+        self: &JsonWebTokenUtil,
+        action: &str,
+        uid: i32,
+        ttl_seconds: i64,
+        binding: Option<&str>,
+    ) -> Result<String, Error> {
+        // single purpose links we mail out (verify email, reset password, etc)
+        // the action claim keeps a token minted for one flow from being accepted by another
+        // the optional binding claim ties a token to some account state, once that state changes the token is spent
+
+        let exp = chrono::Utc::now().timestamp() + ttl_seconds;
+        let claims = match binding {
+            Some(bnd) => json!({ "iss": &self.issuer, "exp": exp, "act": action, "uid": uid, "bnd": bnd }),
+            None => json!({ "iss": &self.issuer, "exp": exp, "act": action, "uid": uid }),
+        };
+
+        self.sign(&claims)
     }
 
-    pub fn verify_csrf_token(
+    pub fn sign_invite_token(
         self: &JsonWebTokenUtil,
-        csrf_header_string: &str,
-        session_id: &str,
-        secrets: &SharedKeyring,
-    ) -> Result<serde_json::value::Value, jsonwebtokens::error::Error> {
-        let pem_data = &self.secrets.pub_key_pem_data[..];
-
-        let alg = Algorithm::new_rsa_pem_verifier(AlgorithmID::RS256, pem_data)?;
+        invite_id: i32,
+        inviter_uid: i32,
+        expires_at: Option<i64>,
+    ) -> Result<String, Error> {
+        // the code only names the invite row, uses and role are looked up when it is redeemed
+        // so an admin can always re-display a code without minting a new one
+
+        let claims = match expires_at {
+            Some(exp) => json!({ "iss": &self.issuer, "exp": exp, "act": INVITE_ACTION, "uid": inviter_uid, "inv": invite_id }),
+            None => json!({ "iss": &self.issuer, "act": INVITE_ACTION, "uid": inviter_uid, "inv": invite_id }),
+        };
 
-        let sid = Hasher::Blake2b512
-            .digest(session_id.as_bytes())
-            .expect("blake digest");
-        let sid_hex = emoji::encode(sid.as_ref());
+        self.sign(&claims)
+    }
 
-        let message = UserEncryptedBase64Message {
-            message: csrf_header_string.to_owned(),
-        };
+    pub fn verify_action_token(
+        self: &JsonWebTokenUtil,
+        token_str: &str,
+        action: &str,
+    ) -> Result<serde_json::value::Value, Error> {
+        self.verify_with_claim(token_str, "act", action)
+    }
 
-        let decrypted = message.decrypt(secrets).expect("can decrypt csrf");
+    /// Invite codes are the one token that may go without an exp, the invite row decides when they end.
+    pub fn verify_invite_token(
+        self: &JsonWebTokenUtil,
+        token_str: &str,
+    ) -> Result<serde_json::value::Value, Error> {
+        self.verify_with_claims(token_str, &["iss"], "act", INVITE_ACTION)
+    }
 
-        let verifier = Verifier::create()
-            .issuer(&self.issuer)
-            .string_equals("sid", sid_hex)
-            .build()?;
+    pub fn encode_pubkey(self: &JsonWebTokenUtil) -> String {
+        emoji::encode(&self.secrets.signing_key().pub_key_pem_data)
+    }
 
-        let res = verifier.verify(&decrypted, &alg)?;
+    fn hashed_session_id(session_id: &str) -> String {
+        let sid = Hasher::Blake2b512
+            .digest(session_id.as_bytes())
+            .expect("blake digest");
+        emoji::encode(sid.as_ref())
+    }
 
-        Ok(res)
+    /// Checks a plaintext csrf token. The handshake gets it as is, everything after it arrives
+    /// sealed with the keyring and is opened (and checked for replays) by the caller first.
+    pub fn verify_csrf_token(
+        self: &JsonWebTokenUtil,
+        token_str: &str,
+        session_id: &str,
+    ) -> Result<serde_json::value::Value, Error> {
+        self.verify_with_claim(token_str, "sid", &Self::hashed_session_id(session_id))
     }
 
     pub fn sign_csrf_token(
         self: &JsonWebTokenUtil,
         session_id: &str,
-        keyring: &SharedKeyring,
-    ) -> Result<String, jsonwebtokens::error::Error> {
-        // use our secret key to sign some data for the client:
+    ) -> Result<String, Error> {
+        // binds the page to this session, it carries no key material
+        // the keyring is agreed afterwards by posting a client generated public key to /handshake
 
-        // provide the bootstrap key-signing credentials in this handshake
-        // we will expect all subsequent comminications to be encrypted with the keyring
+        let now = chrono::Utc::now().timestamp();
+        let twentyfour_hr_millis = self.expiry_duration_millis;
+        let exp = now + twentyfour_hr_millis;
+        let claims = json!({ "iss": &self.issuer, "exp": exp, "sid": Self::hashed_session_id(session_id) });
+
+        self.sign(&claims)
+    }
+}
 
-        // right now the user needs to be able to decrypt the encrypted keyring...
-        // how will we determine our shared handshake key? for now we will just use the hashed SID as our slice
+pub fn parse_algorithm(name: &str) -> Option<Algorithm> {
+    match name {
+        "EdDSA" => Some(Algorithm::EdDSA),
+        "ES256" => Some(Algorithm::ES256),
+        "RS256" => Some(Algorithm::RS256),
+        _ => None,
+    }
+}
 
-        let pem_data = &self.secrets.key_pem_data[..];
+/// A public key we verify with, the signing key is one of these too.
+#[derive(Clone)]
+pub struct JsonWebTokenKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub_key_pem_data: Vec<u8>,
+    decoding_key: DecodingKey,
+    /// unix seconds, after this tokens naming the key are refused and it leaves the jwks
+    pub retires_at: Option<i64>,
+}
 
-        let sid = Hasher::Blake2b512
-            .digest(session_id.as_bytes())
-            .expect("blake digest");
-        let sid_hex = emoji::encode(sid.as_ref());
+impl JsonWebTokenKey {
+    pub fn from_pem(
+        kid: &str,
+        algorithm: Algorithm,
+        pub_key_pem_data: Vec<u8>,
+        retires_at: Option<i64>,
+    ) -> Result<JsonWebTokenKey, Error> {
+        let decoding_key = match algorithm {
+            Algorithm::EdDSA => DecodingKey::from_ed_pem(&pub_key_pem_data)?,
+            Algorithm::ES256 => DecodingKey::from_ec_pem(&pub_key_pem_data)?,
+            _ => DecodingKey::from_rsa_pem(&pub_key_pem_data)?,
+        };
 
-        let secret_slice =
-            &emoji::EmojiEncodedBytes::blake_hash_to_secret(sid_hex.as_bytes().to_owned());
-        let encoded_secret = emoji::encode(secret_slice);
-        let keyr: EncryptedKeyring = EncryptedKeyring::seal_with_emoji(keyring, &encoded_secret)
-            .expect("encrypted key ring");
+        Ok(JsonWebTokenKey {
+            kid: String::from(kid),
+            algorithm: algorithm,
+            pub_key_pem_data: pub_key_pem_data,
+            decoding_key: decoding_key,
+            retires_at: retires_at,
+        })
+    }
 
-        let alg = Algorithm::new_rsa_pem_signer(AlgorithmID::RS256, pem_data)?;
-        let header = json!({ "alg": alg.name() });
-        let now = chrono::Utc::now().timestamp();
-        let twentyfour_hr_millis = self.expiry_duration_millis;
-        let exp = now + twentyfour_hr_millis;
-        let claims = json!({ "iss": &self.issuer, "exp": exp, "sid": sid_hex, "keyring": keyr });
+    fn is_retired(&self, now: i64) -> bool {
+        self.retires_at.map(|at| at <= now).unwrap_or(false)
+    }
 
-        encode(&header, &claims, &alg)
+    /// The public half as a JWK (RFC 7517 / 8037), so other services can verify our tokens.
+    pub fn to_jwk(&self) -> Result<serde_json::value::Value, String> {
+        let public_key = spki_public_key(&self.pub_key_pem_data)?;
+        let b64 = |bytes: &[u8]| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+
+        match self.algorithm {
+            Algorithm::EdDSA => Ok(json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "use": "sig",
+                "alg": "EdDSA",
+                "kid": &self.kid,
+                "x": b64(&public_key),
+            })),
+            Algorithm::ES256 => {
+                // uncompressed point: 0x04 then x and y, 32 bytes each
+                if public_key.len() != 65 || public_key[0] != 4 {
+                    return Err(String::from("public key is not an uncompressed P-256 point"));
+                }
+                Ok(json!({
+                    "kty": "EC",
+                    "crv": "P-256",
+                    "use": "sig",
+                    "alg": "ES256",
+                    "kid": &self.kid,
+                    "x": b64(&public_key[1..33]),
+                    "y": b64(&public_key[33..]),
+                }))
+            }
+            _ => {
+                let (n, e) = rsa_public_components(&public_key)?;
+                Ok(json!({
+                    "kty": "RSA",
+                    "use": "sig",
+                    "alg": "RS256",
+                    "kid": &self.kid,
+                    "n": b64(&n),
+                    "e": b64(&e),
+                }))
+            }
+        }
     }
 }
 
 #[derive(Clone)]
 pub struct JsonWebTokenSecrets {
-    key_pem_data: Vec<u8>,
-    pub_key_pem_data: Vec<u8>,
+    signing_kid: String,
+    signing_algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    keys: Vec<JsonWebTokenKey>,
 }
 
 impl JsonWebTokenSecrets {
-    pub fn read_keys(key_path: &str, pubkey_path: &str) -> JsonWebTokenSecrets {
-        let key_bytes = std::fs::read(key_path).expect("Unable to load RSA key file.");
-        let pubkey_bytes = std::fs::read(pubkey_path).expect("Unable to load RSA public key file.");
+    /// Loads the active signing keypair plus any older public keys still accepted for verification.
+    /// `verify_keys` is a comma separated list of `kid=[ALG:]/path/to/key.pub`, optionally followed by
+    /// `@<rfc3339>` for when that key retires. Keys without an algorithm are RS256.
+    pub fn read_key_set(
+        signing_kid: &str,
+        signing_algorithm: &str,
+        key_path: &str,
+        pubkey_path: &str,
+        verify_keys: &str,
+    ) -> Result<JsonWebTokenSecrets, String> {
+        let algorithm = parse_algorithm(signing_algorithm)
+            .ok_or("HCC_JWT_SIGNING_ALG must be EdDSA, ES256 or RS256")?;
+
+        let key_bytes = std::fs::read(key_path)
+            .map_err(|e| format!("unable to load JWT private key file {}: {}", key_path, e))?;
+        let pubkey_bytes = std::fs::read(pubkey_path)
+            .map_err(|e| format!("unable to load JWT public key file {}: {}", pubkey_path, e))?;
+
+        let encoding_key = match algorithm {
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(&key_bytes),
+            Algorithm::ES256 => EncodingKey::from_ec_pem(&key_bytes),
+            _ => EncodingKey::from_rsa_pem(&key_bytes),
+        }
+        .map_err(|_| "JWT private key is not a PEM key for HCC_JWT_SIGNING_ALG")?;
+
+        let mut keys = vec![JsonWebTokenKey::from_pem(signing_kid, algorithm, pubkey_bytes, None)
+            .map_err(|_| "JWT public key is not a PEM key for HCC_JWT_SIGNING_ALG")?];
+
+        for entry in verify_keys.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (kid, rest) = entry
+                .split_once('=')
+                .ok_or("HCC_JWT_VERIFY_KEYS entries look like kid=[ALG:]path[@retire-time]")?;
+
+            let (rest, retires_at) = match rest.rsplit_once('@') {
+                Some((rest, at)) => {
+                    let at = chrono::DateTime::parse_from_rfc3339(at)
+                        .map_err(|_| "HCC_JWT_VERIFY_KEYS retire time must be rfc3339")?;
+                    (rest, Some(at.timestamp()))
+                }
+                None => (rest, None),
+            };
+
+            let (algorithm, path) = match rest.split_once(':') {
+                Some((name, path)) if parse_algorithm(name).is_some() => (parse_algorithm(name).unwrap(), path),
+                _ => (Algorithm::RS256, rest),
+            };
+
+            if keys.iter().any(|key| key.kid == kid) {
+                return Err(format!("jwt key id {} is used twice", kid));
+            }
+
+            let pem_data = std::fs::read(path)
+                .map_err(|e| format!("unable to load JWT public key file {} from HCC_JWT_VERIFY_KEYS: {}", path, e))?;
+            keys.push(
+                JsonWebTokenKey::from_pem(kid, algorithm, pem_data, retires_at).map_err(|_| {
+                    format!("HCC_JWT_VERIFY_KEYS key {} doesn't match its algorithm", kid)
+                })?,
+            );
+        }
+
+        Ok(JsonWebTokenSecrets {
+            signing_kid: String::from(signing_kid),
+            signing_algorithm: algorithm,
+            encoding_key: encoding_key,
+            keys: keys,
+        })
+    }
+
+    pub fn signing_key(&self) -> &JsonWebTokenKey {
+        self.keys
+            .iter()
+            .find(|key| key.kid == self.signing_kid)
+            .expect("signing key is in the key set")
+    }
+
+    /// Keys still accepted for verification, the ones we publish.
+    pub fn live_keys(&self) -> Vec<&JsonWebTokenKey> {
+        let now = chrono::Utc::now().timestamp();
+        self.keys.iter().filter(|key| !key.is_retired(now)).collect()
+    }
+
+    fn verifying_key(&self, token_str: &str) -> &JsonWebTokenKey {
+        // tokens from before the key set carry no kid, they were all signed by the signing key
+        // an unknown or retired kid also lands on the signing key and fails on the signature
+        let kid = decode_header(token_str).ok().and_then(|header| header.kid);
+
+        kid.and_then(|kid| self.live_keys().into_iter().find(|key| key.kid == kid))
+            .unwrap_or_else(|| self.signing_key())
+    }
+}
+
+// the subject public key bits out of a PEM SubjectPublicKeyInfo
+fn spki_public_key(pub_key_pem_data: &[u8]) -> Result<Vec<u8>, String> {
+    use simple_asn1::ASN1Block;
+
+    let parsed = pem::parse(pub_key_pem_data).map_err(|e| e.to_string())?;
+    let spki = simple_asn1::from_der(&parsed.contents).map_err(|e| e.to_string())?;
+
+    match spki.first() {
+        Some(ASN1Block::Sequence(_, fields)) => match fields.get(1) {
+            Some(ASN1Block::BitString(_, _, bytes)) => Ok(bytes.to_owned()),
+            _ => Err(String::from("public key is not a SubjectPublicKeyInfo")),
+        },
+        _ => Err(String::from("public key is not a SubjectPublicKeyInfo")),
+    }
+}
+
+// modulus and exponent out of a DER RSAPublicKey, big endian without a sign byte
+fn rsa_public_components(rsa_key_der: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+    use simple_asn1::ASN1Block;
+
+    match simple_asn1::from_der(rsa_key_der).map_err(|e| e.to_string())?.first() {
+        Some(ASN1Block::Sequence(_, fields)) => match (fields.get(0), fields.get(1)) {
+            (Some(ASN1Block::Integer(_, n)), Some(ASN1Block::Integer(_, e))) => {
+                Ok((n.to_bytes_be().1, e.to_bytes_be().1))
+            }
+            _ => Err(String::from("public key is not an RSA key")),
+        },
+        _ => Err(String::from("public key is not an RSA key")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    // public half only, ring can't generate rsa keys so the jwk shape is checked against a fixed one
+    const RSA_PUBLIC_PEM: &str = "-----BEGIN PUBLIC KEY-----
+MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAtp0vfUa85VEQN5BOGwb3
+/Vrso3rVhVVO1tjp9Tba0Mk0uywKJb9Q0xFlMK9vm4OUvb7VjTZOmuYuiM0nIfkx
+F+9GTxUpPO/3muXTnNHqZW/QYj5t3kEKTsEReAJjaF4/rYnMiQemOQ8rUnlL/6L/
+Ja42Ev9KCvtt5atU+Lu7UrsqfJrUslBHc+bJWM0Q3totUxRdF+WraGgM/E31BWbd
+IYga0zsmx79OgnLa3fS/QX91wGRw4RXgEb6wyNBioiRh4Dmrn+J7/8MVNsEQ5cao
+2edXT2o1OLiahBOA+88/taNtYoAVbetGF0RzBJPSlsHO/CdS/U/FDUSK7gh1+ZSL
+eQIDAQAB
+-----END PUBLIC KEY-----
+";
+
+    fn key_dir() -> String {
+        let dir = std::env::temp_dir().join(format!("hcc-jwt-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        String::from(dir.to_str().unwrap())
+    }
+
+    fn generate(dir: &str, algorithm: &str, kid: &str) -> String {
+        let args = [algorithm, kid, dir].map(String::from);
+        crate::commands::generate_keys::run(&args).unwrap();
+        format!("{}/{}.key", dir, kid)
+    }
 
-        JsonWebTokenSecrets {
-            key_pem_data: key_bytes,
-            pub_key_pem_data: pubkey_bytes,
+    fn util(dir: &str, algorithm: &str, kid: &str, verify_keys: &str) -> JsonWebTokenUtil {
+        let key_path = format!("{}/{}.key", dir, kid);
+        let pubkey_path = format!("{}.pub", key_path);
+
+        JsonWebTokenUtil {
+            secrets: JsonWebTokenSecrets::read_key_set(
+                kid,
+                algorithm,
+                &key_path,
+                &pubkey_path,
+                verify_keys,
+            )
+            .unwrap(),
+            issuer: String::from("holycharisma.com"),
+            expiry_duration_millis: 3600,
         }
     }
+
+    #[test]
+    fn test_tokens_verify_under_the_key_that_signed_them() {
+        let dir = key_dir();
+        let old_path = generate(&dir, "ES256", "old");
+        generate(&dir, "EdDSA", "new");
+
+        let old = util(&dir, "ES256", "old", "");
+        let new = util(&dir, "EdDSA", "new", &format!("old=ES256:{}.pub", old_path));
+
+        let old_token = old.sign_auth_token("human@holycharisma.com").unwrap();
+        let new_token = new.sign_auth_token("human@holycharisma.com").unwrap();
+
+        assert_eq!(
+            decode_header(&old_token).unwrap().kid.as_deref(),
+            Some("old")
+        );
+        assert_eq!(
+            decode_header(&new_token).unwrap().kid.as_deref(),
+            Some("new")
+        );
+        assert_eq!(decode_header(&new_token).unwrap().alg, Algorithm::EdDSA);
+
+        assert!(new
+            .verify_auth_token(&old_token, "human@holycharisma.com")
+            .is_ok());
+        assert!(new
+            .verify_auth_token(&new_token, "human@holycharisma.com")
+            .is_ok());
+        assert!(new
+            .verify_auth_token(&new_token, "robot@holycharisma.com")
+            .is_err());
+
+        // the old key never heard of the new one
+        assert!(old
+            .verify_auth_token(&new_token, "human@holycharisma.com")
+            .is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_verifying_key_selection() {
+        let dir = key_dir();
+        let old_path = generate(&dir, "EdDSA", "old");
+        generate(&dir, "EdDSA", "new");
+
+        let old = util(&dir, "EdDSA", "old", "");
+        let old_token = old.sign_auth_token("human@holycharisma.com").unwrap();
+
+        let live = util(&dir, "EdDSA", "new", &format!("old=EdDSA:{}.pub", old_path));
+        assert_eq!(live.secrets.verifying_key(&old_token).kid, "old");
+        assert!(live
+            .verify_auth_token(&old_token, "human@holycharisma.com")
+            .is_ok());
+
+        // past its retire time the kid lands on the signing key and fails on the signature
+        let retired = util(
+            &dir,
+            "EdDSA",
+            "new",
+            &format!("old=EdDSA:{}.pub@2000-01-01T00:00:00Z", old_path),
+        );
+        assert_eq!(retired.secrets.verifying_key(&old_token).kid, "new");
+        assert_eq!(retired.secrets.live_keys().len(), 1);
+        assert!(retired
+            .verify_auth_token(&old_token, "human@holycharisma.com")
+            .is_err());
+
+        // tokens from before the key set have no kid at all
+        let claims = json!({ "iss": &live.issuer, "exp": chrono::Utc::now().timestamp() + 60, "email": "human@holycharisma.com" });
+        let no_kid = encode(
+            &Header::new(Algorithm::EdDSA),
+            &claims,
+            &live.secrets.encoding_key,
+        )
+        .unwrap();
+        assert_eq!(live.secrets.verifying_key(&no_kid).kid, "new");
+        assert!(live
+            .verify_auth_token(&no_kid, "human@holycharisma.com")
+            .is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_verify_keys_parsing() {
+        let dir = key_dir();
+        let signing_path = generate(&dir, "EdDSA", "new");
+        let ec_path = generate(&dir, "ES256", "ec");
+        let read = |verify_keys: &str| {
+            JsonWebTokenSecrets::read_key_set(
+                "new",
+                "EdDSA",
+                &signing_path,
+                &format!("{}.pub", signing_path),
+                verify_keys,
+            )
+        };
+
+        let secrets = read(&format!(
+            " ec=ES256:{}.pub@2030-01-01T00:00:00Z , ,",
+            ec_path
+        ))
+        .unwrap();
+        let ec = secrets.keys.iter().find(|key| key.kid == "ec").unwrap();
+        assert_eq!(ec.algorithm, Algorithm::ES256);
+        assert_eq!(ec.retires_at, Some(1893456000));
+
+        // no algorithm means RS256, which an ec key isn't
+        assert!(read(&format!("ec={}.pub", ec_path))
+            .unwrap_err()
+            .contains("doesn't match its algorithm"));
+        assert!(read(&format!("{}.pub", ec_path))
+            .unwrap_err()
+            .contains("kid=[ALG:]path"));
+        assert!(read(&format!("ec=ES256:{}.pub@tomorrow", ec_path))
+            .unwrap_err()
+            .contains("rfc3339"));
+        assert!(read(&format!("new=EdDSA:{}.pub", signing_path))
+            .unwrap_err()
+            .contains("used twice"));
+        assert!(read("ec=ES256:/nowhere.pub").is_err());
+        assert!(JsonWebTokenSecrets::read_key_set(
+            "new",
+            "HS256",
+            &signing_path,
+            &signing_path,
+            ""
+        )
+        .is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_jwk_shape_per_algorithm() {
+        let dir = key_dir();
+        generate(&dir, "EdDSA", "ed");
+        generate(&dir, "ES256", "ec");
+
+        let ed = util(&dir, "EdDSA", "ed", "")
+            .secrets
+            .signing_key()
+            .to_jwk()
+            .unwrap();
+        assert_eq!(ed["kty"], "OKP");
+        assert_eq!(ed["crv"], "Ed25519");
+        assert_eq!(ed["kid"], "ed");
+        assert_eq!(ed["x"].as_str().unwrap().len(), 43);
+
+        let ec = util(&dir, "ES256", "ec", "")
+            .secrets
+            .signing_key()
+            .to_jwk()
+            .unwrap();
+        assert_eq!(ec["kty"], "EC");
+        assert_eq!(ec["crv"], "P-256");
+        assert_eq!(ec["x"].as_str().unwrap().len(), 43);
+        assert_eq!(ec["y"].as_str().unwrap().len(), 43);
+
+        let rsa = JsonWebTokenKey::from_pem(
+            "rsa",
+            Algorithm::RS256,
+            RSA_PUBLIC_PEM.as_bytes().to_vec(),
+            None,
+        )
+        .unwrap()
+        .to_jwk()
+        .unwrap();
+        assert_eq!(rsa["kty"], "RSA");
+        assert_eq!(rsa["alg"], "RS256");
+        assert_eq!(rsa["e"], "AQAB");
+        // 2048 bits, no sign byte
+        assert_eq!(rsa["n"].as_str().unwrap().len(), 342);
+
+        assert!(spki_public_key(b"not a pem").is_err());
+        assert!(rsa_public_components(&[0x05, 0x00]).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_action_and_invite_tokens() {
+        let dir = key_dir();
+        generate(&dir, "EdDSA", "k");
+        let jwt = util(&dir, "EdDSA", "k", "");
+
+        let verify = jwt.sign_action_token("verify", 7, 60, None).unwrap();
+        assert_eq!(
+            jwt.verify_action_token(&verify, "verify").unwrap()["uid"],
+            7
+        );
+        assert!(jwt.verify_action_token(&verify, "reset").is_err());
+
+        let reset = jwt
+            .sign_action_token("reset", 7, 60, Some("pwhash"))
+            .unwrap();
+        assert_eq!(
+            jwt.verify_action_token(&reset, "reset").unwrap()["bnd"],
+            "pwhash"
+        );
+
+        // well past the default leeway
+        let expired = jwt.sign_action_token("verify", 7, -600, None).unwrap();
+        assert!(jwt.verify_action_token(&expired, "verify").is_err());
+
+        let open_invite = jwt.sign_invite_token(3, 7, None).unwrap();
+        assert_eq!(jwt.verify_invite_token(&open_invite).unwrap()["inv"], 3);
+        // only invites may go without an exp
+        assert!(jwt
+            .verify_action_token(&open_invite, INVITE_ACTION)
+            .is_err());
+
+        let expired_invite = jwt
+            .sign_invite_token(3, 7, Some(chrono::Utc::now().timestamp() - 600))
+            .unwrap();
+        assert!(jwt.verify_invite_token(&expired_invite).is_err());
+        assert!(jwt.verify_invite_token(&verify).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
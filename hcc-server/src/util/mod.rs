@@ -1,4 +1,10 @@
 pub mod jwt;
 pub mod password;
 pub mod encryption;
-pub mod emoji;
\ No newline at end of file
+pub use hcc_common::emoji;
+pub mod totp;
+pub mod backoff;
+pub mod request;
+pub mod replay;
+pub mod config_check;
+pub mod config_source;
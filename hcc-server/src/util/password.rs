@@ -1,27 +1,174 @@
 extern crate bcrypt;
 
+use std::fmt;
 use std::str;
 
-use bcrypt::{hash, DEFAULT_COST};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
 
-pub struct PasswordUtil {}
+// hashes are stored as phc strings: $argon2id$v=19$m=...,t=...,p=...$salt$hash
+// older accounts still carry bcrypt hashes ($2b$...), which verify fine and get upgraded on login
+
+#[derive(Clone, Debug)]
+pub struct PasswordParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for PasswordParams {
+    // owasp's minimum recommendation for argon2id
+    fn default() -> Self {
+        PasswordParams {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PasswordError(pub String);
+
+impl fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "password hashing failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for PasswordError {}
+
+impl From<argon2::password_hash::Error> for PasswordError {
+    fn from(e: argon2::password_hash::Error) -> Self {
+        PasswordError(e.to_string())
+    }
+}
+
+impl From<argon2::Error> for PasswordError {
+    fn from(e: argon2::Error) -> Self {
+        PasswordError(e.to_string())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PasswordVerification {
+    Invalid,
+    Valid,
+    /// The password matched, but the stored hash should be replaced with a fresh one.
+    ValidNeedsRehash,
+}
+
+impl PasswordVerification {
+    pub fn is_valid(&self) -> bool {
+        *self != PasswordVerification::Invalid
+    }
+}
+
+#[derive(Clone)]
+pub struct PasswordUtil {
+    params: PasswordParams,
+}
 
 impl PasswordUtil {
-    pub fn into_password_hash(_plaintext: &str) -> Vec<u8> {
-        match hash(_plaintext, DEFAULT_COST) {
-            Ok(hash) => String::into_bytes(hash),
-            _ => vec![]
+    pub fn new(params: PasswordParams) -> Result<PasswordUtil, PasswordError> {
+        // fail at startup rather than on the first signup
+        Params::new(params.memory_kib, params.iterations, params.parallelism, None)?;
+        Ok(PasswordUtil { params: params })
+    }
+
+    fn argon2(&self) -> Result<Argon2<'static>, PasswordError> {
+        let params = Params::new(
+            self.params.memory_kib,
+            self.params.iterations,
+            self.params.parallelism,
+            None,
+        )?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+
+    // argon2 is built to be slow, it runs on the blocking pool instead of holding up an executor thread
+
+    pub async fn into_password_hash(&self, plaintext: &str) -> Result<Vec<u8>, PasswordError> {
+        let util = self.clone();
+        let plaintext = String::from(plaintext);
+        async_std::task::spawn_blocking(move || util.into_password_hash_blocking(&plaintext)).await
+    }
+
+    pub async fn verify_hashed_bytes(&self, attempt: &str, hash: &[u8]) -> PasswordVerification {
+        let util = self.clone();
+        let attempt = String::from(attempt);
+        let hash = hash.to_vec();
+        async_std::task::spawn_blocking(move || util.verify_hashed_bytes_blocking(&attempt, &hash))
+            .await
+    }
+
+    fn into_password_hash_blocking(&self, plaintext: &str) -> Result<Vec<u8>, PasswordError> {
+        let salt = SaltString::generate(&mut OsRng);
+        let phc = self.argon2()?.hash_password(plaintext.as_bytes(), &salt)?;
+        Ok(phc.to_string().into_bytes())
+    }
+
+    fn verify_hashed_bytes_blocking(&self, attempt: &str, hash: &[u8]) -> PasswordVerification {
+        let encoded = match str::from_utf8(hash) {
+            Ok(encoded) => encoded,
+            Err(_) => return PasswordVerification::Invalid,
+        };
+
+        if Self::is_bcrypt(encoded) {
+            return match bcrypt::verify(attempt, encoded) {
+                Ok(true) => PasswordVerification::ValidNeedsRehash,
+                _ => PasswordVerification::Invalid,
+            };
+        }
+
+        let parsed = match PasswordHash::new(encoded) {
+            Ok(parsed) => parsed,
+            Err(_) => return PasswordVerification::Invalid,
+        };
+
+        // verify with whatever parameters the hash was made with, then compare against ours
+        let matched = Argon2::default()
+            .verify_password(attempt.as_bytes(), &parsed)
+            .is_ok();
+
+        if !matched {
+            PasswordVerification::Invalid
+        } else if self.is_outdated(&parsed) {
+            PasswordVerification::ValidNeedsRehash
+        } else {
+            PasswordVerification::Valid
         }
     }
 
-    pub fn verify_hashed_bytes(attempt: &str, hash: &[u8]) -> bool {
-        match bcrypt::verify(attempt, str::from_utf8(hash).unwrap()) {
-            Ok(b) => b,
-            _ => false
+    /// Whether `hash` is something `verify_hashed_bytes` could ever accept a password for.
+    pub fn is_recognized_hash(hash: &[u8]) -> bool {
+        match str::from_utf8(hash) {
+            Ok(encoded) if Self::is_bcrypt(encoded) => encoded.len() == 60,
+            Ok(encoded) => PasswordHash::new(encoded).is_ok(),
+            Err(_) => false,
         }
     }
-}
 
+    fn is_bcrypt(encoded: &str) -> bool {
+        encoded.starts_with("$2a$") || encoded.starts_with("$2b$") || encoded.starts_with("$2y$")
+    }
+
+    fn is_outdated(&self, parsed: &PasswordHash) -> bool {
+        if parsed.algorithm != Algorithm::Argon2id.ident() {
+            return true;
+        }
+
+        match Params::try_from(parsed) {
+            Ok(params) => {
+                params.m_cost() != self.params.memory_kib
+                    || params.t_cost() != self.params.iterations
+                    || params.p_cost() != self.params.parallelism
+            }
+            Err(_) => true,
+        }
+    }
+}
 
 #[cfg(test)]
 mod test {
@@ -30,20 +177,80 @@ mod test {
 
     use super::*;
 
+    fn cheap_params() -> PasswordParams {
+        PasswordParams {
+            memory_kib: 1024,
+            iterations: 1,
+            parallelism: 1,
+        }
+    }
+
     #[test]
     fn test_some_password_stuff() {
+        let password_util = PasswordUtil::new(cheap_params()).unwrap();
 
         let password_plaintext = "hunter23";
-        let password_hash = PasswordUtil::into_password_hash(&password_plaintext);
+        let password_hash = password_util.into_password_hash_blocking(&password_plaintext).unwrap();
 
         let encoded_password = emoji::encode(&password_hash);
         println!("encoded password: {}", encoded_password);
 
         let decoded_hash = emoji::decode(&encoded_password);
 
-        assert!(PasswordUtil::verify_hashed_bytes(&password_plaintext, &decoded_hash));
-        assert!(! PasswordUtil::verify_hashed_bytes("hunter24", &decoded_hash))
+        assert_eq!(
+            password_util.verify_hashed_bytes_blocking(&password_plaintext, &decoded_hash),
+            PasswordVerification::Valid
+        );
+        assert_eq!(
+            password_util.verify_hashed_bytes_blocking("hunter24", &decoded_hash),
+            PasswordVerification::Invalid
+        );
+
+        assert!(PasswordUtil::is_recognized_hash(&decoded_hash));
+        assert!(!PasswordUtil::is_recognized_hash(password_plaintext.as_bytes()));
     }
-}
 
+    #[test]
+    fn test_legacy_bcrypt_needs_rehash() {
+        let password_util = PasswordUtil::new(cheap_params()).unwrap();
+
+        let legacy = bcrypt::hash("hunter23", 4).unwrap();
+
+        assert_eq!(
+            password_util.verify_hashed_bytes_blocking("hunter23", legacy.as_bytes()),
+            PasswordVerification::ValidNeedsRehash
+        );
+        assert_eq!(
+            password_util.verify_hashed_bytes_blocking("hunter24", legacy.as_bytes()),
+            PasswordVerification::Invalid
+        );
+        assert!(PasswordUtil::is_recognized_hash(legacy.as_bytes()));
+    }
+
+    #[test]
+    fn test_changed_params_need_rehash() {
+        let old = PasswordUtil::new(cheap_params()).unwrap();
+        let hash = old.into_password_hash_blocking("hunter23").unwrap();
+
+        let stronger = PasswordUtil::new(PasswordParams {
+            iterations: 2,
+            ..cheap_params()
+        })
+        .unwrap();
+
+        assert_eq!(
+            stronger.verify_hashed_bytes_blocking("hunter23", &hash),
+            PasswordVerification::ValidNeedsRehash
+        );
+    }
 
+    #[test]
+    fn test_bad_params_are_errors() {
+        assert!(PasswordUtil::new(PasswordParams {
+            memory_kib: 1,
+            iterations: 1,
+            parallelism: 1,
+        })
+        .is_err());
+    }
+}
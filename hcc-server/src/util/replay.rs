@@ -0,0 +1,217 @@
+// replay protection for messages the client seals with its keyring
+// every sealed plaintext starts with an envelope: the client's clock in millis and a random nonce
+// the session remembers which nonces it has seen recently, anything seen twice or older than
+// the window is refused
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+pub use hcc_common::keyring::ENVELOPE_NONCE_SIZE;
+
+// measured against the newest message from the same client, not our clock, so skew doesn't matter
+pub const WINDOW_MILLIS: i64 = 5 * 60 * 1000;
+
+// a client can't fill the session with nonces, past this the oldest fall out of the window early
+pub const MAX_SEEN: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayError {
+    Malformed,
+    Replayed,
+    Expired,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Malformed => write!(f, "message has no replay envelope"),
+            ReplayError::Replayed => write!(f, "message was already received"),
+            ReplayError::Expired => write!(f, "message is too old"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+pub struct Envelope {
+    pub timestamp: i64,
+    pub nonce: [u8; ENVELOPE_NONCE_SIZE],
+}
+
+impl Envelope {
+    /// Splits the envelope from the front of a decrypted message.
+    pub fn split(plaintext: &[u8]) -> Result<(Envelope, &[u8]), ReplayError> {
+        let (timestamp, nonce, payload) =
+            hcc_common::keyring::split_envelope(plaintext).ok_or(ReplayError::Malformed)?;
+        Ok((Envelope { timestamp, nonce }, payload))
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct ReplayWindow {
+    newest: i64,
+    // (timestamp, base64 nonce), oldest first
+    seen: Vec<(i64, String)>,
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl ReplayWindow {
+    pub fn accept(&mut self, envelope: &Envelope) -> Result<(), ReplayError> {
+        if envelope.timestamp <= self.floor() {
+            return Err(ReplayError::Expired);
+        }
+
+        let nonce = base64::encode(&envelope.nonce);
+        if self.seen.iter().any(|(_, seen)| seen == &nonce) {
+            return Err(ReplayError::Replayed);
+        }
+
+        self.newest = self.newest.max(envelope.timestamp);
+        let position = self
+            .seen
+            .iter()
+            .position(|(timestamp, _)| *timestamp > envelope.timestamp)
+            .unwrap_or(self.seen.len());
+        self.seen.insert(position, (envelope.timestamp, nonce));
+
+        let floor = self.floor();
+        self.seen.retain(|(timestamp, _)| *timestamp > floor);
+        if self.seen.len() > MAX_SEEN {
+            let overflow = self.seen.len() - MAX_SEEN;
+            self.seen.drain(..overflow);
+        }
+
+        self.changed = true;
+        Ok(())
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    fn floor(&self) -> i64 {
+        // a full window means everything at or before its oldest entry is no longer tracked
+        let window_floor = self.newest - WINDOW_MILLIS;
+        match self.seen.first() {
+            Some((oldest, _)) if self.seen.len() >= MAX_SEEN => window_floor.max(*oldest),
+            _ => window_floor,
+        }
+    }
+}
+
+// every request on one keyring checks its messages against the same window, so a nonce one of them
+// records is refused by all the others, however many run at once. the copy saved on the session
+// only seeds a keyring's window when this process hasn't seen it yet, after a restart
+pub struct ReplayWindows {
+    idle_millis: i64,
+    // keyed by the keyring's server public key, a new handshake starts a new window
+    windows: Mutex<HashMap<String, (i64, Arc<Mutex<ReplayWindow>>)>>,
+}
+
+impl ReplayWindows {
+    /// Windows nobody asked for in `idle_millis` are dropped, the session TTL is a safe choice.
+    pub fn new(idle_millis: i64) -> Self {
+        ReplayWindows {
+            idle_millis,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The window every request on `keyring` shares, `saved` seeds it the first time it's asked for.
+    pub fn shared(
+        &self,
+        keyring: &str,
+        now_millis: i64,
+        saved: impl FnOnce() -> ReplayWindow,
+    ) -> Arc<Mutex<ReplayWindow>> {
+        let mut windows = self.windows.lock().expect("replay windows");
+        let idle_millis = self.idle_millis;
+        windows.retain(|_, (used, _)| now_millis - *used <= idle_millis);
+
+        let (used, window) = windows
+            .entry(keyring.to_owned())
+            .or_insert_with(|| (now_millis, Arc::new(Mutex::new(saved()))));
+        *used = now_millis;
+        window.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn envelope(timestamp: i64, nonce: u8) -> Envelope {
+        Envelope {
+            timestamp,
+            nonce: [nonce; ENVELOPE_NONCE_SIZE],
+        }
+    }
+
+    #[test]
+    fn test_rejects_repeated_nonce() {
+        let mut window = ReplayWindow::default();
+        assert_eq!(window.accept(&envelope(1_000, 1)), Ok(()));
+        assert_eq!(window.accept(&envelope(1_000, 2)), Ok(()));
+        assert_eq!(window.accept(&envelope(1_000, 1)), Err(ReplayError::Replayed));
+    }
+
+    #[test]
+    fn test_accepts_out_of_order_within_window() {
+        let mut window = ReplayWindow::default();
+        assert_eq!(window.accept(&envelope(WINDOW_MILLIS, 1)), Ok(()));
+        assert_eq!(window.accept(&envelope(WINDOW_MILLIS - 10, 2)), Ok(()));
+        assert_eq!(window.accept(&envelope(0, 3)), Err(ReplayError::Expired));
+    }
+
+    #[test]
+    fn test_splits_envelope() {
+        let mut message = 42i64.to_be_bytes().to_vec();
+        message.extend([7u8; ENVELOPE_NONCE_SIZE]);
+        message.extend(b"hello");
+
+        let (envelope, payload) = Envelope::split(&message).unwrap();
+        assert_eq!(envelope.timestamp, 42);
+        assert_eq!(envelope.nonce, [7u8; ENVELOPE_NONCE_SIZE]);
+        assert_eq!(payload, b"hello");
+        assert!(Envelope::split(b"short").is_err());
+    }
+
+    #[test]
+    fn test_requests_on_one_keyring_share_a_window() {
+        let windows = ReplayWindows::new(1_000);
+        let first = windows.shared("keyring", 0, ReplayWindow::default);
+        let second = windows.shared("keyring", 10, || panic!("already seeded"));
+
+        assert_eq!(first.lock().unwrap().accept(&envelope(1_000, 1)), Ok(()));
+        assert_eq!(
+            second.lock().unwrap().accept(&envelope(1_000, 1)),
+            Err(ReplayError::Replayed)
+        );
+
+        let other = windows.shared("other", 20, ReplayWindow::default);
+        assert_eq!(other.lock().unwrap().accept(&envelope(1_000, 1)), Ok(()));
+    }
+
+    #[test]
+    fn test_idle_windows_are_seeded_again() {
+        let windows = ReplayWindows::new(1_000);
+        let first = windows.shared("keyring", 0, ReplayWindow::default);
+        first.lock().unwrap().accept(&envelope(1_000, 1)).unwrap();
+        let saved = first.lock().unwrap().clone();
+
+        let mut seeded = false;
+        let later = windows.shared("keyring", 5_000, || {
+            seeded = true;
+            saved
+        });
+        assert!(seeded);
+        assert_eq!(
+            later.lock().unwrap().accept(&envelope(1_000, 1)),
+            Err(ReplayError::Replayed)
+        );
+    }
+}
@@ -0,0 +1,24 @@
+use std::net::{IpAddr, SocketAddr};
+
+use tide::Request;
+
+use crate::wiring::ServerWiring;
+
+/// The client ip lockouts and session lists go by. Forwarded headers are anyone's to write,
+/// they are only honored (the same way tide does) behind a proxy that sets them itself.
+pub fn client_address(req: &Request<ServerWiring>) -> String {
+    let remote = if req.state().config.trust_proxy_headers {
+        req.remote()
+    } else {
+        req.peer_addr()
+    }
+    .unwrap_or("unknown");
+
+    if let Ok(socket) = remote.parse::<SocketAddr>() {
+        socket.ip().to_string()
+    } else if let Ok(ip) = remote.parse::<IpAddr>() {
+        ip.to_string()
+    } else {
+        String::from(remote)
+    }
+}
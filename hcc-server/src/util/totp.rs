@@ -0,0 +1,196 @@
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+use orion::hazardous::hash::blake2::blake2b::Hasher;
+
+// rfc 6238 time based one time passwords
+// sha1 + 6 digits + 30 second steps is what every authenticator app agrees on
+
+const STEP_SECONDS: u64 = 30;
+const SECRET_BYTES: usize = 20;
+const RECOVERY_CODE_COUNT: usize = 8;
+
+// accept codes from one step either side of now to allow for clock drift
+const DRIFT_STEPS: i64 = 1;
+
+pub struct Totp {
+    secret: Vec<u8>,
+    digits: u32,
+}
+
+impl Totp {
+    pub fn new(secret: &[u8]) -> Totp {
+        Totp {
+            secret: secret.to_vec(),
+            digits: 6,
+        }
+    }
+
+    pub fn generate_secret() -> Vec<u8> {
+        let mut secret = vec![0u8; SECRET_BYTES];
+        orion::util::secure_rand_bytes(&mut secret).expect("random bytes");
+        secret
+    }
+
+    pub fn step_at(unix_seconds: u64) -> i64 {
+        (unix_seconds / STEP_SECONDS) as i64
+    }
+
+    fn hotp(&self, counter: u64) -> String {
+        let mut mac = Hmac::<Sha1>::new_from_slice(&self.secret).expect("hmac accepts any key size");
+        mac.update(&counter.to_be_bytes());
+        let digest = mac.finalize().into_bytes();
+
+        // dynamic truncation from rfc 4226
+        let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+        let binary = ((digest[offset] as u32 & 0x7f) << 24)
+            | ((digest[offset + 1] as u32) << 16)
+            | ((digest[offset + 2] as u32) << 8)
+            | (digest[offset + 3] as u32);
+
+        let code = binary % 10u32.pow(self.digits);
+        format!("{:0width$}", code, width = self.digits as usize)
+    }
+
+    pub fn code_at(&self, unix_seconds: u64) -> String {
+        self.hotp(Self::step_at(unix_seconds) as u64)
+    }
+
+    /// Returns the matched time step so callers can refuse to accept the same step twice.
+    pub fn verify(&self, code: &str, unix_seconds: u64, last_used_step: i64) -> Option<i64> {
+        let code = code.trim();
+        let now_step = Self::step_at(unix_seconds);
+
+        (-DRIFT_STEPS..=DRIFT_STEPS)
+            .map(|drift| now_step + drift)
+            .filter(|step| *step > last_used_step && *step >= 0)
+            .find(|step| constant_time_eq(&self.hotp(*step as u64), code))
+    }
+
+    pub fn encoded_secret(&self) -> String {
+        base32::encode(base32::Alphabet::RFC4648 { padding: false }, &self.secret)
+    }
+
+    pub fn otpauth_uri(&self, issuer: &str, account: &str) -> String {
+        format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            percent_encode(issuer),
+            percent_encode(account),
+            self.encoded_secret(),
+            percent_encode(issuer),
+            self.digits,
+            STEP_SECONDS
+        )
+    }
+
+    pub fn qr_svg(&self, issuer: &str, account: &str) -> String {
+        let code = qrcode::QrCode::new(self.otpauth_uri(issuer, account).as_bytes())
+            .expect("otpauth uri fits in a qr code");
+        code.render::<qrcode::render::svg::Color>()
+            .min_dimensions(200, 200)
+            .build()
+    }
+}
+
+// codes are compared without returning early on the first differing byte
+fn constant_time_eq(expected: &str, attempt: &str) -> bool {
+    orion::util::secure_cmp(expected.as_bytes(), attempt.as_bytes()).is_ok()
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+pub struct RecoveryCodes {}
+
+impl RecoveryCodes {
+    /// Plaintext codes to show the member exactly once.
+    pub fn generate() -> Vec<String> {
+        (0..RECOVERY_CODE_COUNT)
+            .map(|_| {
+                let mut bytes = [0u8; 7];
+                orion::util::secure_rand_bytes(&mut bytes).expect("random bytes");
+                let encoded =
+                    base32::encode(base32::Alphabet::RFC4648 { padding: false }, &bytes);
+                format!("{}-{}", &encoded[0..5], &encoded[5..10]).to_ascii_lowercase()
+            })
+            .collect()
+    }
+
+    fn normalize(code: &str) -> String {
+        code.trim().replace('-', "").to_ascii_lowercase()
+    }
+
+    pub fn hash(code: &str) -> String {
+        // codes are long random strings, a plain digest is enough to keep them out of the db
+        let digest = Hasher::Blake2b256
+            .digest(Self::normalize(code).as_bytes())
+            .expect("blake digest");
+        base64::encode_config(digest.as_ref(), base64::URL_SAFE_NO_PAD)
+    }
+
+    /// Returns the remaining hashes when the attempt matches one of the stored codes.
+    pub fn redeem(attempt: &str, hashes: &[String]) -> Option<Vec<String>> {
+        let attempt_hash = Self::hash(attempt);
+        if hashes.iter().any(|h| constant_time_eq(h, &attempt_hash)) {
+            Some(
+                hashes
+                    .iter()
+                    .filter(|h| **h != attempt_hash)
+                    .cloned()
+                    .collect(),
+            )
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_rfc6238_sha1_vectors() {
+        let totp = Totp {
+            secret: b"12345678901234567890".to_vec(),
+            digits: 8,
+        };
+
+        assert_eq!(totp.code_at(59), "94287082");
+        assert_eq!(totp.code_at(1111111109), "07081804");
+        assert_eq!(totp.code_at(1234567890), "89005924");
+        assert_eq!(totp.code_at(2000000000), "69279037");
+    }
+
+    #[test]
+    fn test_verify_allows_drift_but_not_reuse() {
+        let totp = Totp::new(&Totp::generate_secret());
+        let now = 1_650_000_000;
+
+        let previous = totp.code_at(now - 30);
+        let matched = totp.verify(&previous, now, 0).unwrap();
+        assert_eq!(matched, Totp::step_at(now) - 1);
+
+        assert!(totp.verify(&previous, now, matched).is_none());
+        assert!(totp.verify(&totp.code_at(now - 90), now, 0).is_none());
+    }
+
+    #[test]
+    fn test_recovery_codes_are_single_use() {
+        let codes = RecoveryCodes::generate();
+        let hashes: Vec<String> = codes.iter().map(|c| RecoveryCodes::hash(c)).collect();
+
+        let remaining = RecoveryCodes::redeem(&codes[0].to_ascii_uppercase(), &hashes).unwrap();
+        assert_eq!(remaining.len(), hashes.len() - 1);
+        assert!(RecoveryCodes::redeem(&codes[0], &remaining).is_none());
+    }
+}
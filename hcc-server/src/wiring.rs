@@ -4,9 +4,17 @@ use std::sync::Arc;
 extern crate dotenv;
 
 use dotenv::dotenv;
-use std::env;
 
+use crate::mailer::{file::FileMailer, smtp::SmtpMailer, Mailer};
+use crate::middleware::headers::SecurityHeaders;
+use crate::util::config_check::ConfigProblems;
+use crate::util::config_source::ConfigSource;
+use crate::util::emoji;
+use crate::util::encryption::KeyringRotation;
 use crate::util::jwt::{JsonWebTokenSecrets, JsonWebTokenUtil};
+use crate::util::password::{PasswordParams, PasswordUtil};
+use crate::util::replay::ReplayWindows;
+use domain::encrypted::{ColumnKeyProvider, ColumnKeys};
 use domain::server_config::ServerConfig;
 
 #[derive(Clone)]
@@ -22,41 +30,89 @@ impl ServerWiring {
         db
     }
 
-    pub fn init_server_config() -> ServerConfig {
+    /// Everything the config file and environment could give, alongside what they were missing.
+    /// `config_check::checked_config` is the way in, it looks the values over too.
+    pub fn init_server_config() -> (ServerConfig, ConfigProblems) {
         dotenv().ok();
-        ServerConfig {
-            domain: env::var("HCC_ORIGIN_DOMAIN")
-                .expect("Invalid configuration: HCC_ORIGIN_DOMAIN required"),
-            session_cookie_name: env::var("HCC_SESSION_COOKIE_NAME")
-                .expect("Invalid configuration: HCC_SESSION_COOKIE_NAME required"),
-            session_ttl_hours: env::var("HCC_SESSION_TTL_HOURS")
-                .expect("Invalid configuration: HCC_SESSION_TTL_HOURS required")
-                .parse()
-                .expect("Invalid configuration: HCC_SESSION_TTL_HOURS must be a number"),
-            encryption_key_emoji: env::var("HCC_ENCRYPTION_KEY_EMOJI")
-                .expect("Invalid configuration: HCC_ENCRYPTION_KEY_EMOJI required"),
-            encryption_salt_emoji: env::var("HCC_ENCRYPTION_SALT_EMOJI")
-                .expect("Invalid configuration: HCC_ENCRYPTION_SALT_EMOJI required"),
-            rsa_private_key_path: env::var("HCC_RSA_PRIVATE_KEY_PATH")
-                .expect("Invalid configuration: HCC_RSA_PRIVATE_KEY_PATH required"),
-            rsa_public_key_path: env::var("HCC_RSA_PUBLIC_KEY_PATH")
-                .expect("Invalid configuration: HCC_RSA_PUBLIC_KEY_PATH required"),
-            postgres_sql_connection_url: env::var("HCC_POSTGRES_SQL_CONNECTION_URL")
-                .expect("Invalid configuration: HCC_POSTGRES_SQL_CONNECTION_URL required"),
-            bind_url: env::var("HCC_BIND_URL")
-                .expect("Invalid configuration: HCC_BIND_URL required"),
-            super_user_email: env::var("HCC_SUPER_USER_EMAIL")
-                .expect("Invalid configuration: HCC_SUPER_USER_EMAIL required"),
-            super_user_pwhash_emoji: env::var("HCC_SUPER_USER_PWHASH_EMOJI")
-                .expect("Invalid configuration: HCC_SUPER_USER_PWHASH_EMOJI required"),
-        }
+        let mut source = ConfigSource::load();
+        let config = ServerConfig {
+            domain: source.required("HCC_ORIGIN_DOMAIN"),
+            session_cookie_name: source.required("HCC_SESSION_COOKIE_NAME"),
+            session_ttl_hours: source.required_number("HCC_SESSION_TTL_HOURS"),
+            encryption_key_emoji: source.required("HCC_ENCRYPTION_KEY_EMOJI"),
+            column_key_emoji: source.required("HCC_COLUMN_KEY_EMOJI"),
+            column_key_version: source.number("HCC_COLUMN_KEY_VERSION", 1),
+            column_retired_keys_emoji: source.string("HCC_COLUMN_RETIRED_KEYS_EMOJI", ""),
+            blind_index_key_emoji: source.required("HCC_BLIND_INDEX_KEY_EMOJI"),
+            // the HCC_RSA_* names predate EdDSA and ES256 keys, they still work
+            jwt_private_key_path: source
+                .required_or_legacy("HCC_JWT_PRIVATE_KEY_PATH", "HCC_RSA_PRIVATE_KEY_PATH"),
+            jwt_public_key_path: source
+                .required_or_legacy("HCC_JWT_PUBLIC_KEY_PATH", "HCC_RSA_PUBLIC_KEY_PATH"),
+            jwt_signing_kid: source.string("HCC_JWT_SIGNING_KID", "primary"),
+            jwt_signing_algorithm: source.string("HCC_JWT_SIGNING_ALG", "RS256"),
+            jwt_verify_keys: source.string("HCC_JWT_VERIFY_KEYS", ""),
+            postgres_sql_connection_url: source.required("HCC_POSTGRES_SQL_CONNECTION_URL"),
+            bind_url: source.required("HCC_BIND_URL"),
+            trust_proxy_headers: source.flag("HCC_TRUST_PROXY_HEADERS", false),
+            super_user_email: source.required("HCC_SUPER_USER_EMAIL"),
+            super_user_pwhash_emoji: source.required("HCC_SUPER_USER_PWHASH_EMOJI"),
+            mailer_transport: source.string("HCC_MAILER_TRANSPORT", "file"),
+            mailer_from: source.required("HCC_MAILER_FROM"),
+            mailer_smtp_url: source.string("HCC_MAILER_SMTP_URL", ""),
+            mailer_file_path: source.string("HCC_MAILER_FILE_PATH", "-"),
+            password_argon2_memory_kib: source
+                .number("HCC_PASSWORD_ARGON2_MEMORY_KIB", PasswordParams::default().memory_kib),
+            password_argon2_iterations: source
+                .number("HCC_PASSWORD_ARGON2_ITERATIONS", PasswordParams::default().iterations),
+            password_argon2_parallelism: source
+                .number("HCC_PASSWORD_ARGON2_PARALLELISM", PasswordParams::default().parallelism),
+            keyring_rotate_messages: source
+                .number("HCC_KEYRING_ROTATE_MESSAGES", KeyringRotation::default().max_messages),
+            keyring_rotate_seconds: source
+                .number("HCC_KEYRING_ROTATE_SECONDS", KeyringRotation::default().max_age_seconds),
+            keyring_grace_epochs: source
+                .number("HCC_KEYRING_GRACE_EPOCHS", KeyringRotation::default().grace_epochs),
+            content_security_policy: source.string(
+                "HCC_CONTENT_SECURITY_POLICY",
+                &SecurityHeaders::default().content_security_policy,
+            ),
+            frame_ancestors: source.string(
+                "HCC_FRAME_ANCESTORS",
+                &SecurityHeaders::default().frame_ancestors,
+            ),
+            hsts_max_age_seconds: source
+                .number("HCC_HSTS_MAX_AGE_SECONDS", SecurityHeaders::default().hsts_max_age_seconds),
+            referrer_policy: source.string(
+                "HCC_REFERRER_POLICY",
+                &SecurityHeaders::default().referrer_policy,
+            ),
+            permissions_policy: source.string(
+                "HCC_PERMISSIONS_POLICY",
+                &SecurityHeaders::default().permissions_policy,
+            ),
+        };
+
+        (config, source.problems)
     }
 
     pub async fn new(server_config: &ServerConfig) -> Result<ServerWiring, tide::Error> {
         let config = server_config.to_owned();
         let server_state = ServerWiring {
             services: ServiceWiring {
-                jwt_util: Arc::new(ServiceWiring::jwt_util(&config)),
+                jwt_util: Arc::new(
+                    ServiceWiring::jwt_util(&config)
+                        .unwrap_or_else(|e| panic!("Invalid configuration: {}", e)),
+                ),
+                mailer: ServiceWiring::mailer(&config),
+                password_util: Arc::new(ServiceWiring::password_util(&config)),
+                column_keys: Arc::new(
+                    ServiceWiring::column_keys(&config)
+                        .unwrap_or_else(|e| panic!("Invalid configuration: {}", e)),
+                ),
+                replay_windows: Arc::new(ReplayWindows::new(
+                    (config.session_ttl_hours * 1000 * 60 * 60) as i64,
+                )),
             },
             db: {
                 tide::log::info!("Trying to connect to sea-orm db...");
@@ -73,19 +129,88 @@ impl ServerWiring {
 #[derive(Clone)]
 pub struct ServiceWiring {
     pub jwt_util: Arc<JsonWebTokenUtil>,
+    pub mailer: Arc<dyn Mailer>,
+    pub password_util: Arc<PasswordUtil>,
+    /// seals and opens the `Encrypted` and `BlindIndexed` columns
+    pub column_keys: Arc<dyn ColumnKeyProvider>,
+    /// one replay window per session keyring, shared by its parallel requests
+    pub replay_windows: Arc<ReplayWindows>,
 }
 
 impl ServiceWiring {
-    pub fn jwt_util(config: &ServerConfig) -> JsonWebTokenUtil {
-        let rsa_secrets = JsonWebTokenSecrets::read_keys(
-            &config.rsa_private_key_path,
-            &config.rsa_public_key_path,
-        );
-
-        JsonWebTokenUtil {
-            secrets: rsa_secrets,
+    pub fn jwt_util(config: &ServerConfig) -> Result<JsonWebTokenUtil, String> {
+        let secrets = JsonWebTokenSecrets::read_key_set(
+            &config.jwt_signing_kid,
+            &config.jwt_signing_algorithm,
+            &config.jwt_private_key_path,
+            &config.jwt_public_key_path,
+            &config.jwt_verify_keys,
+        )?;
+
+        Ok(JsonWebTokenUtil {
+            secrets: secrets,
             issuer: String::from(&config.domain),
             expiry_duration_millis: (config.session_ttl_hours * 1000 * 60 * 60) as i64,
+        })
+    }
+
+    /// HCC_COLUMN_KEY_EMOJI seals new values, HCC_COLUMN_RETIRED_KEYS_EMOJI
+    /// (`version=key,...`) keeps values sealed under older versions readable.
+    /// HCC_ENCRYPTION_KEY_EMOJI has no versions, so it is never one of these.
+    pub fn column_keys(config: &ServerConfig) -> Result<ColumnKeys, String> {
+        let decode = |name: &str, emoji_key: &str| {
+            emoji::try_decode(emoji_key)
+                .ok_or_else(|| format!("{} is not emoji encoded bytes", name))
+        };
+
+        let mut retired_keys = vec![];
+        for entry in config
+            .column_retired_keys_emoji
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (version, key) = entry
+                .split_once('=')
+                .ok_or("HCC_COLUMN_RETIRED_KEYS_EMOJI entries look like version=key")?;
+            let version = version
+                .parse()
+                .map_err(|_| "HCC_COLUMN_RETIRED_KEYS_EMOJI versions must be numbers")?;
+            retired_keys.push((version, decode("HCC_COLUMN_RETIRED_KEYS_EMOJI", key)?));
+        }
+
+        ColumnKeys::new(
+            config.column_key_version,
+            decode("HCC_COLUMN_KEY_EMOJI", &config.column_key_emoji)?,
+            retired_keys,
+            decode("HCC_BLIND_INDEX_KEY_EMOJI", &config.blind_index_key_emoji)?,
+        )
+        .map_err(|e| format!("HCC_COLUMN_* keys: {}", e.0))
+    }
+
+    pub fn password_util(config: &ServerConfig) -> PasswordUtil {
+        PasswordUtil::new(PasswordParams {
+            memory_kib: config.password_argon2_memory_kib,
+            iterations: config.password_argon2_iterations,
+            parallelism: config.password_argon2_parallelism,
+        })
+        .expect("Invalid configuration: HCC_PASSWORD_ARGON2_* parameters rejected by argon2")
+    }
+
+    pub fn mailer(config: &ServerConfig) -> Arc<dyn Mailer> {
+        match config.mailer_transport.as_str() {
+            "smtp" => Arc::new(
+                SmtpMailer::new(&config.mailer_smtp_url, &config.mailer_from)
+                    .expect("Invalid configuration: HCC_MAILER_SMTP_URL must be a valid smtp url"),
+            ),
+            "file" => Arc::new(FileMailer::new(
+                &config.mailer_file_path,
+                &config.mailer_from,
+            )),
+            other => panic!(
+                "Invalid configuration: HCC_MAILER_TRANSPORT must be smtp or file, got {}",
+                other
+            ),
         }
     }
 }